@@ -0,0 +1,305 @@
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Command line tool for key ceremonies and debugging.
+//! keygen and sign run one party against a peer, over TCP or over frame files exchanged by the
+//! operator (see FileTransport). Shares are kept in passphrase encrypted share files, the
+//! passphrase is read from the PARADISE_CITY_PASSPHRASE environment variable.
+
+extern crate paradise_city;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use paradise_city::protocols::two_party::driver;
+use paradise_city::protocols::two_party::nonce_store::FileNonceStore;
+use paradise_city::protocols::two_party::policy::Policies;
+use paradise_city::protocols::two_party::session::{Party1SigningSession, Party2SigningSession};
+use paradise_city::protocols::two_party::share_file::ShareFile;
+use paradise_city::protocols::two_party::transport::{FileTransport, TcpTransport, Transport};
+use paradise_city::protocols::two_party::{
+    verify, vk_from_bytes, vk_to_bytes, KeyGenRole, SessionId, Signature, SpendAuthMessage,
+};
+use paradise_city::Error;
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "usage:
+  paradise-city keygen --party <one|two> --session <hex> --out <share file> <peer>
+  paradise-city sign --share <share file> --session <hex> --sighash <hex>
+                     --out <signature file> <peer>
+  paradise-city verify --in <signature file>
+  paradise-city verify --vk <hex> --sighash <hex> --signature <hex>
+  paradise-city inspect --share <share file>
+  paradise-city inspect --signature <signature file>
+
+peer:
+  --listen <address>                 wait for the peer to connect
+  --connect <address>                connect to the peer
+  --outbox <dir> --inbox <dir>       exchange frame files with the peer
+
+Both parties pass the same --session (and --sighash when signing). sign takes no vk: the vk a
+signature verifies under comes from the coin flip of the signing session, it is an output of
+sign, printed and written to the signature file next to the signature.
+sign records its ephemeral keys in <share file>.nonces and refuses to reuse one, keep that file
+with the share file. The share file passphrase is read from PARADISE_CITY_PASSPHRASE.";
+
+const PASSPHRASE_VAR: &str = "PARADISE_CITY_PASSPHRASE";
+
+/// output of sign, input of verify
+#[derive(Serialize, Deserialize)]
+struct SignatureFile {
+    vk: String,
+    sighash: String,
+    signature: String,
+}
+
+enum Peer {
+    Tcp(TcpTransport),
+    File(FileTransport),
+}
+
+impl Transport for Peer {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), Error> {
+        match *self {
+            Peer::Tcp(ref mut transport) => transport.send_frame(frame),
+            Peer::File(ref mut transport) => transport.send_frame(frame),
+        }
+    }
+
+    fn receive_frame(&mut self) -> Result<Vec<u8>, Error> {
+        match *self {
+            Peer::Tcp(ref mut transport) => transport.receive_frame(),
+            Peer::File(ref mut transport) => transport.receive_frame(),
+        }
+    }
+}
+
+type Flags = HashMap<String, String>;
+
+fn parse_flags(args: &[String]) -> Result<Flags, String> {
+    let mut flags = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            return Err(format!("unexpected argument {}", arg));
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", arg))?;
+        flags.insert(arg[2..].to_string(), value.clone());
+    }
+    Ok(flags)
+}
+
+fn flag<'a>(flags: &'a Flags, name: &str) -> Result<&'a str, String> {
+    flags
+        .get(name)
+        .map(|value| value.as_str())
+        .ok_or_else(|| format!("missing --{}", name))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(format!("{} is not hex", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("{} is not hex", hex)))
+        .collect()
+}
+
+fn session_id(flags: &Flags) -> Result<SessionId, String> {
+    let id = from_hex(flag(flags, "session")?)?;
+    if id.is_empty() {
+        return Err("empty session id".to_string());
+    }
+    Ok(SessionId(id))
+}
+
+fn passphrase() -> Result<Vec<u8>, String> {
+    match env::var(PASSPHRASE_VAR) {
+        Ok(ref passphrase) if !passphrase.is_empty() => Ok(passphrase.as_bytes().to_vec()),
+        _ => Err(format!("{} is not set", PASSPHRASE_VAR)),
+    }
+}
+
+fn peer(flags: &Flags) -> Result<Peer, String> {
+    let transport = if let Some(addr) = flags.get("listen") {
+        let listener = TcpListener::bind(addr.as_str()).map_err(|e| e.to_string())?;
+        TcpTransport::accept(&listener).map(Peer::Tcp)
+    } else if let Some(addr) = flags.get("connect") {
+        TcpTransport::connect(addr.as_str()).map(Peer::Tcp)
+    } else {
+        FileTransport::new(flag(flags, "outbox")?, flag(flags, "inbox")?).map(Peer::File)
+    };
+    transport.map_err(|e| format!("cannot reach the peer: {}", e))
+}
+
+// never overwrite a share or a signature by accident
+fn check_new_file(path: &str) -> Result<(), String> {
+    match Path::new(path).exists() {
+        true => Err(format!("{} already exists", path)),
+        false => Ok(()),
+    }
+}
+
+// fails on an existing file as well, check_new_file only saves running a protocol for nothing
+fn write_new_file(path: &str, contents: &str) -> Result<(), String> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| format!("cannot write {}: {}", path, e))
+}
+
+fn keygen(flags: &Flags) -> Result<(), String> {
+    let role = match flag(flags, "party")? {
+        "one" => KeyGenRole::PartyOne,
+        "two" => KeyGenRole::PartyTwo,
+        other => return Err(format!("unknown party {}", other)),
+    };
+    let out = flag(flags, "out")?;
+    check_new_file(out)?;
+    let passphrase = passphrase()?;
+    let session_id = session_id(flags)?;
+    let mut transport = peer(flags)?;
+
+    let (keys, ak) = match role {
+        KeyGenRole::PartyOne => driver::party_one_keygen(&mut transport, &session_id),
+        KeyGenRole::PartyTwo => driver::party_two_keygen(&mut transport, &session_id),
+    }
+    .map_err(|e| format!("keygen failed: {}", e))?;
+    let share_file = ShareFile::seal(&keys, &ak, role, &passphrase).map_err(|e| e.to_string())?;
+    share_file.save(out).map_err(|e| e.to_string())?;
+    println!("key id: {}", share_file.key_id);
+    println!("ak: {}", share_file.ak);
+    Ok(())
+}
+
+fn sign(flags: &Flags) -> Result<(), String> {
+    let share_path = flag(flags, "share")?;
+    let share_file = ShareFile::load(share_path).map_err(|e| e.to_string())?;
+    let sighash = from_hex(flag(flags, "sighash")?)?;
+    let out = flag(flags, "out")?;
+    check_new_file(out)?;
+    let (keys, ak) = share_file
+        .open(&passphrase()?)
+        .map_err(|e| format!("cannot open share file: {}", e))?;
+    let mut nonce_store = FileNonceStore::open(format!("{}.nonces", share_path))
+        .map_err(|e| format!("cannot open nonce file: {}", e))?;
+    let session_id = session_id(flags)?;
+    let mut transport = peer(flags)?;
+
+    // keygen party one initiates
+    let (message, sig) = match share_file.role {
+        KeyGenRole::PartyOne => driver::party_one_sign(
+            &mut transport,
+            Party1SigningSession::new(keys, ak, session_id),
+            &sighash,
+            &mut nonce_store,
+        ),
+        // whoever runs sign for party two approves the sighash, there is no policy to add
+        KeyGenRole::PartyTwo => driver::party_two_sign(
+            &mut transport,
+            Party2SigningSession::new(keys, ak, session_id),
+            &sighash,
+            &Policies::new(),
+            &mut nonce_store,
+            "",
+            None,
+        ),
+    }
+    .map_err(|e| format!("signing failed: {}", e))?;
+    let signature_file = SignatureFile {
+        vk: to_hex(&vk_to_bytes(&message.vk())),
+        sighash: to_hex(message.sighash()),
+        signature: to_hex(&sig.to_bytes()),
+    };
+    // printed first, so a signature file that cannot be written does not lose the signature
+    println!("vk: {}", signature_file.vk);
+    println!("signature: {}", signature_file.signature);
+    let json = serde_json::to_string_pretty(&signature_file).map_err(|e| e.to_string())?;
+    write_new_file(out, &json)
+}
+
+fn load_signature_file(path: &str) -> Result<SignatureFile, String> {
+    let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&json).map_err(|e| format!("{} is not a signature file: {}", path, e))
+}
+
+fn verify_signature(flags: &Flags) -> Result<(), String> {
+    let signature_file = match flags.get("in") {
+        Some(path) => load_signature_file(path)?,
+        None => SignatureFile {
+            vk: flag(flags, "vk")?.to_string(),
+            sighash: flag(flags, "sighash")?.to_string(),
+            signature: flag(flags, "signature")?.to_string(),
+        },
+    };
+    let vk = vk_from_bytes(&from_hex(&signature_file.vk)?).map_err(|e| e.to_string())?;
+    let message = SpendAuthMessage::new(&vk, &from_hex(&signature_file.sighash)?)
+        .map_err(|e| e.to_string())?;
+    let sig =
+        Signature::from_bytes(&from_hex(&signature_file.signature)?).map_err(|e| e.to_string())?;
+    verify(vk, &message, &sig).map_err(|e| e.to_string())?;
+    println!("signature is valid");
+    Ok(())
+}
+
+// public data only, inspect never asks for the passphrase
+fn inspect(flags: &Flags) -> Result<(), String> {
+    if let Some(path) = flags.get("share") {
+        let share_file = ShareFile::load(path).map_err(|e| e.to_string())?;
+        share_file.ak().map_err(|e| format!("bad ak: {}", e))?;
+        println!("share file version: {}", share_file.version);
+        println!("key id: {}", share_file.key_id);
+        println!("keygen role: {:?}", share_file.role);
+        println!("ak: {}", share_file.ak);
+        return Ok(());
+    }
+    let signature_file = load_signature_file(flag(flags, "signature")?)?;
+    println!("vk: {}", signature_file.vk);
+    println!("sighash: {}", signature_file.sighash);
+    println!("signature: {}", signature_file.signature);
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = args.first().map(|command| command.as_str()).unwrap_or("");
+    let result = parse_flags(&args[args.len().min(1)..]).and_then(|flags| match command {
+        "keygen" => keygen(&flags),
+        "sign" => sign(&flags),
+        "verify" => verify_signature(&flags),
+        "inspect" => inspect(&flags),
+        _ => Err(USAGE.to_string()),
+    });
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
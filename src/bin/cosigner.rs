@@ -0,0 +1,76 @@
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Runs party two as a service, see the cosigner module.
+//!
+//! usage: paradise-city-cosigner <listen address> <key directory> [audit log]
+//!
+//! The ephemeral keys the cosigner committed to are kept in nonces.jsonl in the key directory.
+//! Signing policies keep their state in memory only, see the cosigner module.
+//!
+//! With an audit log file every session is logged to it, see the audit module. The log is
+//! verified when the cosigner starts, it does not start on a broken log.
+
+extern crate paradise_city;
+
+use paradise_city::protocols::two_party::audit::{AuditLog, FileAuditStorage};
+use paradise_city::protocols::two_party::cosigner::{Cosigner, KeyStore};
+use paradise_city::protocols::two_party::nonce_store::FileNonceStore;
+use std::env;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 && args.len() != 4 {
+        eprintln!(
+            "usage: {} <listen address> <key directory> [audit log]",
+            args[0]
+        );
+        process::exit(2);
+    }
+    let store = KeyStore::open(&args[2]).unwrap_or_else(|e| {
+        eprintln!("cannot open key directory {}: {}", args[2], e);
+        process::exit(1);
+    });
+    let nonce_path = Path::new(&args[2]).join("nonces.jsonl");
+    let nonce_store = FileNonceStore::open(&nonce_path).unwrap_or_else(|e| {
+        eprintln!("cannot open nonce store {}: {}", nonce_path.display(), e);
+        process::exit(1);
+    });
+    let mut cosigner = Cosigner::new(store).with_nonce_store(nonce_store);
+    if let Some(path) = args.get(3) {
+        let audit_log = FileAuditStorage::open(path)
+            .and_then(AuditLog::open)
+            .unwrap_or_else(|e| {
+                eprintln!("cannot open audit log {}: {}", path, e);
+                process::exit(1);
+            });
+        println!(
+            "audit log {} has {} entries",
+            path,
+            audit_log.head().entries
+        );
+        cosigner = cosigner.with_audit_log(audit_log);
+    }
+    let listener = TcpListener::bind(&args[1]).unwrap_or_else(|e| {
+        eprintln!("cannot listen on {}: {}", args[1], e);
+        process::exit(1);
+    });
+    println!("cosigner listening on {}", args[1]);
+    cosigner.serve(listener);
+}
@@ -15,6 +15,25 @@ use super::traits::Commitment;
 use super::SECURITY_BITS;
 use curv::arithmetic::traits::Samplable;
 use curv::cryptographic_primitives::hashing::blake2b512::Blake;
+use curv::cryptographic_primitives::hashing::transcript::Transcript;
+
+const COMMITMENT_PERSONA: &[u8] = b"ParadiseCity_Com";
+
+impl HashCommitment {
+    /// c = H(transcript, m, r): binds the commitment to the protocol run the transcript
+    /// describes, a decommitment is only accepted under the same transcript.
+    pub fn create_commitment_with_transcript(
+        message: &BigInt,
+        blinding_factor: &BigInt,
+        transcript: &Transcript,
+    ) -> BigInt {
+        let mut transcript = transcript.clone();
+        transcript.append_message(b"dom-sep", COMMITMENT_PERSONA);
+        transcript.append_message(b"m", &BigInt::to_vec(message));
+        transcript.append_message(b"r", &BigInt::to_vec(blinding_factor));
+        BigInt::from(&transcript.challenge_bytes(b"commitment")[..])
+    }
+}
 
 //TODO:  using the function with BigInt's as input instead of string's makes it impossible to commit to empty message or use empty randomness
 impl Commitment<BigInt> for HashCommitment {
@@ -36,6 +36,37 @@ impl Blake {
         let result = BigInt::from(digest.finalize().as_ref());
         ECScalar::from(&result)
     }
+
+    // same as create_hash but absorbs a context (e.g. session id, party, round) first.
+    // the context is length prefixed so it cannot run into the hashed values.
+    pub fn create_hash_with_context(
+        context: &[u8],
+        big_ints: &[&BigInt],
+        persona: &[u8],
+    ) -> BigInt {
+        let mut digest = Blake2b::with_params(64, &[], &[], persona);
+        digest.update(&(context.len() as u64).to_le_bytes());
+        digest.update(context);
+
+        for value in big_ints {
+            digest.update(&BigInt::to_vec(value));
+        }
+        let ret = digest.finalize();
+        BigInt::from(ret.as_ref())
+    }
+
+    pub fn create_hash_from_ge_with_context(context: &[u8], ge_vec: &[&GE], persona: &[u8]) -> FE {
+        let mut digest = Blake2b::with_params(64, &[], &[], persona);
+        digest.update(&(context.len() as u64).to_le_bytes());
+        digest.update(context);
+
+        for value in ge_vec {
+            digest.update(&value.pk_to_key_slice());
+        }
+
+        let result = BigInt::from(digest.finalize().as_ref());
+        ECScalar::from(&result)
+    }
 }
 
 #[cfg(test)]
@@ -68,4 +99,18 @@ mod tests {
         assert_eq!(result2, result3);
     }
 
+    #[test]
+    fn create_hash_with_context_test() {
+        let result1 =
+            Blake::create_hash_with_context(b"ab", &vec![&BigInt::one()], b"Zcash_RedJubjubH");
+        let result2 =
+            Blake::create_hash_with_context(b"a", &vec![&BigInt::one()], b"Zcash_RedJubjubH");
+        assert_ne!(result1, result2);
+        let point = GE::base_point2();
+        let result3 =
+            Blake::create_hash_from_ge_with_context(b"a", &vec![&point], b"Zcash_RedJubjubH");
+        let result4 =
+            Blake::create_hash_from_ge_with_context(b"b", &vec![&point], b"Zcash_RedJubjubH");
+        assert_ne!(result3, result4);
+    }
 }
@@ -6,3 +6,4 @@
 */
 
 pub mod blake2b512;
+pub mod transcript;
@@ -0,0 +1,117 @@
+/*
+    This file is part of Curv library
+    Copyright 2018 by Kzen Networks
+    (https://github.com/KZen-networks/curv)
+    License MIT: https://github.com/KZen-networks/curv/blob/master/LICENSE
+*/
+
+use blake2_rfc::blake2b::Blake2b;
+use curv::arithmetic::big_gmp::BigInt;
+use curv::arithmetic::traits::Converter;
+use curv::elliptic::curves::curve_jubjub::FE;
+use curv::elliptic::curves::curve_jubjub::GE;
+use curv::elliptic::curves::traits::*;
+
+const TRANSCRIPT_PERSONA: &[u8] = b"ParadiseCity_Tra";
+
+/// Fiat-Shamir transcript in the style of Merlin (https://merlin.cool), over Blake2b.
+/// Every appended item is a label and a value, both length prefixed, and points and scalars are
+/// appended in their exact 32 byte encodings, so two different sequences of items never hash the
+/// same. A challenge is the hash of everything appended so far and is itself appended, so later
+/// challenges depend on earlier ones.
+///
+/// Proofs and commitments take the transcript of the outer protocol by reference and work on a
+/// copy extended with their own domain separator and statement: a proof is bound to the protocol
+/// context it was created in, and two proofs made from the same transcript cannot be swapped.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Transcript {
+    absorbed: Vec<u8>,
+}
+
+impl Transcript {
+    pub fn new(label: &[u8]) -> Transcript {
+        let mut transcript = Transcript {
+            absorbed: Vec::new(),
+        };
+        transcript.append_message(b"dom-sep", label);
+        transcript
+    }
+
+    pub fn append_message(&mut self, label: &[u8], message: &[u8]) {
+        for bytes in &[label, message] {
+            self.absorbed
+                .extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+            self.absorbed.extend_from_slice(bytes);
+        }
+    }
+
+    pub fn append_point(&mut self, label: &[u8], point: &GE) {
+        self.append_message(label, &point.pk_to_key_slice());
+    }
+
+    /// the scalar is appended as 32 bytes little endian
+    pub fn append_scalar(&mut self, label: &[u8], scalar: &FE) {
+        let mut bytes = [0u8; 32];
+        let scalar_vec = BigInt::to_vec(&scalar.to_big_int());
+        for (i, byte) in scalar_vec.iter().rev().enumerate() {
+            bytes[i] = *byte;
+        }
+        self.append_message(label, &bytes);
+    }
+
+    pub fn challenge_bytes(&mut self, label: &[u8]) -> [u8; 64] {
+        self.append_message(b"challenge", label);
+        let mut digest = Blake2b::with_params(64, &[], &[], TRANSCRIPT_PERSONA);
+        digest.update(&self.absorbed);
+        let mut challenge = [0u8; 64];
+        challenge.copy_from_slice(digest.finalize().as_ref());
+        self.append_message(label, &challenge);
+        challenge
+    }
+
+    /// 64 challenge bytes read little endian and reduced mod q
+    pub fn challenge_scalar(&mut self, label: &[u8]) -> FE {
+        let mut challenge = self.challenge_bytes(label).to_vec();
+        challenge.reverse();
+        let challenge_bn = BigInt::from(&challenge[..]).mod_floor(&FE::q());
+        ECScalar::from(&challenge_bn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Transcript;
+    use curv::elliptic::curves::curve_jubjub::GE;
+    use curv::elliptic::curves::traits::ECPoint;
+
+    #[test]
+    fn test_transcript_challenges() {
+        let mut transcript = Transcript::new(b"test");
+        transcript.append_point(b"G", &GE::generator());
+        let mut other = transcript.clone();
+        assert_eq!(
+            transcript.challenge_scalar(b"c"),
+            other.challenge_scalar(b"c")
+        );
+        // every challenge is absorbed, the next one differs
+        assert!(transcript.challenge_scalar(b"c") != transcript.challenge_scalar(b"c"));
+    }
+
+    #[test]
+    fn test_transcript_framing() {
+        // moving bytes between label and message changes the challenge
+        let mut transcript = Transcript::new(b"test");
+        transcript.append_message(b"ab", b"c");
+        let mut other = Transcript::new(b"test");
+        other.append_message(b"a", b"bc");
+        assert!(transcript.challenge_bytes(b"c")[..] != other.challenge_bytes(b"c")[..]);
+
+        let mut transcript = Transcript::new(b"test");
+        transcript.append_message(b"m", b"");
+        transcript.append_message(b"m", b"x");
+        let mut other = Transcript::new(b"test");
+        other.append_message(b"m", b"x");
+        other.append_message(b"m", b"");
+        assert!(transcript.challenge_bytes(b"c")[..] != other.challenge_bytes(b"c")[..]);
+    }
+}
@@ -11,7 +11,7 @@ use curv::elliptic::curves::curve_jubjub::GE;
 
 use curv::elliptic::curves::traits::*;
 
-use curv::cryptographic_primitives::hashing::blake2b512::Blake;
+use curv::cryptographic_primitives::hashing::transcript::Transcript;
 use zeroize::Zeroize;
 
 /// This is implementation of Schnorr's identification protocol for elliptic curve groups or a
@@ -24,6 +24,9 @@ use zeroize::Zeroize;
 /// How to prove yourself: Practical solutions to identification and signature problems.
 /// In Advances in Cryptology - CRYPTO ’86, Santa Barbara, California, USA, 1986, Proceedings,
 /// pages 186–194, 1986.
+///
+/// The challenge is drawn from the caller supplied transcript, so a proof only verifies in the
+/// protocol context (e.g. session, party and round) it was created in.
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct DLogProof {
     pub pk: GE,
@@ -31,29 +34,31 @@ pub struct DLogProof {
     pub challenge_response: FE,
 }
 
+const DLOG_PERSONA: &[u8] = b"ParadiseCityDLog";
+
 pub trait ProveDLog {
-    fn prove(sk: &FE) -> DLogProof;
+    fn prove(sk: &FE, transcript: &Transcript) -> DLogProof;
+
+    fn verify(proof: &DLogProof, transcript: &Transcript) -> Result<(), ProofError>;
+}
 
-    fn verify(proof: &DLogProof) -> Result<(), ProofError>;
+fn challenge(transcript: &Transcript, pk: &GE, pk_t_rand_commitment: &GE) -> FE {
+    let mut transcript = transcript.clone();
+    transcript.append_message(b"dom-sep", DLOG_PERSONA);
+    transcript.append_point(b"G", &GE::generator());
+    transcript.append_point(b"pk", pk);
+    transcript.append_point(b"pk_t_rand_commitment", pk_t_rand_commitment);
+    transcript.challenge_scalar(b"challenge")
 }
 
 impl ProveDLog for DLogProof {
-    fn prove(sk: &FE) -> DLogProof {
+    fn prove(sk: &FE, transcript: &Transcript) -> DLogProof {
         let base_point: GE = ECPoint::generator();
-        let generator_x = base_point.bytes_compressed_to_big_int();
         let mut sk_t_rand_commitment: FE = ECScalar::new_random();
         let pk_t_rand_commitment = base_point.scalar_mul(&sk_t_rand_commitment.get_element());
         let ec_point: GE = ECPoint::generator();
         let pk = ec_point.scalar_mul(&sk.get_element());
-        let challenge = Blake::create_hash(
-            &[
-                &pk_t_rand_commitment.bytes_compressed_to_big_int(),
-                &generator_x,
-                &pk.bytes_compressed_to_big_int(),
-            ],
-            b"Zcash_RedJubjubH",
-        );
-        let challenge_fe: FE = ECScalar::from(&challenge);
+        let challenge_fe = challenge(transcript, &pk, &pk_t_rand_commitment);
         let challenge_mul_sk = challenge_fe.mul(&sk.get_element());
         let challenge_response = sk_t_rand_commitment.sub(&challenge_mul_sk.get_element());
         sk_t_rand_commitment.zeroize();
@@ -64,18 +69,8 @@ impl ProveDLog for DLogProof {
         }
     }
 
-    fn verify(proof: &DLogProof) -> Result<(), ProofError> {
-        let ec_point: GE = ECPoint::generator();
-        let challenge = Blake::create_hash(
-            &[
-                &proof.pk_t_rand_commitment.bytes_compressed_to_big_int(),
-                &ec_point.bytes_compressed_to_big_int(),
-                &proof.pk.bytes_compressed_to_big_int(),
-            ],
-            b"Zcash_RedJubjubH",
-        );
-
-        let sk_challenge: FE = ECScalar::from(&challenge);
+    fn verify(proof: &DLogProof, transcript: &Transcript) -> Result<(), ProofError> {
+        let sk_challenge = challenge(transcript, &proof.pk, &proof.pk_t_rand_commitment);
         let pk = proof.pk;
         let pk_challenge = pk.scalar_mul(&sk_challenge.get_element());
 
@@ -95,18 +90,33 @@ impl ProveDLog for DLogProof {
 
 #[cfg(test)]
 mod tests {
+    use curv::cryptographic_primitives::hashing::transcript::Transcript;
     use curv::cryptographic_primitives::proofs::sigma_dlog::*;
     use curv::elliptic::curves::curve_jubjub::FE;
 
     #[test]
     fn test_dlog_proof() {
         let witness: FE = ECScalar::new_random();
-        let dlog_proof = DLogProof::prove(&witness);
-        let verified = DLogProof::verify(&dlog_proof);
+        let transcript = Transcript::new(b"session");
+        let dlog_proof = DLogProof::prove(&witness, &transcript);
+        let verified = DLogProof::verify(&dlog_proof, &transcript);
         match verified {
             Ok(_t) => assert!(true),
             Err(_e) => assert!(false),
         }
     }
 
+    #[test]
+    fn test_dlog_proof_wrong_context() {
+        let witness: FE = ECScalar::new_random();
+        let dlog_proof = DLogProof::prove(&witness, &Transcript::new(b"session"));
+        assert!(DLogProof::verify(&dlog_proof, &Transcript::new(b"other session")).is_err());
+
+        // bound to everything appended to the outer transcript
+        let mut transcript = Transcript::new(b"session");
+        transcript.append_message(b"round", b"keygen");
+        let dlog_proof = DLogProof::prove(&witness, &transcript);
+        assert!(DLogProof::verify(&dlog_proof, &transcript).is_ok());
+        assert!(DLogProof::verify(&dlog_proof, &Transcript::new(b"session")).is_err());
+    }
 }
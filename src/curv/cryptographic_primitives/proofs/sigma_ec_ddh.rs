@@ -6,7 +6,7 @@
 */
 
 use super::ProofError;
-use curv::cryptographic_primitives::hashing::blake2b512::Blake;
+use curv::cryptographic_primitives::hashing::transcript::Transcript;
 use curv::elliptic::curves::curve_jubjub::FE;
 use curv::elliptic::curves::curve_jubjub::GE;
 use curv::elliptic::curves::traits::*;
@@ -24,6 +24,8 @@ use zeroize::Zeroize;
 /// prover sends pi = {e, A1,A2,z}
 ///
 /// verifier checks that zG1 = A1 + eH1, zG2 = A2 + eH2
+///
+/// e is drawn from the caller supplied transcript, binding the proof to its protocol context.
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct ECDDHProof {
     pub a1: GE,
@@ -44,34 +46,40 @@ pub struct ECDDHWitness {
     pub x: FE,
 }
 
+const ECDDH_PERSONA: &[u8] = b"ParadiseCityEDDH";
+
 // TODO: move to super and use in other sigma protocols
 pub trait NISigmaProof<T, W, S> {
-    fn prove(w: &W, delta: &S) -> T;
+    fn prove(w: &W, delta: &S, transcript: &Transcript) -> T;
+
+    fn verify(&self, delta: &S, transcript: &Transcript) -> Result<(), ProofError>;
+}
 
-    fn verify(&self, delta: &S) -> Result<(), ProofError>;
+fn challenge(transcript: &Transcript, delta: &ECDDHStatement, a1: &GE, a2: &GE) -> FE {
+    let mut transcript = transcript.clone();
+    transcript.append_message(b"dom-sep", ECDDH_PERSONA);
+    transcript.append_point(b"g1", &delta.g1);
+    transcript.append_point(b"h1", &delta.h1);
+    transcript.append_point(b"g2", &delta.g2);
+    transcript.append_point(b"h2", &delta.h2);
+    transcript.append_point(b"a1", a1);
+    transcript.append_point(b"a2", a2);
+    transcript.challenge_scalar(b"challenge")
 }
 
 impl NISigmaProof<ECDDHProof, ECDDHWitness, ECDDHStatement> for ECDDHProof {
-    fn prove(w: &ECDDHWitness, delta: &ECDDHStatement) -> ECDDHProof {
+    fn prove(w: &ECDDHWitness, delta: &ECDDHStatement, transcript: &Transcript) -> ECDDHProof {
         let mut s: FE = ECScalar::new_random();
         let a1 = delta.g1 * s;
         let a2 = delta.g2 * s;
-        let e = Blake::create_hash_from_ge(
-            &[&delta.g1, &delta.h1, &delta.g2, &delta.h2, &a1, &a2],
-            b"Zcash_RedJubjubH",
-        );
+        let e = challenge(transcript, delta, &a1, &a2);
         let z = s + e * w.x;
         s.zeroize();
         ECDDHProof { a1, a2, z }
     }
 
-    fn verify(&self, delta: &ECDDHStatement) -> Result<(), ProofError> {
-        let e = Blake::create_hash_from_ge(
-            &[
-                &delta.g1, &delta.h1, &delta.g2, &delta.h2, &self.a1, &self.a2,
-            ],
-            b"Zcash_RedJubjubH",
-        );
+    fn verify(&self, delta: &ECDDHStatement, transcript: &Transcript) -> Result<(), ProofError> {
+        let e = challenge(transcript, delta, &self.a1, &self.a2);
         let z_g1 = delta.g1 * self.z;
         let z_g2 = delta.g2 * self.z;
         let a1_plus_e_h1 = self.a1 + delta.h1 * e;
@@ -86,6 +94,7 @@ impl NISigmaProof<ECDDHProof, ECDDHWitness, ECDDHStatement> for ECDDHProof {
 
 #[cfg(test)]
 mod tests {
+    use curv::cryptographic_primitives::hashing::transcript::Transcript;
     use curv::cryptographic_primitives::proofs::sigma_ec_ddh::*;
     use curv::elliptic::curves::curve_jubjub::FE;
     use curv::elliptic::curves::curve_jubjub::GE;
@@ -100,8 +109,12 @@ mod tests {
         let h2 = &g2 * &x;
         let delta = ECDDHStatement { g1, g2, h1, h2 };
         let w = ECDDHWitness { x };
-        let proof = ECDDHProof::prove(&w, &delta);
-        assert!(proof.verify(&delta).is_ok());
+        let transcript = Transcript::new(b"session");
+        let proof = ECDDHProof::prove(&w, &delta, &transcript);
+        assert!(proof.verify(&delta, &transcript).is_ok());
+        assert!(proof
+            .verify(&delta, &Transcript::new(b"other session"))
+            .is_err());
     }
 
     #[test]
@@ -115,8 +128,9 @@ mod tests {
         let h2 = &g2 * &x2;
         let delta = ECDDHStatement { g1, g2, h1, h2 };
         let w = ECDDHWitness { x };
-        let proof = ECDDHProof::prove(&w, &delta);
-        assert!(proof.verify(&delta).is_ok());
+        let transcript = Transcript::new(b"session");
+        let proof = ECDDHProof::prove(&w, &delta, &transcript);
+        assert!(proof.verify(&delta, &transcript).is_ok());
     }
 
 }
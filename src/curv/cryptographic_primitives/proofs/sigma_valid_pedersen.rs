@@ -8,7 +8,7 @@
 use super::ProofError;
 use curv::cryptographic_primitives::commitments::pedersen_commitment::PedersenCommitment;
 use curv::cryptographic_primitives::commitments::traits::Commitment;
-use curv::cryptographic_primitives::hashing::blake2b512::Blake;
+use curv::cryptographic_primitives::hashing::transcript::Transcript;
 use curv::elliptic::curves::curve_jubjub::FE;
 use curv::elliptic::curves::curve_jubjub::GE;
 use curv::elliptic::curves::traits::*;
@@ -23,6 +23,8 @@ use zeroize::Zeroize;
 /// prover sends pi = {e, A1,A2,c, z1,z2}
 ///
 /// verifier checks that z1*G + z2*H  = A1 + A2 + ec
+///
+/// e is drawn from the caller supplied transcript, binding the proof to its protocol context.
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct PedersenProof {
     e: FE,
@@ -33,14 +35,27 @@ pub struct PedersenProof {
     z2: FE,
 }
 
+const PEDERSEN_PERSONA: &[u8] = b"ParadiseCity_Ped";
+
 pub trait ProvePederesen {
-    fn prove(m: &FE, r: &FE) -> PedersenProof;
+    fn prove(m: &FE, r: &FE, transcript: &Transcript) -> PedersenProof;
+
+    fn verify(proof: &PedersenProof, transcript: &Transcript) -> Result<(), ProofError>;
+}
 
-    fn verify(proof: &PedersenProof) -> Result<(), ProofError>;
+fn challenge(transcript: &Transcript, com: &GE, a1: &GE, a2: &GE) -> FE {
+    let mut transcript = transcript.clone();
+    transcript.append_message(b"dom-sep", PEDERSEN_PERSONA);
+    transcript.append_point(b"g", &GE::generator());
+    transcript.append_point(b"h", &GE::base_point2());
+    transcript.append_point(b"com", com);
+    transcript.append_point(b"a1", a1);
+    transcript.append_point(b"a2", a2);
+    transcript.challenge_scalar(b"challenge")
 }
 
 impl ProvePederesen for PedersenProof {
-    fn prove(m: &FE, r: &FE) -> PedersenProof {
+    fn prove(m: &FE, r: &FE, transcript: &Transcript) -> PedersenProof {
         let g: GE = ECPoint::generator();
         let h = GE::base_point2();
         let mut s1: FE = ECScalar::new_random();
@@ -52,18 +67,7 @@ impl ProvePederesen for PedersenProof {
             &r.to_big_int(),
         );
         let g: GE = ECPoint::generator();
-        let challenge = Blake::create_hash(
-            &[
-                &g.bytes_compressed_to_big_int(),
-                &h.bytes_compressed_to_big_int(),
-                &com.bytes_compressed_to_big_int(),
-                &a1.bytes_compressed_to_big_int(),
-                &a2.bytes_compressed_to_big_int(),
-            ],
-            b"Zcash_RedJubjubH",
-        );
-
-        let e: FE = ECScalar::from(&challenge);
+        let e = challenge(transcript, &com, &a1, &a2);
 
         let em = e.mul(&m.get_element());
         let z1 = s1.add(&em.get_element());
@@ -82,20 +86,10 @@ impl ProvePederesen for PedersenProof {
         }
     }
 
-    fn verify(proof: &PedersenProof) -> Result<(), ProofError> {
+    fn verify(proof: &PedersenProof, transcript: &Transcript) -> Result<(), ProofError> {
         let g: GE = ECPoint::generator();
         let h = GE::base_point2();
-        let challenge = Blake::create_hash(
-            &[
-                &g.bytes_compressed_to_big_int(),
-                &h.bytes_compressed_to_big_int(),
-                &proof.com.bytes_compressed_to_big_int(),
-                &proof.a1.bytes_compressed_to_big_int(),
-                &proof.a2.bytes_compressed_to_big_int(),
-            ],
-            b"Zcash_RedJubjubH",
-        );
-        let e: FE = ECScalar::from(&challenge);
+        let e = challenge(transcript, &proof.com, &proof.a1, &proof.a2);
 
         let z1g = g.scalar_mul(&proof.z1.get_element());
         let z2h = h.scalar_mul(&proof.z2.get_element());
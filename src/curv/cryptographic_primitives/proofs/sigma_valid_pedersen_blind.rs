@@ -10,7 +10,7 @@ use curv::cryptographic_primitives::commitments::pedersen_commitment::PedersenCo
 use curv::cryptographic_primitives::commitments::traits::Commitment;
 use curv::elliptic::curves::traits::*;
 
-use curv::cryptographic_primitives::hashing::blake2b512::Blake;
+use curv::cryptographic_primitives::hashing::transcript::Transcript;
 use curv::elliptic::curves::curve_jubjub::FE;
 use curv::elliptic::curves::curve_jubjub::GE;
 use zeroize::Zeroize;
@@ -23,6 +23,8 @@ use zeroize::Zeroize;
 /// prover calculates z  = s + er,
 /// prover sends pi = {e, m,A,c, z}
 /// verifier checks that emG + zH  = A + ec
+///
+/// e is drawn from the caller supplied transcript, binding the proof to its protocol context.
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct PedersenBlindingProof {
     e: FE,
@@ -31,14 +33,27 @@ pub struct PedersenBlindingProof {
     pub com: GE,
     z: FE,
 }
+const PEDERSEN_BLIND_PERSONA: &[u8] = b"ParadiseCityPedB";
+
 pub trait ProvePederesenBlind {
-    fn prove(m: &FE, r: &FE) -> PedersenBlindingProof;
+    fn prove(m: &FE, r: &FE, transcript: &Transcript) -> PedersenBlindingProof;
+
+    fn verify(proof: &PedersenBlindingProof, transcript: &Transcript) -> Result<(), ProofError>;
+}
 
-    fn verify(proof: &PedersenBlindingProof) -> Result<(), ProofError>;
+fn challenge(transcript: &Transcript, com: &GE, a: &GE, m: &FE) -> FE {
+    let mut transcript = transcript.clone();
+    transcript.append_message(b"dom-sep", PEDERSEN_BLIND_PERSONA);
+    transcript.append_point(b"g", &GE::generator());
+    transcript.append_point(b"h", &GE::base_point2());
+    transcript.append_point(b"com", com);
+    transcript.append_point(b"a", a);
+    transcript.append_scalar(b"m", m);
+    transcript.challenge_scalar(b"challenge")
 }
 impl ProvePederesenBlind for PedersenBlindingProof {
     //TODO: add self verification to prover proof
-    fn prove(m: &FE, r: &FE) -> PedersenBlindingProof {
+    fn prove(m: &FE, r: &FE, transcript: &Transcript) -> PedersenBlindingProof {
         let h = GE::base_point2();
         let mut s: FE = ECScalar::new_random();
         let a = h.scalar_mul(&s.get_element());
@@ -47,17 +62,7 @@ impl ProvePederesenBlind for PedersenBlindingProof {
             &r.to_big_int(),
         );
         let g: GE = ECPoint::generator();
-        let challenge = Blake::create_hash(
-            &[
-                &g.bytes_compressed_to_big_int(),
-                &h.bytes_compressed_to_big_int(),
-                &com.bytes_compressed_to_big_int(),
-                &a.bytes_compressed_to_big_int(),
-                &m.to_big_int(),
-            ],
-            b"Zcash_RedJubjubH",
-        );
-        let e: FE = ECScalar::from(&challenge);
+        let e = challenge(transcript, &com, &a, m);
 
         let er = e.mul(&r.get_element());
         let z = s.add(&er.get_element());
@@ -71,21 +76,10 @@ impl ProvePederesenBlind for PedersenBlindingProof {
         }
     }
 
-    fn verify(proof: &PedersenBlindingProof) -> Result<(), ProofError> {
+    fn verify(proof: &PedersenBlindingProof, transcript: &Transcript) -> Result<(), ProofError> {
         let g: GE = ECPoint::generator();
         let h = GE::base_point2();
-        let challenge = Blake::create_hash(
-            &[
-                &g.bytes_compressed_to_big_int(),
-                &h.bytes_compressed_to_big_int(),
-                &proof.com.bytes_compressed_to_big_int(),
-                &proof.a.bytes_compressed_to_big_int(),
-                &proof.m.to_big_int(),
-            ],
-            b"Zcash_RedJubjubH",
-        );
-
-        let e: FE = ECScalar::from(&challenge);
+        let e = challenge(transcript, &proof.com, &proof.a, &proof.m);
 
         let zh = h.scalar_mul(&proof.z.get_element());
         let mg = g.scalar_mul(&proof.m.get_element());
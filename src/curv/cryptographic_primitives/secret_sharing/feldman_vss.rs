@@ -10,7 +10,7 @@ use curv::arithmetic::big_gmp::BigInt;
 use curv::elliptic::curves::curve_jubjub::FE;
 use curv::elliptic::curves::curve_jubjub::GE;
 use curv::elliptic::curves::traits::*;
-use ErrorSS::{self, VerifyShareError};
+use ErrorSS::{self, DuplicateIndex, InvalidParameters, InvalidShareCount, VerifyShareError};
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct ShamirSecretSharing {
@@ -34,15 +34,17 @@ impl VerifiableSS {
     }
 
     // generate VerifiableSS from a secret
-    pub fn share(t: usize, n: usize, secret: &FE) -> (VerifiableSS, Vec<FE>) {
-        assert!(t < n);
+    pub fn share(t: usize, n: usize, secret: &FE) -> Result<(VerifiableSS, Vec<FE>), ErrorSS> {
+        if t >= n {
+            return Err(InvalidParameters);
+        }
         let poly = VerifiableSS::sample_polynomial(t, secret);
         let index_vec: Vec<usize> = (1..=n).collect();
         let secret_shares = VerifiableSS::evaluate_polynomial(&poly, &index_vec);
 
         let G: GE = ECPoint::generator();
         let commitments = (0..poly.len()).map(|i| G * poly[i]).collect::<Vec<GE>>();
-        (
+        Ok((
             VerifiableSS {
                 parameters: ShamirSecretSharing {
                     threshold: t,
@@ -51,7 +53,7 @@ impl VerifiableSS {
                 commitments,
             },
             secret_shares,
-        )
+        ))
     }
 
     // generate VerifiableSS from a secret and user defined x values (in case user wants to distribute point f(1), f(4), f(6) and not f(1),f(2),f(3))
@@ -60,8 +62,13 @@ impl VerifiableSS {
         n: usize,
         secret: &FE,
         index_vec: &[usize],
-    ) -> (VerifiableSS, Vec<FE>) {
-        assert_eq!(n, index_vec.len());
+    ) -> Result<(VerifiableSS, Vec<FE>), ErrorSS> {
+        if n != index_vec.len() {
+            return Err(InvalidShareCount);
+        }
+        if t >= n {
+            return Err(InvalidParameters);
+        }
         let poly = VerifiableSS::sample_polynomial(t, secret);
         let secret_shares = VerifiableSS::evaluate_polynomial(&poly, index_vec);
 
@@ -69,7 +76,7 @@ impl VerifiableSS {
         let commitments = (0..poly.len())
             .map(|i| G.clone() * &poly[i])
             .collect::<Vec<GE>>();
-        (
+        Ok((
             VerifiableSS {
                 parameters: ShamirSecretSharing {
                     threshold: t,
@@ -78,7 +85,7 @@ impl VerifiableSS {
                 commitments,
             },
             secret_shares,
-        )
+        ))
     }
 
     // returns vector of coefficients
@@ -114,9 +121,14 @@ impl VerifiableSS {
         })
     }
 
-    pub fn reconstruct(&self, indices: &[usize], shares: &[FE]) -> FE {
-        assert_eq!(shares.len(), indices.len());
-        assert!(shares.len() >= self.reconstruct_limit());
+    pub fn reconstruct(&self, indices: &[usize], shares: &[FE]) -> Result<FE, ErrorSS> {
+        if shares.len() != indices.len() || shares.len() < self.reconstruct_limit() {
+            return Err(InvalidShareCount);
+        }
+        // a repeated index makes the lagrange denominator zero
+        if (1..indices.len()).any(|i| indices[..i].contains(&indices[i])) {
+            return Err(DuplicateIndex);
+        }
         // add one to indices to get points
         let points = indices
             .iter()
@@ -125,7 +137,9 @@ impl VerifiableSS {
                 ECScalar::from(&index_bn)
             })
             .collect::<Vec<FE>>();
-        VerifiableSS::lagrange_interpolation_at_zero(&points, &shares)
+        Ok(VerifiableSS::lagrange_interpolation_at_zero(
+            &points, &shares,
+        ))
     }
 
     // Performs a Lagrange interpolation in field Zp at the origin
@@ -245,7 +259,8 @@ mod tests {
     fn test_secret_sharing_3_out_of_5_at_indices() {
         let secret: FE = ECScalar::new_random();
         let parties = [1, 2, 4, 5, 6];
-        let (vss_scheme, secret_shares) = VerifiableSS::share_at_indices(3, 5, &secret, &parties);
+        let (vss_scheme, secret_shares) =
+            VerifiableSS::share_at_indices(3, 5, &secret, &parties).unwrap();
 
         let mut shares_vec = Vec::new();
         shares_vec.push(secret_shares[0].clone());
@@ -254,7 +269,9 @@ mod tests {
         shares_vec.push(secret_shares[4].clone());
         //test reconstruction
 
-        let secret_reconstructed = vss_scheme.reconstruct(&vec![0, 1, 4, 5], &shares_vec);
+        let secret_reconstructed = vss_scheme
+            .reconstruct(&vec![0, 1, 4, 5], &shares_vec)
+            .unwrap();
         assert_eq!(secret, secret_reconstructed);
     }
 
@@ -262,7 +279,7 @@ mod tests {
     fn test_secret_sharing_3_out_of_5() {
         let secret: FE = ECScalar::new_random();
 
-        let (vss_scheme, secret_shares) = VerifiableSS::share(3, 5, &secret);
+        let (vss_scheme, secret_shares) = VerifiableSS::share(3, 5, &secret).unwrap();
 
         let mut shares_vec = Vec::new();
         shares_vec.push(secret_shares[0].clone());
@@ -271,7 +288,9 @@ mod tests {
         shares_vec.push(secret_shares[4].clone());
         //test reconstruction
 
-        let secret_reconstructed = vss_scheme.reconstruct(&vec![0, 1, 2, 4], &shares_vec);
+        let secret_reconstructed = vss_scheme
+            .reconstruct(&vec![0, 1, 2, 4], &shares_vec)
+            .unwrap();
 
         assert_eq!(secret, secret_reconstructed);
         // test secret shares are verifiable
@@ -304,7 +323,7 @@ mod tests {
     fn test_secret_sharing_3_out_of_7() {
         let secret: FE = ECScalar::new_random();
 
-        let (vss_scheme, secret_shares) = VerifiableSS::share(3, 7, &secret);
+        let (vss_scheme, secret_shares) = VerifiableSS::share(3, 7, &secret).unwrap();
 
         let mut shares_vec = Vec::new();
         shares_vec.push(secret_shares[0].clone());
@@ -313,7 +332,9 @@ mod tests {
         shares_vec.push(secret_shares[4].clone());
 
         //test reconstruction
-        let secret_reconstructed = vss_scheme.reconstruct(&vec![0, 6, 2, 4], &shares_vec);
+        let secret_reconstructed = vss_scheme
+            .reconstruct(&vec![0, 6, 2, 4], &shares_vec)
+            .unwrap();
         assert_eq!(secret, secret_reconstructed);
 
         // test secret shares are verifiable
@@ -337,18 +358,33 @@ mod tests {
         assert_eq!(w, secret_reconstructed);
     }
 
+    #[test]
+    fn test_secret_sharing_bad_reconstruct() {
+        let secret: FE = ECScalar::new_random();
+
+        let (vss_scheme, secret_shares) = VerifiableSS::share(1, 3, &secret).unwrap();
+
+        let too_few = vss_scheme.reconstruct(&vec![0], &secret_shares[0..1]);
+        assert_eq!(too_few, Err(ErrorSS::InvalidShareCount));
+        let mismatch = vss_scheme.reconstruct(&vec![0, 1, 2], &secret_shares[0..2]);
+        assert_eq!(mismatch, Err(ErrorSS::InvalidShareCount));
+        let duplicate = vss_scheme.reconstruct(&vec![0, 0], &secret_shares[0..2]);
+        assert_eq!(duplicate, Err(ErrorSS::DuplicateIndex));
+        assert!(VerifiableSS::share(3, 3, &secret).is_err());
+    }
+
     #[test]
     fn test_secret_sharing_1_out_of_2() {
         let secret: FE = ECScalar::new_random();
 
-        let (vss_scheme, secret_shares) = VerifiableSS::share(1, 2, &secret);
+        let (vss_scheme, secret_shares) = VerifiableSS::share(1, 2, &secret).unwrap();
 
         let mut shares_vec = Vec::new();
         shares_vec.push(secret_shares[0].clone());
         shares_vec.push(secret_shares[1].clone());
 
         //test reconstruction
-        let secret_reconstructed = vss_scheme.reconstruct(&vec![0, 1], &shares_vec);
+        let secret_reconstructed = vss_scheme.reconstruct(&vec![0, 1], &shares_vec).unwrap();
         assert_eq!(secret, secret_reconstructed);
 
         // test secret shares are verifiable
@@ -370,14 +406,14 @@ mod tests {
     fn test_secret_sharing_1_out_of_3() {
         let secret: FE = ECScalar::new_random();
 
-        let (vss_scheme, secret_shares) = VerifiableSS::share(1, 3, &secret);
+        let (vss_scheme, secret_shares) = VerifiableSS::share(1, 3, &secret).unwrap();
 
         let mut shares_vec = Vec::new();
         shares_vec.push(secret_shares[0].clone());
         shares_vec.push(secret_shares[1].clone());
 
         // test commitment to point and sum of commitments
-        let (vss_scheme2, secret_shares2) = VerifiableSS::share(1, 3, &secret);
+        let (vss_scheme2, secret_shares2) = VerifiableSS::share(1, 3, &secret).unwrap();
         let sum = secret_shares[0].clone() + secret_shares2[0].clone();
         let point_comm1 = vss_scheme.get_point_commitment(1);
         let point_comm2 = vss_scheme.get_point_commitment(2);
@@ -390,7 +426,7 @@ mod tests {
         assert_eq!(point1_sum_com, g_sum);
 
         //test reconstruction
-        let secret_reconstructed = vss_scheme.reconstruct(&vec![0, 1], &shares_vec);
+        let secret_reconstructed = vss_scheme.reconstruct(&vec![0, 1], &shares_vec).unwrap();
         assert_eq!(secret, secret_reconstructed);
 
         // test secret shares are verifiable
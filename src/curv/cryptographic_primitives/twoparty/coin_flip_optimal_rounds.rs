@@ -5,10 +5,12 @@
     License MIT: <https://github.com/KZen-networks/curv/blob/master/LICENSE>
 */
 
+use curv::cryptographic_primitives::hashing::transcript::Transcript;
 use curv::cryptographic_primitives::proofs::sigma_valid_pedersen::PedersenProof;
 use curv::cryptographic_primitives::proofs::sigma_valid_pedersen::ProvePederesen;
 use curv::cryptographic_primitives::proofs::sigma_valid_pedersen_blind::PedersenBlindingProof;
 use curv::cryptographic_primitives::proofs::sigma_valid_pedersen_blind::ProvePederesenBlind;
+use curv::cryptographic_primitives::proofs::ProofError;
 use curv::elliptic::curves::curve_jubjub::FE;
 use curv::elliptic::curves::curve_jubjub::GE;
 use curv::elliptic::curves::traits::*;
@@ -16,6 +18,7 @@ use curv::elliptic::curves::traits::*;
 /// based on How To Simulate It – A Tutorial on the Simulation
 /// Proof Technique. protocol 7.3: Multiple coin tossing. which provide simulatble constant round
 /// coin toss
+/// the transcript is passed to party1's proofs, both parties must use the same one
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Party1FirstMessage {
     pub proof: PedersenProof,
@@ -31,18 +34,21 @@ pub struct Party1SecondMessage {
     pub seed: FE,
 }
 impl Party1FirstMessage {
-    pub fn commit() -> (Party1FirstMessage, FE, FE) {
+    pub fn commit(transcript: &Transcript) -> (Party1FirstMessage, FE, FE) {
         let seed: FE = ECScalar::new_random();
         let blinding: FE = ECScalar::new_random();
-        let proof = PedersenProof::prove(&seed, &blinding);
+        let proof = PedersenProof::prove(&seed, &blinding, transcript);
         (Party1FirstMessage { proof }, seed, blinding)
     }
 }
 impl Party2FirstMessage {
-    pub fn share(proof: &PedersenProof) -> Party2FirstMessage {
-        PedersenProof::verify(&proof).expect("{(m,r),c} proof failed");
+    pub fn share(
+        proof: &PedersenProof,
+        transcript: &Transcript,
+    ) -> Result<Party2FirstMessage, ProofError> {
+        PedersenProof::verify(&proof, transcript)?;
         let seed: FE = ECScalar::new_random();
-        Party2FirstMessage { seed }
+        Ok(Party2FirstMessage { seed })
     }
 }
 impl Party1SecondMessage {
@@ -50,8 +56,9 @@ impl Party1SecondMessage {
         party2seed: &FE,
         party1seed: &FE,
         party1blinding: &FE,
+        transcript: &Transcript,
     ) -> (Party1SecondMessage, FE) {
-        let proof = PedersenBlindingProof::prove(&party1seed, &party1blinding);
+        let proof = PedersenBlindingProof::prove(&party1seed, &party1blinding, transcript);
         let coin_flip_result = &party1seed.to_big_int() ^ &party2seed.to_big_int();
         (
             Party1SecondMessage {
@@ -64,27 +71,69 @@ impl Party1SecondMessage {
 }
 
 // party2 finalize
-pub fn finalize(proof: &PedersenBlindingProof, party2seed: &FE, party1comm: &GE) -> FE {
-    PedersenBlindingProof::verify(&proof).expect("{r,(m,c)} proof failed");
-    assert_eq!(&proof.com, party1comm);
+pub fn finalize(
+    proof: &PedersenBlindingProof,
+    party2seed: &FE,
+    party1comm: &GE,
+    transcript: &Transcript,
+) -> Result<FE, ProofError> {
+    PedersenBlindingProof::verify(&proof, transcript)?;
+    if &proof.com != party1comm {
+        return Err(ProofError);
+    }
     let coin_flip_result = &proof.m.to_big_int() ^ &party2seed.to_big_int();
-    ECScalar::from(&coin_flip_result)
+    Ok(ECScalar::from(&coin_flip_result))
 }
 
 #[cfg(test)]
 mod tests {
+    use curv::cryptographic_primitives::hashing::transcript::Transcript;
     use curv::cryptographic_primitives::twoparty::coin_flip_optimal_rounds::*;
     #[test]
     pub fn test_coin_toss() {
-        let (party1_first_message, m1, r1) = Party1FirstMessage::commit();
-        let party2_first_message = Party2FirstMessage::share(&party1_first_message.proof);
+        let transcript = Transcript::new(b"session");
+        let (party1_first_message, m1, r1) = Party1FirstMessage::commit(&transcript);
+        let party2_first_message =
+            Party2FirstMessage::share(&party1_first_message.proof, &transcript)
+                .expect("proof failed");
         let (party1_second_message, random1) =
-            Party1SecondMessage::reveal(&party2_first_message.seed, &m1, &r1);
+            Party1SecondMessage::reveal(&party2_first_message.seed, &m1, &r1, &transcript);
         let random2 = finalize(
             &party1_second_message.proof,
             &party2_first_message.seed,
             &party1_first_message.proof.com,
-        );
+            &transcript,
+        )
+        .expect("proof failed");
         assert_eq!(random1, random2)
     }
+
+    #[test]
+    pub fn test_coin_toss_wrong_commitment() {
+        let transcript = Transcript::new(b"session");
+        let (party1_first_message, m1, r1) = Party1FirstMessage::commit(&transcript);
+        let (other_first_message, _, _) = Party1FirstMessage::commit(&transcript);
+        let party2_first_message =
+            Party2FirstMessage::share(&party1_first_message.proof, &transcript)
+                .expect("proof failed");
+        let (party1_second_message, _) =
+            Party1SecondMessage::reveal(&party2_first_message.seed, &m1, &r1, &transcript);
+        let result = finalize(
+            &party1_second_message.proof,
+            &party2_first_message.seed,
+            &other_first_message.proof.com,
+            &transcript,
+        );
+        assert!(result.is_err())
+    }
+
+    #[test]
+    pub fn test_coin_toss_wrong_transcript() {
+        let (party1_first_message, _, _) = Party1FirstMessage::commit(&Transcript::new(b"session"));
+        let result = Party2FirstMessage::share(
+            &party1_first_message.proof,
+            &Transcript::new(b"other session"),
+        );
+        assert!(result.is_err())
+    }
 }
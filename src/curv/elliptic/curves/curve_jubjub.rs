@@ -10,6 +10,7 @@ use std::fmt::Debug;
 use std::str;
 pub const SECRET_KEY_SIZE: usize = 64;
 use super::pairing::bls12_381::Bls12;
+use super::sapling_crypto::group_hash::group_hash;
 use super::sapling_crypto::jubjub::*;
 use super::sapling_crypto::jubjub::{edwards, fs::Fs, JubjubBls12, PrimeOrder, Unknown};
 use super::traits::{ECPoint, ECScalar};
@@ -38,6 +39,20 @@ use std::ptr;
 use std::sync::atomic;
 use zeroize::Zeroize;
 
+// bits per window of multi_scalar_mul, divides 64 so that no window spans two limbs
+const MSM_WINDOW: usize = 4;
+const MSM_WINDOW_MASK: usize = (1 << MSM_WINDOW) - 1;
+
+// position of the highest set bit plus one, of a little endian scalar representation
+fn repr_bits(repr: &[u64; 4]) -> usize {
+    repr.iter()
+        .enumerate()
+        .rev()
+        .find(|(_, limb)| **limb != 0)
+        .map(|(i, limb)| 64 * i + 64 - limb.leading_zeros() as usize)
+        .unwrap_or(0)
+}
+
 #[derive(Clone, Copy)]
 pub struct JubjubScalar {
     purpose: &'static str,
@@ -242,7 +257,7 @@ impl<'de> Visitor<'de> for JubjubScalarVisitor {
     }
 
     fn visit_str<E: de::Error>(self, s: &str) -> Result<JubjubScalar, E> {
-        let v = BigInt::from_str_radix(s, 16).expect("Failed in serde");
+        let v = BigInt::from_str_radix(s, 16).map_err(|_| E::custom("invalid scalar hex"))?;
         Ok(ECScalar::from(&v))
     }
 }
@@ -279,6 +294,26 @@ impl JubjubPoint {
             ge: h.get_element(),
         }
     }
+
+    // H of the Sapling spec, nk = nsk * H
+    pub fn proof_generation_generator() -> JubjubPoint {
+        let params = JubjubBls12::new();
+        let p_g = FixedGenerators::ProofGenerationKey;
+
+        JubjubPoint {
+            purpose: "base_fe",
+            ge: PK::from(params.generator(p_g).clone()),
+        }
+    }
+
+    // GroupHash of the Sapling spec, None if the hash is not a point or has small order
+    pub fn group_hash(tag: &[u8], personalization: &[u8]) -> Option<JubjubPoint> {
+        let params = JubjubBls12::new();
+        group_hash::<Bls12>(tag, personalization, &params).map(|ge| JubjubPoint {
+            purpose: "group_hash",
+            ge,
+        })
+    }
 }
 
 impl Zeroize for GE {
@@ -462,6 +497,78 @@ impl<'o> Add<&'o JubjubPoint> for &'o JubjubPoint {
     }
 }
 
+impl JubjubPoint {
+    /// exact inverse of pk_to_key_slice: unlike from_bytes the cofactor is not cleared, a point
+    /// outside of the prime order subgroup is rejected
+    pub fn from_key_slice(bytes: &[u8]) -> Result<JubjubPoint, ErrorKey> {
+        if bytes.len() > 32 {
+            return Err(InvalidPublicKey);
+        }
+        let params = &JubjubBls12::new();
+        let mut bytes_array_32 = [0u8; 32];
+        bytes_array_32[32 - bytes.len()..].copy_from_slice(bytes);
+        let point = PKu::read(&bytes_array_32[..], params).map_err(|_| InvalidPublicKey)?;
+        // read accepts the sign bit set on x = 0, only the canonical encoding is let through
+        let mut canonical = vec![];
+        point.write(&mut canonical).map_err(|_| InvalidPublicKey)?;
+        if canonical[..] != bytes_array_32[..] {
+            return Err(InvalidPublicKey);
+        }
+        match point.as_prime_order(params) {
+            Some(ge) => Ok(JubjubPoint {
+                purpose: "from_key_slice",
+                ge,
+            }),
+            None => Err(InvalidPublicKey),
+        }
+    }
+
+    /// sum of scalar * point over all terms, by windowed Straus: the doublings are shared between
+    /// the terms, and each term adds one precomputed multiple of its point per MSM_WINDOW bits.
+    /// A term only costs additions up to the length of its scalar, so the 128 bit scalars of a
+    /// batch verification are about half the price of full width ones. Not constant time.
+    pub fn multi_scalar_mul(terms: &[(JubjubScalar, JubjubPoint)]) -> JubjubPoint {
+        let params = &JubjubBls12::new();
+        let reprs = terms
+            .iter()
+            .map(|(fe, _)| fe.fe.into_repr().0)
+            .collect::<Vec<[u64; 4]>>();
+        // [P, 2P, .., (2^MSM_WINDOW - 1)P] for every point
+        let tables = terms
+            .iter()
+            .map(|(_, point)| {
+                let mut table = Vec::with_capacity((1 << MSM_WINDOW) - 1);
+                table.push(point.ge.clone());
+                for i in 1..(1 << MSM_WINDOW) - 1 {
+                    let next = table[i - 1].add(&point.ge, params);
+                    table.push(next);
+                }
+                table
+            })
+            .collect::<Vec<Vec<PK>>>();
+        // windows above the longest scalar are all zero
+        let bits = reprs.iter().map(repr_bits).max().unwrap_or(0);
+        let windows = (bits + MSM_WINDOW - 1) / MSM_WINDOW;
+        let mut acc = PK::zero();
+        for window in (0..windows).rev() {
+            for _ in 0..MSM_WINDOW {
+                acc = acc.double(params);
+            }
+            let shift = (window * MSM_WINDOW) % 64;
+            for (repr, table) in reprs.iter().zip(tables.iter()) {
+                let digit = (repr[window * MSM_WINDOW / 64] >> shift) as usize & MSM_WINDOW_MASK;
+                if digit != 0 {
+                    acc = acc.add(&table[digit - 1], params);
+                }
+            }
+        }
+        JubjubPoint {
+            purpose: "multi_scalar_mul",
+            ge: acc,
+        }
+    }
+}
+
 impl Serialize for JubjubPoint {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -502,13 +609,14 @@ impl<'de> Visitor<'de> for RistrettoCurvPointVisitor {
                 "bytes_str" => {
                     bytes_str = String::from(v);
                 }
-                _ => panic!("deSerialization failed!"),
+                _ => return Err(de::Error::unknown_field(key, &["bytes_str"])),
             }
         }
-        let bytes_bn = BigInt::from_hex(&bytes_str);
+        let bytes_bn = BigInt::from_str_radix(&bytes_str, 16)
+            .map_err(|_| de::Error::custom("invalid point hex"))?;
         let bytes = BigInt::to_vec(&bytes_bn);
 
-        Ok(JubjubPoint::from_bytes(&bytes[..]).expect("error deserializing point"))
+        JubjubPoint::from_key_slice(&bytes[..]).map_err(|_| de::Error::custom("invalid point"))
     }
 }
 
@@ -516,7 +624,7 @@ impl<'de> Visitor<'de> for RistrettoCurvPointVisitor {
 mod tests {
     use super::JubjubPoint;
     use curv::arithmetic::big_gmp::BigInt;
-    use curv::arithmetic::traits::Modulo;
+    use curv::arithmetic::traits::{Modulo, Samplable};
     use curv::elliptic::curves::curve_jubjub::{FE, GE};
     use curv::elliptic::curves::traits::ECPoint;
     use curv::elliptic::curves::traits::ECScalar;
@@ -535,16 +643,65 @@ mod tests {
         let pk = GE::generator();
         let s = serde_json::to_string(&pk).expect("Failed in serialization");
         let des_pk: GE = serde_json::from_str(&s).expect("Failed in deserialization");
-        let eight: FE = ECScalar::from(&BigInt::from(8));
-        let eight_inv = eight.invert();
-        assert_eq!(des_pk.clone(), pk.clone() * &eight);
-        assert_eq!(des_pk.clone() * eight_inv, pk);
+        assert_eq!(des_pk, pk);
 
         let pk = GE::base_point2();
         let s = serde_json::to_string(&pk).expect("Failed in serialization");
         let des_pk: GE = serde_json::from_str(&s).expect("Failed in deserialization");
-        let eight = ECScalar::from(&BigInt::from(8));
-        assert_eq!(des_pk, pk * &eight);
+        assert_eq!(des_pk, pk);
+
+        let pk = GE::generator() * FE::new_random();
+        let s = serde_json::to_string(&pk).expect("Failed in serialization");
+        let des_pk: GE = serde_json::from_str(&s).expect("Failed in deserialization");
+        assert_eq!(des_pk, pk);
+    }
+
+    #[test]
+    fn test_from_key_slice_small_order() {
+        // y = 0 encodes a point of order 4, it is not in the prime order subgroup
+        let bytes = [0u8; 32];
+        assert!(JubjubPoint::from_key_slice(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_multi_scalar_mul() {
+        let mut terms = (0..5)
+            .map(|_| {
+                let point = GE::generator() * &FE::new_random();
+                (FE::new_random(), point)
+            })
+            .collect::<Vec<_>>();
+        // short, zero and maximal scalars, and a digit of every value
+        let point = GE::generator() * &FE::new_random();
+        terms.push((ECScalar::from(&BigInt::sample(128)), point));
+        terms.push((FE::zero(), point));
+        terms.push((ECScalar::from(&(FE::q() - BigInt::one())), point));
+        terms.push((
+            ECScalar::from(&BigInt::from_str_radix("fedcba9876543210", 16).unwrap()),
+            point,
+        ));
+        let expected = terms
+            .iter()
+            .skip(1)
+            .fold(terms[0].1 * &terms[0].0, |acc, (fe, point)| {
+                acc + point * fe
+            });
+        assert_eq!(JubjubPoint::multi_scalar_mul(&terms), expected);
+
+        let short = vec![(ECScalar::from(&BigInt::from(5)), point)];
+        assert_eq!(JubjubPoint::multi_scalar_mul(&short), point * &short[0].0);
+        let zero = JubjubPoint::multi_scalar_mul(&[]);
+        assert_eq!(zero + point, point);
+    }
+
+    #[test]
+    fn test_from_key_slice_non_canonical() {
+        // (0, 1) with the sign bit of x set decodes to the identity but is not its encoding
+        let mut bytes = [0u8; 32];
+        bytes[0] = 1;
+        assert!(JubjubPoint::from_key_slice(&bytes).is_ok());
+        bytes[31] = 0x80;
+        assert!(JubjubPoint::from_key_slice(&bytes).is_err());
     }
 
     #[test]
@@ -559,6 +716,13 @@ mod tests {
         assert_eq!(des_pk, pk * &eight);
     }
 
+    #[test]
+    fn test_serdes_pk_unknown_key() {
+        let s = "{\"bytes\":\"30\"}";
+        let des_pk: Result<GE, _> = serde_json::from_str(s);
+        assert!(des_pk.is_err());
+    }
+
     #[test]
     fn test_from_mpz() {
         let rand_scalar: FE = ECScalar::new_random();
@@ -683,5 +847,4 @@ mod tests {
         let s_b: FE = ECScalar::from(&s_bn);
         assert_eq!(s_a, s_b);
     }
-
 }
@@ -5,10 +5,10 @@
     License MIT: <https://github.com/KZen-networks/curv/blob/master/LICENSE>
 */
 
-extern crate rand;
+pub extern crate rand;
 
-extern crate pairing;
-extern crate sapling_crypto;
+pub extern crate pairing;
+pub extern crate sapling_crypto;
 
 pub mod curve_jubjub;
 
@@ -16,26 +16,205 @@
 
 #[macro_use]
 extern crate serde_derive;
+extern crate aes;
+extern crate bech32;
 extern crate blake2_rfc;
+extern crate chacha20poly1305;
+extern crate fpe;
+extern crate futures;
+extern crate scrypt;
 extern crate serde;
 extern crate serde_json;
 extern crate zeroize;
 pub mod curv;
 pub mod protocols;
 
-#[derive(Copy, PartialEq, Eq, Clone, Debug)]
+use std::error;
+use std::fmt;
+
+#[allow(non_snake_case)]
+#[derive(Copy, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub enum Error {
     InvalidKey,
     InvalidSS,
     InvalidCom,
     InvalidSig,
+    InvalidProof,
+    InvalidEncoding,
+    /// stored state was written by an incompatible version
+    StateVersion(u32),
+    /// the connection to the other party failed or was closed
+    Transport,
+    /// reading or writing stored key material failed
+    Storage,
+    /// the signing policy of party two refused to release its local signature
+    PolicyRejected(Rejection),
+    /// the audit log entry with this sequence number was modified, removed or moved
+    AuditChain(u64),
+    /// the ephemeral key was committed for another session or message, or has signed already
+    NonceReuse,
+    /// the cosigner has too many sessions in flight, retry later
+    Busy,
+    Protocol {
+        round: Round,
+        check: Check,
+    },
+    /// the local signature of `party` failed s_i*G == R_i + c*pk_i. s_i is encoded little endian,
+    /// R_i and pk_i as points
+    LocalSignature {
+        party: u8,
+        s_i: [u8; 32],
+        R_i: [u8; 32],
+        pk_i: [u8; 32],
+    },
+}
+
+/// protocol round in which a check failed
+#[derive(Copy, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub enum Round {
+    KeyGen,
+    CoinFlip,
+    EphKeyGen,
+    Signing,
+    Refresh,
+    Export,
+}
+
+/// the check that failed inside a protocol round
+#[derive(Copy, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub enum Check {
+    SecretShareRange,
+    PkCommitment,
+    ZkPokCommitment,
+    DLogProof,
+    ECDDHProof,
+    PedersenProof,
+    PedersenBlindingProof,
+    CoinFlipCommitment,
+    PublicShare,
+    /// both parties claim the same keygen role
+    KeyGenRole,
+    MessageFormat,
+    BatchSize,
+    Presignature,
+    Signature,
+    /// not signed by the long-term identity key the verifier expects
+    Identity,
+}
+
+/// why a signing policy refused a request
+#[derive(Copy, PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub enum Rejection {
+    /// the request needs transaction details the policy can check, and has none
+    MissingDetails,
+    SpendLimitExceeded,
+    NotAllowed,
+    Denied,
+    /// waits for an operator, retry later
+    PendingApproval,
+    ApprovalDenied,
+    /// too many requests wait for an operator already
+    ApprovalQueueFull,
 }
 
+#[derive(Copy, PartialEq, Eq, Clone, Debug)]
 pub enum ErrorSS {
     VerifyShareError,
+    InvalidParameters,
+    InvalidShareCount,
+    DuplicateIndex,
 }
 
 #[derive(Copy, PartialEq, Eq, Clone, Debug)]
 pub enum ErrorKey {
     InvalidPublicKey,
 }
+
+impl Error {
+    pub fn protocol(round: Round, check: Check) -> Error {
+        Error::Protocol { round, check }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidKey => write!(f, "invalid key"),
+            Error::InvalidSS => write!(f, "invalid secret sharing"),
+            Error::InvalidCom => write!(f, "invalid commitment"),
+            Error::InvalidSig => write!(f, "invalid signature"),
+            Error::InvalidProof => write!(f, "invalid proof"),
+            Error::InvalidEncoding => write!(f, "invalid encoding"),
+            Error::StateVersion(version) => write!(f, "unsupported state version {}", version),
+            Error::Transport => write!(f, "transport failure"),
+            Error::Storage => write!(f, "key storage failure"),
+            Error::PolicyRejected(rejection) => {
+                write!(f, "signing policy rejected the request: {:?}", rejection)
+            }
+            Error::AuditChain(sequence) => write!(f, "audit log broken at entry {}", sequence),
+            Error::NonceReuse => write!(f, "ephemeral key reused"),
+            Error::Busy => write!(f, "too many sessions in flight"),
+            Error::Protocol { round, check } => {
+                write!(f, "{:?} round failed: {:?} check", round, check)
+            }
+            Error::LocalSignature { party, .. } => {
+                write!(f, "local signature of party {} does not verify", party)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "paradise-city protocol error"
+    }
+}
+
+impl fmt::Display for ErrorSS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorSS::VerifyShareError => write!(f, "share does not match the commitments"),
+            ErrorSS::InvalidParameters => write!(f, "threshold must be smaller than share count"),
+            ErrorSS::InvalidShareCount => write!(f, "wrong number of shares or indices"),
+            ErrorSS::DuplicateIndex => write!(f, "share index used twice"),
+        }
+    }
+}
+
+impl error::Error for ErrorSS {
+    fn description(&self) -> &str {
+        "secret sharing error"
+    }
+}
+
+impl fmt::Display for ErrorKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKey::InvalidPublicKey => write!(f, "invalid public key"),
+        }
+    }
+}
+
+impl error::Error for ErrorKey {
+    fn description(&self) -> &str {
+        "key error"
+    }
+}
+
+impl From<ErrorSS> for Error {
+    fn from(_: ErrorSS) -> Error {
+        Error::InvalidSS
+    }
+}
+
+impl From<ErrorKey> for Error {
+    fn from(_: ErrorKey) -> Error {
+        Error::InvalidKey
+    }
+}
+
+impl From<curv::cryptographic_primitives::proofs::ProofError> for Error {
+    fn from(_: curv::cryptographic_primitives::proofs::ProofError) -> Error {
+        Error::InvalidProof
+    }
+}
@@ -0,0 +1,525 @@
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! The driver module for transports that do not block.
+//! Every function returns a future that runs the side of one party over an AsyncTransport, in the
+//! same order and with the same frame labels as its blocking counterpart in driver, so an async
+//! party can sign with a blocking one. The future resolves to the transport, handed back for the
+//! next session, together with the result.
+//!
+//! Both parties record their ephemeral keys in a NonceStore and party two releases its local
+//! signature only once its SigningPolicy accepts, as in driver. A policy or a store shared between
+//! sessions is passed as an Arc, a store as an Arc<Mutex<_>>.
+//!
+//! Waiting for the counterparty does not hold the executor: a server can drive many sessions on
+//! one executor. A transport may hold threads of its own, AsyncTcpTransport has two per
+//! connection. A request waiting for an operator is retried as in driver, each pause between two
+//! retries sleeps on a short lived helper thread instead of the executor.
+//!
+//! The futures are futures 0.1 ones, as the crate is edition 2015 on the pinned 2018 dependency
+//! set without async/await. They run on any executor that takes them or on the current thread
+//! with wait().
+
+use super::driver::{COIN_FLIP_FIRST, COIN_FLIP_SECOND, EPH_KEYGEN_FIRST, EPH_KEYGEN_SECOND};
+use super::driver::{KEYGEN_FIRST, KEYGEN_SECOND, LOCAL_SIGNATURE};
+use super::nonce_store::NonceStore;
+use super::party_one;
+use super::party_two;
+use super::policy::{Held, SigningPolicy, TransactionDetails};
+use super::policy::{APPROVAL_POLL_INTERVAL, APPROVAL_TTL};
+use super::session::{
+    Party1KeyGenCommitted, Party1SigningSession, Party1Start, Party2EphKeyGenDone,
+    Party2KeyGenCreated, Party2LocalSigned, Party2SigningSession, Party2Start,
+};
+use super::transport::{AsyncTransport, TransportFuture};
+use super::{EcKeyPair, SessionId, Signature, SpendAuthMessage};
+use curv::elliptic::curves::curve_jubjub::GE;
+use futures::future::{self, Loop};
+use futures::sync::oneshot;
+use futures::Future;
+use std::thread;
+use std::time::{Duration, Instant};
+use {Error, Rejection};
+
+// resolves once duration has passed, the wait runs on a helper thread
+fn delay(duration: Duration) -> TransportFuture<()> {
+    let (sender, receiver) = oneshot::channel();
+    thread::spawn(move || {
+        thread::sleep(duration);
+        let _ = sender.send(());
+    });
+    Box::new(receiver.map_err(|_| Error::Transport))
+}
+
+// local_sign_with_policy of the session, retried while the request waits for an operator
+fn local_sign_with_policy<P: SigningPolicy + 'static, S: NonceStore + 'static>(
+    session: Party2SigningSession<Party2EphKeyGenDone>,
+    policy: P,
+    store: S,
+    caller: String,
+    details: Option<TransactionDetails>,
+) -> TransportFuture<(
+    party_two::LocalSignatureMsg,
+    Party2SigningSession<Party2LocalSigned>,
+)> {
+    let start = Instant::now();
+    Box::new(future::loop_fn(
+        (session, policy, store),
+        move |(session, policy, mut store)| -> TransportFuture<Loop<_, _>> {
+            match session.local_sign_with_policy(&policy, &mut store, &caller, details.clone()) {
+                Ok(signed) => Box::new(future::ok(Loop::Break(signed))),
+                Err(Held {
+                    error: Error::PolicyRejected(Rejection::PendingApproval),
+                    session: Some(held),
+                }) if start.elapsed() < APPROVAL_TTL => Box::new(
+                    delay(APPROVAL_POLL_INTERVAL)
+                        .map(move |_| Loop::Continue((held, policy, store))),
+                ),
+                Err(held) => Box::new(future::err(held.error)),
+            }
+        },
+    ))
+}
+
+/// resolves to the transport, the share of party one and ak
+pub fn party_one_keygen<T: AsyncTransport>(
+    transport: T,
+    session_id: &SessionId,
+) -> TransportFuture<(T, EcKeyPair, GE)> {
+    let (first_msg, committed) = Party1KeyGenCommitted::commit(session_id);
+    Box::new(
+        transport
+            .send(KEYGEN_FIRST, &first_msg)
+            .and_then(|transport| transport.receive::<party_two::KeyGenFirstMsg>(KEYGEN_FIRST))
+            .and_then(move |(transport, party2_first_msg)| {
+                future::result(committed.decommit(&party2_first_msg)).and_then(
+                    move |(second_msg, keys, ak)| {
+                        transport
+                            .send(KEYGEN_SECOND, &second_msg)
+                            .map(move |transport| (transport, keys, ak))
+                    },
+                )
+            }),
+    )
+}
+
+/// resolves to the transport, the share of party two and ak
+pub fn party_two_keygen<T: AsyncTransport>(
+    transport: T,
+    session_id: &SessionId,
+) -> TransportFuture<(T, EcKeyPair, GE)> {
+    let session_id = session_id.clone();
+    Box::new(
+        transport
+            .receive::<party_one::KeyGenFirstMsg>(KEYGEN_FIRST)
+            .and_then(move |(transport, party1_first_msg)| {
+                let (first_msg, created) =
+                    Party2KeyGenCreated::create(&party1_first_msg, &session_id);
+                transport
+                    .send(KEYGEN_FIRST, &first_msg)
+                    .map(move |transport| (transport, created))
+            })
+            .and_then(|(transport, created)| {
+                transport
+                    .receive::<party_one::KeyGenSecondMsg>(KEYGEN_SECOND)
+                    .map(move |(transport, party1_second_msg)| {
+                        (transport, created, party1_second_msg)
+                    })
+            })
+            .and_then(|(transport, created, party1_second_msg)| {
+                created
+                    .verify(&party1_second_msg)
+                    .map(|(keys, ak)| (transport, keys, ak))
+            }),
+    )
+}
+
+/// signs sighash under the vk the coin flip of this session gives
+pub fn party_one_sign<T: AsyncTransport, S: NonceStore + 'static>(
+    transport: T,
+    session: Party1SigningSession<Party1Start>,
+    sighash: &[u8],
+    mut store: S,
+) -> TransportFuture<(T, SpendAuthMessage, Signature)> {
+    let sighash = sighash.to_vec();
+    let (cf_first_msg, session) = session.coin_flip_commit();
+    Box::new(
+        transport
+            .send(COIN_FLIP_FIRST, &cf_first_msg)
+            .and_then(|transport| transport.receive::<party_two::CoinFlipFirstMsg>(COIN_FLIP_FIRST))
+            .and_then(move |(transport, party2_cf_first_msg)| {
+                future::result(session.coin_flip_reveal(&party2_cf_first_msg)).and_then(
+                    move |(cf_second_msg, session)| {
+                        transport
+                            .send(COIN_FLIP_SECOND, &cf_second_msg)
+                            .map(move |transport| (transport, session))
+                    },
+                )
+            })
+            .and_then(move |(transport, session)| {
+                future::result(SpendAuthMessage::new(&session.vk(), &sighash)).and_then(
+                    move |message| {
+                        future::result(session.eph_keygen_commit_guarded(&message, &mut store))
+                            .and_then(move |(eph_first_msg, session)| {
+                                transport
+                                    .send(EPH_KEYGEN_FIRST, &eph_first_msg)
+                                    .map(move |transport| (transport, session, message, store))
+                            })
+                    },
+                )
+            })
+            .and_then(|(transport, session, message, store)| {
+                transport
+                    .receive::<party_two::EphKeyGenFirstMsg>(EPH_KEYGEN_FIRST)
+                    .map(move |(transport, party2_eph_first_msg)| {
+                        (transport, session, message, store, party2_eph_first_msg)
+                    })
+            })
+            .and_then(
+                |(transport, session, message, store, party2_eph_first_msg)| {
+                    future::result(session.eph_keygen_decommit(&party2_eph_first_msg)).and_then(
+                        move |(eph_second_msg, session)| {
+                            transport
+                                .send(EPH_KEYGEN_SECOND, &eph_second_msg)
+                                .map(move |transport| (transport, session, message, store))
+                        },
+                    )
+                },
+            )
+            .and_then(|(transport, session, message, mut store)| {
+                future::result(session.local_sign_guarded(&mut store)).and_then(
+                    move |(local_sig, session)| {
+                        transport
+                            .send(LOCAL_SIGNATURE, &local_sig)
+                            .map(move |transport| (transport, session, message))
+                    },
+                )
+            })
+            .and_then(|(transport, session, message)| {
+                transport
+                    .receive::<party_two::LocalSignatureMsg>(LOCAL_SIGNATURE)
+                    .and_then(move |(transport, party2_local_sig)| {
+                        session
+                            .finalize(&party2_local_sig)
+                            .map(|sig| (transport, message, sig))
+                    })
+            }),
+    )
+}
+
+/// signs sighash under the vk the coin flip of this session gives, once policy accepts the
+/// signing context of caller and details
+pub fn party_two_sign<T: AsyncTransport, P: SigningPolicy + 'static, S: NonceStore + 'static>(
+    transport: T,
+    session: Party2SigningSession<Party2Start>,
+    sighash: &[u8],
+    policy: P,
+    mut store: S,
+    caller: &str,
+    details: Option<TransactionDetails>,
+) -> TransportFuture<(T, SpendAuthMessage, Signature)> {
+    let sighash = sighash.to_vec();
+    let caller = caller.to_string();
+    Box::new(
+        transport
+            .receive::<party_one::CoinFlipFirstMsg>(COIN_FLIP_FIRST)
+            .and_then(move |(transport, party1_cf_first_msg)| {
+                future::result(session.coin_flip_share(&party1_cf_first_msg)).and_then(
+                    move |(cf_first_msg, session)| {
+                        transport
+                            .send(COIN_FLIP_FIRST, &cf_first_msg)
+                            .map(move |transport| (transport, session))
+                    },
+                )
+            })
+            .and_then(|(transport, session)| {
+                transport
+                    .receive::<party_one::CoinFlipSecondMsg>(COIN_FLIP_SECOND)
+                    .and_then(move |(transport, party1_cf_second_msg)| {
+                        session
+                            .coin_flip_finalize(&party1_cf_second_msg)
+                            .map(|session| (transport, session))
+                    })
+            })
+            .and_then(move |(transport, session)| {
+                future::result(SpendAuthMessage::new(&session.vk(), &sighash))
+                    .map(move |message| (transport, session, message))
+            })
+            .and_then(|(transport, session, message)| {
+                transport
+                    .receive::<party_one::EphKeyGenFirstMsg>(EPH_KEYGEN_FIRST)
+                    .and_then(move |(transport, party1_eph_first_msg)| {
+                        future::result(session.eph_keygen_create_guarded(
+                            &party1_eph_first_msg,
+                            &message,
+                            &mut store,
+                        ))
+                        .and_then(move |(eph_first_msg, session)| {
+                            transport
+                                .send(EPH_KEYGEN_FIRST, &eph_first_msg)
+                                .map(move |transport| (transport, session, message, store))
+                        })
+                    })
+            })
+            .and_then(|(transport, session, message, store)| {
+                transport
+                    .receive::<party_one::EphKeyGenSecondMsg>(EPH_KEYGEN_SECOND)
+                    .and_then(move |(transport, party1_eph_second_msg)| {
+                        session
+                            .eph_keygen_verify(&party1_eph_second_msg)
+                            .map(|(_, session)| (transport, session, message, store))
+                    })
+            })
+            .and_then(move |(transport, session, message, store)| {
+                local_sign_with_policy(session, policy, store, caller, details).and_then(
+                    move |(local_sig, session)| {
+                        transport
+                            .send(LOCAL_SIGNATURE, &local_sig)
+                            .map(move |transport| (transport, session, message))
+                    },
+                )
+            })
+            .and_then(|(transport, session, message)| {
+                transport
+                    .receive::<party_one::LocalSignatureMsg>(LOCAL_SIGNATURE)
+                    .and_then(move |(transport, party1_local_sig)| {
+                        session
+                            .finalize(&party1_local_sig)
+                            .map(|sig| (transport, message, sig))
+                    })
+            }),
+    )
+}
+
+/// runs keygen for both parties as one future, resolves to (share of party one, share of party
+/// two, ak)
+pub fn run_keygen<T1: AsyncTransport, T2: AsyncTransport>(
+    party1_transport: T1,
+    party2_transport: T2,
+    session_id: &SessionId,
+) -> TransportFuture<(EcKeyPair, EcKeyPair, GE)> {
+    Box::new(
+        party_one_keygen(party1_transport, session_id)
+            .join(party_two_keygen(party2_transport, session_id))
+            .and_then(
+                |((_, party1_keys, party1_ak), (_, party2_keys, party2_ak))| match party1_ak
+                    == party2_ak
+                {
+                    true => Ok((party1_keys, party2_keys, party1_ak)),
+                    false => Err(Error::InvalidKey),
+                },
+            ),
+    )
+}
+
+/// runs signing for both parties as one future, resolves to the message and the signature both
+/// of them computed. Each party records its ephemeral key in a clone of store, party two checks
+/// policy with an empty caller and no details
+pub fn run_signing<T1, T2, S, P>(
+    party1_transport: T1,
+    party2_transport: T2,
+    party1_session: Party1SigningSession<Party1Start>,
+    party2_session: Party2SigningSession<Party2Start>,
+    sighash: &[u8],
+    store: S,
+    policy: P,
+) -> TransportFuture<(SpendAuthMessage, Signature)>
+where
+    T1: AsyncTransport,
+    T2: AsyncTransport,
+    S: NonceStore + Clone + 'static,
+    P: SigningPolicy + 'static,
+{
+    Box::new(
+        party_one_sign(party1_transport, party1_session, sighash, store.clone())
+            .join(party_two_sign(
+                party2_transport,
+                party2_session,
+                sighash,
+                policy,
+                store,
+                "",
+                None,
+            ))
+            .and_then(|((_, message, party1_sig), (_, _, party2_sig))| {
+                match party1_sig == party2_sig {
+                    true => Ok((message, party1_sig)),
+                    false => Err(Error::InvalidSig),
+                }
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+    use protocols::two_party::async_driver;
+    use protocols::two_party::driver;
+    use protocols::two_party::nonce_store::MemoryNonceStore;
+    use protocols::two_party::policy::{AllowDenyList, Policies};
+    use protocols::two_party::session::{Party1SigningSession, Party2SigningSession};
+    use protocols::two_party::transport::{AsyncChannelTransport, AsyncTcpTransport, TcpTransport};
+    use protocols::two_party::{verify, SessionId};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use {Error, Rejection};
+
+    #[test]
+    fn test_async_driver() {
+        // both parties run on this thread
+        let session_id = SessionId::new_random();
+        let (party1_transport, party2_transport) = AsyncChannelTransport::pair();
+        let (party1_keys, party2_keys, ak) =
+            async_driver::run_keygen(party1_transport, party2_transport, &session_id)
+                .wait()
+                .expect("keygen failed");
+
+        let session_id = SessionId::new_random();
+        let (party1_transport, party2_transport) = AsyncChannelTransport::pair();
+        let (message, sig) = async_driver::run_signing(
+            party1_transport,
+            party2_transport,
+            Party1SigningSession::new(party1_keys.clone(), ak, session_id.clone()),
+            Party2SigningSession::new(party2_keys.clone(), ak, session_id),
+            &[7u8; 32],
+            Arc::new(Mutex::new(MemoryNonceStore::new())),
+            Policies::new(),
+        )
+        .wait()
+        .expect("signing failed");
+        assert!(verify(message.vk(), &message, &sig).is_ok());
+
+        // party two does not sign what its policy rejects
+        let session_id = SessionId::new_random();
+        let (party1_transport, party2_transport) = AsyncChannelTransport::pair();
+        let rejected = async_driver::party_one_sign(
+            party1_transport,
+            Party1SigningSession::new(party1_keys.clone(), ak, session_id.clone()),
+            &[8u8; 32],
+            MemoryNonceStore::new(),
+        )
+        .join(async_driver::party_two_sign(
+            party2_transport,
+            Party2SigningSession::new(party2_keys.clone(), ak, session_id),
+            &[8u8; 32],
+            AllowDenyList::callers(None, vec!["mallory".to_string()]),
+            MemoryNonceStore::new(),
+            "mallory",
+            None,
+        ))
+        .wait();
+        assert_eq!(
+            rejected.err(),
+            Some(Error::PolicyRejected(Rejection::Denied))
+        );
+
+        // the transports are handed back for the next session
+        let session_id = SessionId::new_random();
+        let (party1_transport, party2_transport) = AsyncChannelTransport::pair();
+        let party1_store = Arc::new(Mutex::new(MemoryNonceStore::new()));
+        let party1 = async_driver::party_one_sign(
+            party1_transport,
+            Party1SigningSession::new(party1_keys.clone(), ak, session_id.clone()),
+            &[8u8; 32],
+            party1_store.clone(),
+        );
+        let party2 = async_driver::party_two_sign(
+            party2_transport,
+            Party2SigningSession::new(party2_keys, ak, session_id),
+            &[8u8; 32],
+            Policies::new(),
+            MemoryNonceStore::new(),
+            "wallet",
+            None,
+        );
+        let ((party1_transport, message, sig), (party2_transport, _, _)) =
+            party1.join(party2).wait().expect("signing failed");
+        assert!(verify(message.vk(), &message, &sig).is_ok());
+
+        // the counterparty is gone
+        drop(party2_transport);
+        let session_id = SessionId::new_random();
+        match async_driver::party_one_sign(
+            party1_transport,
+            Party1SigningSession::new(party1_keys, ak, session_id),
+            &[9u8; 32],
+            party1_store,
+        )
+        .wait()
+        {
+            Err(e) => assert_eq!(e, Error::Transport),
+            Ok(_) => panic!("signed without a counterparty"),
+        }
+    }
+
+    #[test]
+    fn test_async_driver_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind");
+        let addr = listener.local_addr().expect("no local address");
+        // connecting does not wait for the accept, both parties run on this thread
+        let party1_transport = AsyncTcpTransport::connect(addr).expect("failed to connect");
+        let party2_transport = AsyncTcpTransport::accept(&listener).expect("failed to accept");
+        let session_id = SessionId::new_random();
+        let (party1_keys, party2_keys, ak) =
+            async_driver::run_keygen(party1_transport, party2_transport, &session_id)
+                .wait()
+                .expect("keygen failed");
+
+        // an async party one signs with a blocking party two
+        let session_id = SessionId::new_random();
+        let party2_session_id = session_id.clone();
+        let party2 = thread::spawn(move || {
+            let mut transport = TcpTransport::accept(&listener)?;
+            let session = Party2SigningSession::new(party2_keys, ak, party2_session_id);
+            driver::party_two_sign(
+                &mut transport,
+                session,
+                &[2u8; 32],
+                &Policies::new(),
+                &mut MemoryNonceStore::new(),
+                "wallet",
+                None,
+            )
+        });
+        let party1_transport = AsyncTcpTransport::connect(addr).expect("failed to connect");
+        let (party1_transport, message, sig) = async_driver::party_one_sign(
+            party1_transport,
+            Party1SigningSession::new(party1_keys.clone(), ak, session_id),
+            &[2u8; 32],
+            MemoryNonceStore::new(),
+        )
+        .wait()
+        .expect("signing failed");
+        let (party2_message, party2_sig) = party2.join().unwrap().expect("signing failed");
+        assert_eq!(message, party2_message);
+        assert_eq!(sig, party2_sig);
+        assert!(verify(message.vk(), &message, &sig).is_ok());
+
+        // party two closed the connection
+        match async_driver::party_one_sign(
+            party1_transport,
+            Party1SigningSession::new(party1_keys, ak, SessionId::new_random()),
+            &[3u8; 32],
+            MemoryNonceStore::new(),
+        )
+        .wait()
+        {
+            Err(e) => assert_eq!(e, Error::Transport),
+            Ok(_) => panic!("signed without a counterparty"),
+        }
+    }
+}
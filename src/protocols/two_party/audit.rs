@@ -0,0 +1,341 @@
+#![allow(non_snake_case)]
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Tamper evident log of keygen and signing sessions.
+//! Every AuditEntry holds one AuditEvent of a session and the hash of the entry before it, so an
+//! entry that is edited, removed or moved breaks the chain from there on, and verify_chain
+//! returns the sequence number where it breaks. Entries removed from the end leave a valid
+//! chain: keep the AuditHead of a verified log somewhere else (publish or sign it now and then)
+//! and check later logs with verify_against.
+//!
+//! Events hold public values only: ak, the coin flip commitment of party one, vk, sighash, R, s2
+//! as sent to party one, the final signature and the error of a failed round. No share,
+//! ephemeral key, seed or alpha is ever written.
+//!
+//! SignatureProduced is only written when party one reports the signature, a party one that
+//! never does leaves no final signature in the log. LocalSignatureReleased is the binding record:
+//! the signature is (R, s1 + s2) for the s1 of party one, so R and s2 tie any signature that
+//! shows up later to the session that released it.
+//!
+//! Where entries are kept is up to the AuditStorage: MemoryAuditStorage, or FileAuditStorage which
+//! appends one JSON line per entry and syncs it before append returns.
+
+use super::policy::TransactionDetails;
+use super::{from_hex, to_hex, SessionId};
+use blake2_rfc::blake2b::Blake2b;
+use curv::elliptic::curves::curve_jubjub::{FE, GE};
+use serde_json;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use Error;
+
+const AUDIT_PERSONA: &[u8] = b"ParadiseCity_Log";
+const HASH_SIZE: usize = 32;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AuditEvent {
+    KeyGenCompleted {
+        key_id: String,
+        ak: GE,
+    },
+    /// commitment of party one to its coin flip seed
+    CoinFlipCommitted {
+        key_id: String,
+        alpha_commitment: GE,
+    },
+    /// sighash in hex
+    MessageBound {
+        vk: GE,
+        sighash: String,
+    },
+    NonceAgreed {
+        R: GE,
+    },
+    /// s2 was released to caller for the nonce R, details as claimed by the caller
+    LocalSignatureReleased {
+        caller: String,
+        details: Option<TransactionDetails>,
+        R: GE,
+        s2: FE,
+    },
+    /// signature in hex, as reported by party one and verified
+    SignatureProduced {
+        signature: String,
+    },
+    /// request is the path of the failed request
+    Failed {
+        request: String,
+        error: Error,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub sequence: u64,
+    /// seconds since the unix epoch, by the clock of the writer
+    pub timestamp: u64,
+    pub session_id: SessionId,
+    pub event: AuditEvent,
+    /// hash of the entry before in hex, zeros for the first entry
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> Result<String, Error> {
+        let prev_hash = from_hex(&self.prev_hash).ok_or(Error::InvalidEncoding)?;
+        let event = serde_json::to_vec(&self.event).map_err(|_| Error::InvalidEncoding)?;
+        let mut digest = Blake2b::with_params(HASH_SIZE, &[], &[], AUDIT_PERSONA);
+        for field in &[
+            prev_hash,
+            self.sequence.to_le_bytes().to_vec(),
+            self.timestamp.to_le_bytes().to_vec(),
+            self.session_id.0.clone(),
+            event,
+        ] {
+            digest.update(&(field.len() as u64).to_le_bytes());
+            digest.update(field);
+        }
+        Ok(to_hex(digest.finalize().as_bytes()))
+    }
+}
+
+/// number of entries and hash of the last one, enough to check that a later log extends this one
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditHead {
+    pub entries: u64,
+    pub hash: String,
+}
+
+impl AuditHead {
+    fn genesis() -> AuditHead {
+        AuditHead {
+            entries: 0,
+            hash: to_hex(&[0u8; HASH_SIZE]),
+        }
+    }
+}
+
+/// checks entries in the order they were stored, returns the head of the log
+pub fn verify_chain(entries: &[AuditEntry]) -> Result<AuditHead, Error> {
+    let mut head = AuditHead::genesis();
+    for entry in entries {
+        if entry.sequence != head.entries
+            || entry.prev_hash != head.hash
+            || entry.compute_hash()? != entry.hash
+        {
+            return Err(Error::AuditChain(head.entries));
+        }
+        head = AuditHead {
+            entries: head.entries + 1,
+            hash: entry.hash.clone(),
+        };
+    }
+    Ok(head)
+}
+
+/// verify_chain, and that entries still hold everything head was taken from
+pub fn verify_against(entries: &[AuditEntry], head: &AuditHead) -> Result<AuditHead, Error> {
+    let current = verify_chain(entries)?;
+    if head.entries == 0 {
+        return Ok(current);
+    }
+    match entries.get(head.entries as usize - 1) {
+        Some(entry) if entry.hash == head.hash => Ok(current),
+        Some(_) => Err(Error::AuditChain(head.entries - 1)),
+        None => Err(Error::AuditChain(current.entries)),
+    }
+}
+
+pub trait AuditStorage: Send {
+    fn append(&mut self, entry: &AuditEntry) -> Result<(), Error>;
+
+    /// all entries, in the order they were appended
+    fn entries(&self) -> Result<Vec<AuditEntry>, Error>;
+}
+
+#[derive(Default)]
+pub struct MemoryAuditStorage {
+    entries: Vec<AuditEntry>,
+}
+
+impl MemoryAuditStorage {
+    pub fn new() -> MemoryAuditStorage {
+        MemoryAuditStorage::default()
+    }
+}
+
+impl AuditStorage for MemoryAuditStorage {
+    fn append(&mut self, entry: &AuditEntry) -> Result<(), Error> {
+        self.entries.push(entry.clone());
+        Ok(())
+    }
+
+    fn entries(&self) -> Result<Vec<AuditEntry>, Error> {
+        Ok(self.entries.clone())
+    }
+}
+
+/// one JSON line per entry, the file is only ever appended to
+pub struct FileAuditStorage {
+    path: PathBuf,
+}
+
+impl FileAuditStorage {
+    /// creates the file if there is none
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileAuditStorage, Error> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.as_ref())
+            .map_err(|_| Error::Storage)?;
+        Ok(FileAuditStorage {
+            path: path.as_ref().to_path_buf(),
+        })
+    }
+}
+
+impl AuditStorage for FileAuditStorage {
+    fn append(&mut self, entry: &AuditEntry) -> Result<(), Error> {
+        let mut line = serde_json::to_string(entry).map_err(|_| Error::InvalidEncoding)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|_| Error::Storage)?;
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.sync_data())
+            .map_err(|_| Error::Storage)
+    }
+
+    fn entries(&self) -> Result<Vec<AuditEntry>, Error> {
+        let log = fs::read_to_string(&self.path).map_err(|_| Error::Storage)?;
+        // a line that does not parse was tampered with like any other
+        log.lines()
+            .enumerate()
+            .map(|(i, line)| serde_json::from_str(line).map_err(|_| Error::AuditChain(i as u64)))
+            .collect()
+    }
+}
+
+pub struct AuditLog {
+    storage: Box<dyn AuditStorage>,
+    head: AuditHead,
+}
+
+impl AuditLog {
+    /// verifies the entries storage already holds, new entries extend them
+    pub fn open<S: AuditStorage + 'static>(storage: S) -> Result<AuditLog, Error> {
+        let head = verify_chain(&storage.entries()?)?;
+        Ok(AuditLog {
+            storage: Box::new(storage),
+            head,
+        })
+    }
+
+    /// the entry is stored once append returns
+    pub fn append(
+        &mut self,
+        session_id: &SessionId,
+        event: AuditEvent,
+    ) -> Result<AuditEntry, Error> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        let mut entry = AuditEntry {
+            sequence: self.head.entries,
+            timestamp,
+            session_id: session_id.clone(),
+            event,
+            prev_hash: self.head.hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash()?;
+        self.storage.append(&entry)?;
+        self.head = AuditHead {
+            entries: entry.sequence + 1,
+            hash: entry.hash.clone(),
+        };
+        Ok(entry)
+    }
+
+    pub fn head(&self) -> AuditHead {
+        self.head.clone()
+    }
+
+    pub fn entries(&self) -> Result<Vec<AuditEntry>, Error> {
+        self.storage.entries()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocols::two_party::audit::{
+        verify_against, verify_chain, AuditEvent, AuditLog, MemoryAuditStorage,
+    };
+    use protocols::two_party::test::tests::two_party_keygen;
+    use protocols::two_party::SessionId;
+    use Error;
+
+    #[test]
+    fn test_audit_log() {
+        let mut audit_log = AuditLog::open(MemoryAuditStorage::new()).expect("failed to open");
+        let session_id = SessionId::new_random();
+        let (_, _, ak) = two_party_keygen();
+        for i in 0..4 {
+            let event = AuditEvent::MessageBound {
+                vk: ak,
+                sighash: format!("{:02x}", i).repeat(32),
+            };
+            let entry = audit_log
+                .append(&session_id, event)
+                .expect("failed to append");
+            assert_eq!(entry.sequence, i);
+        }
+        let entries = audit_log.entries().unwrap();
+        let head = verify_chain(&entries).expect("chain broken");
+        assert_eq!(head, audit_log.head());
+        assert_eq!(head.entries, 4);
+
+        let mut removed = entries.clone();
+        removed.remove(1);
+        assert_eq!(verify_chain(&removed), Err(Error::AuditChain(1)));
+        let mut reordered = entries.clone();
+        reordered.swap(1, 2);
+        assert_eq!(verify_chain(&reordered), Err(Error::AuditChain(1)));
+        let mut edited = entries.clone();
+        edited[2].event = AuditEvent::NonceAgreed { R: ak };
+        assert_eq!(verify_chain(&edited), Err(Error::AuditChain(2)));
+        // renumbering after a removed entry does not help, the hash covers sequence and prev_hash
+        let mut rehashed = removed.clone();
+        rehashed[1].sequence = 1;
+        rehashed[1].prev_hash = entries[0].hash.clone();
+        assert_eq!(verify_chain(&rehashed), Err(Error::AuditChain(1)));
+
+        // a truncated log is only caught against an earlier head
+        assert!(verify_chain(&entries[..3]).is_ok());
+        assert_eq!(
+            verify_against(&entries[..3], &head),
+            Err(Error::AuditChain(3))
+        );
+        let earlier_head = verify_chain(&entries[..2]).unwrap();
+        assert_eq!(verify_against(&entries, &earlier_head), Ok(head));
+    }
+}
@@ -0,0 +1,530 @@
+#![allow(non_snake_case)]
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Batched two party signing of all spends of one transaction.
+//! Each spend runs its own signing session, with its own alpha, vk and R, under the child session
+//! id `session_id.child(i)`. Every round message carries one entry per spend, so a transaction
+//! with N spends is signed in the same number of round trips as a single spend.
+//!
+//! As in the session module every ephemeral key goes through a NonceStore, and party two signs
+//! with local_sign_with_policy: the SigningPolicy sees the signing context of every spend. It
+//! checks all of them first, a rejection there hands the batch back, e.g. while a spend waits for
+//! an operator. Then it checks and records them again in spend order, so a SpendLimit sees the
+//! whole batch. A spend rejected there ends the batch, the spends before it stay recorded.
+
+use super::nonce_store::NonceStore;
+use super::party_one;
+use super::party_two;
+use super::policy::{Held, SigningContext, SigningPolicy, TransactionDetails};
+use super::session::*;
+use super::{EcKeyPair, NonceMode, SessionId, Signature, SpendAuthMessage};
+use curv::elliptic::curves::curve_jubjub::FE;
+use curv::elliptic::curves::curve_jubjub::GE;
+use {Check, Error, Round};
+
+/// one round message per spend, in spend order
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchMsg<T> {
+    pub msgs: Vec<T>,
+}
+
+fn check_batch_size<T>(msgs: &[T], spends: usize, round: Round) -> Result<(), Error> {
+    match msgs.len() == spends {
+        true => Ok(()),
+        false => Err(Error::protocol(round, Check::BatchSize)),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1BatchSigningSession<S> {
+    sessions: Vec<Party1SigningSession<S>>,
+}
+
+impl Party1BatchSigningSession<Party1Start> {
+    pub fn new(
+        keys: EcKeyPair,
+        ak: GE,
+        session_id: &SessionId,
+        spends: u32,
+    ) -> Party1BatchSigningSession<Party1Start> {
+        let sessions = (0..spends)
+            .map(|i| Party1SigningSession::new(keys.clone(), ak, session_id.child(i)))
+            .collect();
+        Party1BatchSigningSession { sessions }
+    }
+
+    /// applies nonce_mode to the session of every spend
+    pub fn with_nonce_mode(self, nonce_mode: NonceMode) -> Party1BatchSigningSession<Party1Start> {
+        let sessions = self
+            .sessions
+            .into_iter()
+            .map(|session| session.with_nonce_mode(nonce_mode))
+            .collect();
+        Party1BatchSigningSession { sessions }
+    }
+
+    pub fn coin_flip_commit(
+        self,
+    ) -> (
+        BatchMsg<party_one::CoinFlipFirstMsg>,
+        Party1BatchSigningSession<Party1CoinFlipCommitted>,
+    ) {
+        let (msgs, sessions) = self
+            .sessions
+            .into_iter()
+            .map(|session| session.coin_flip_commit())
+            .unzip();
+        (BatchMsg { msgs }, Party1BatchSigningSession { sessions })
+    }
+}
+
+impl Party1BatchSigningSession<Party1CoinFlipCommitted> {
+    pub fn coin_flip_reveal(
+        self,
+        party2_cf_first_msgs: &BatchMsg<party_two::CoinFlipFirstMsg>,
+    ) -> Result<
+        (
+            BatchMsg<party_one::CoinFlipSecondMsg>,
+            Party1BatchSigningSession<Party1CoinFlipDone>,
+        ),
+        Error,
+    > {
+        check_batch_size(
+            &party2_cf_first_msgs.msgs,
+            self.sessions.len(),
+            Round::CoinFlip,
+        )?;
+        let mut msgs = Vec::with_capacity(self.sessions.len());
+        let mut sessions = Vec::with_capacity(self.sessions.len());
+        for (session, party2_cf_first_msg) in self
+            .sessions
+            .into_iter()
+            .zip(party2_cf_first_msgs.msgs.iter())
+        {
+            let (msg, session) = session.coin_flip_reveal(party2_cf_first_msg)?;
+            msgs.push(msg);
+            sessions.push(session);
+        }
+        Ok((BatchMsg { msgs }, Party1BatchSigningSession { sessions }))
+    }
+}
+
+impl Party1BatchSigningSession<Party1CoinFlipDone> {
+    pub fn vks(&self) -> Vec<GE> {
+        self.sessions.iter().map(|session| session.vk()).collect()
+    }
+
+    pub fn alphas(&self) -> Vec<FE> {
+        self.sessions
+            .iter()
+            .map(|session| session.alpha())
+            .collect()
+    }
+
+    /// once store accepts R_1 of every spend. messages[i] is the message of spend i, i.e. it must
+    /// be built from vks()[i]
+    pub fn eph_keygen_commit_guarded<S: NonceStore + ?Sized>(
+        self,
+        messages: &[SpendAuthMessage],
+        store: &mut S,
+    ) -> Result<
+        (
+            BatchMsg<party_one::EphKeyGenFirstMsg>,
+            Party1BatchSigningSession<Party1EphKeyGenCommitted>,
+        ),
+        Error,
+    > {
+        check_batch_size(messages, self.sessions.len(), Round::EphKeyGen)?;
+        let mut msgs = Vec::with_capacity(self.sessions.len());
+        let mut sessions = Vec::with_capacity(self.sessions.len());
+        for (session, message) in self.sessions.into_iter().zip(messages.iter()) {
+            let (msg, session) = session.eph_keygen_commit_guarded(message, store)?;
+            msgs.push(msg);
+            sessions.push(session);
+        }
+        Ok((BatchMsg { msgs }, Party1BatchSigningSession { sessions }))
+    }
+}
+
+impl Party1BatchSigningSession<Party1EphKeyGenCommitted> {
+    pub fn eph_keygen_decommit(
+        self,
+        party2_eph_first_msgs: &BatchMsg<party_two::EphKeyGenFirstMsg>,
+    ) -> Result<
+        (
+            BatchMsg<party_one::EphKeyGenSecondMsg>,
+            Party1BatchSigningSession<Party1EphKeyGenDone>,
+        ),
+        Error,
+    > {
+        check_batch_size(
+            &party2_eph_first_msgs.msgs,
+            self.sessions.len(),
+            Round::EphKeyGen,
+        )?;
+        let mut msgs = Vec::with_capacity(self.sessions.len());
+        let mut sessions = Vec::with_capacity(self.sessions.len());
+        for (session, party2_eph_first_msg) in self
+            .sessions
+            .into_iter()
+            .zip(party2_eph_first_msgs.msgs.iter())
+        {
+            let (msg, session) = session.eph_keygen_decommit(party2_eph_first_msg)?;
+            msgs.push(msg);
+            sessions.push(session);
+        }
+        Ok((BatchMsg { msgs }, Party1BatchSigningSession { sessions }))
+    }
+}
+
+impl Party1BatchSigningSession<Party1EphKeyGenDone> {
+    /// once store accepts the one signature of R_1 of every spend
+    pub fn local_sign_guarded<S: NonceStore + ?Sized>(
+        self,
+        store: &mut S,
+    ) -> Result<
+        (
+            BatchMsg<party_one::LocalSignatureMsg>,
+            Party1BatchSigningSession<Party1LocalSigned>,
+        ),
+        Error,
+    > {
+        let mut msgs = Vec::with_capacity(self.sessions.len());
+        let mut sessions = Vec::with_capacity(self.sessions.len());
+        for session in self.sessions {
+            let (msg, session) = session.local_sign_guarded(store)?;
+            msgs.push(msg);
+            sessions.push(session);
+        }
+        Ok((BatchMsg { msgs }, Party1BatchSigningSession { sessions }))
+    }
+}
+
+impl Party1BatchSigningSession<Party1LocalSigned> {
+    /// signatures in spend order, each one verified against the vk of its spend
+    pub fn finalize(
+        self,
+        party2_local_sigs: &BatchMsg<party_two::LocalSignatureMsg>,
+    ) -> Result<Vec<Signature>, Error> {
+        check_batch_size(&party2_local_sigs.msgs, self.sessions.len(), Round::Signing)?;
+        self.sessions
+            .into_iter()
+            .zip(party2_local_sigs.msgs.iter())
+            .map(|(session, party2_local_sig)| session.finalize(party2_local_sig))
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2BatchSigningSession<S> {
+    sessions: Vec<Party2SigningSession<S>>,
+}
+
+impl Party2BatchSigningSession<Party2Start> {
+    pub fn new(
+        keys: EcKeyPair,
+        ak: GE,
+        session_id: &SessionId,
+        spends: u32,
+    ) -> Party2BatchSigningSession<Party2Start> {
+        let sessions = (0..spends)
+            .map(|i| Party2SigningSession::new(keys.clone(), ak, session_id.child(i)))
+            .collect();
+        Party2BatchSigningSession { sessions }
+    }
+
+    /// applies nonce_mode to the session of every spend
+    pub fn with_nonce_mode(self, nonce_mode: NonceMode) -> Party2BatchSigningSession<Party2Start> {
+        let sessions = self
+            .sessions
+            .into_iter()
+            .map(|session| session.with_nonce_mode(nonce_mode))
+            .collect();
+        Party2BatchSigningSession { sessions }
+    }
+
+    pub fn coin_flip_share(
+        self,
+        party1_cf_first_msgs: &BatchMsg<party_one::CoinFlipFirstMsg>,
+    ) -> Result<
+        (
+            BatchMsg<party_two::CoinFlipFirstMsg>,
+            Party2BatchSigningSession<Party2CoinFlipShared>,
+        ),
+        Error,
+    > {
+        check_batch_size(
+            &party1_cf_first_msgs.msgs,
+            self.sessions.len(),
+            Round::CoinFlip,
+        )?;
+        let mut msgs = Vec::with_capacity(self.sessions.len());
+        let mut sessions = Vec::with_capacity(self.sessions.len());
+        for (session, party1_cf_first_msg) in self
+            .sessions
+            .into_iter()
+            .zip(party1_cf_first_msgs.msgs.iter())
+        {
+            let (msg, session) = session.coin_flip_share(party1_cf_first_msg)?;
+            msgs.push(msg);
+            sessions.push(session);
+        }
+        Ok((BatchMsg { msgs }, Party2BatchSigningSession { sessions }))
+    }
+}
+
+impl Party2BatchSigningSession<Party2CoinFlipShared> {
+    pub fn coin_flip_finalize(
+        self,
+        party1_cf_second_msgs: &BatchMsg<party_one::CoinFlipSecondMsg>,
+    ) -> Result<Party2BatchSigningSession<Party2CoinFlipDone>, Error> {
+        check_batch_size(
+            &party1_cf_second_msgs.msgs,
+            self.sessions.len(),
+            Round::CoinFlip,
+        )?;
+        let sessions = self
+            .sessions
+            .into_iter()
+            .zip(party1_cf_second_msgs.msgs.iter())
+            .map(|(session, party1_cf_second_msg)| session.coin_flip_finalize(party1_cf_second_msg))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Party2BatchSigningSession { sessions })
+    }
+}
+
+impl Party2BatchSigningSession<Party2CoinFlipDone> {
+    pub fn vks(&self) -> Vec<GE> {
+        self.sessions.iter().map(|session| session.vk()).collect()
+    }
+
+    /// once store accepts R_2 of every spend. messages[i] is the message of spend i, i.e. it must
+    /// be built from vks()[i]
+    pub fn eph_keygen_create_guarded<S: NonceStore + ?Sized>(
+        self,
+        party1_eph_first_msgs: &BatchMsg<party_one::EphKeyGenFirstMsg>,
+        messages: &[SpendAuthMessage],
+        store: &mut S,
+    ) -> Result<
+        (
+            BatchMsg<party_two::EphKeyGenFirstMsg>,
+            Party2BatchSigningSession<Party2EphKeyGenCreated>,
+        ),
+        Error,
+    > {
+        check_batch_size(
+            &party1_eph_first_msgs.msgs,
+            self.sessions.len(),
+            Round::EphKeyGen,
+        )?;
+        check_batch_size(messages, self.sessions.len(), Round::EphKeyGen)?;
+        let mut msgs = Vec::with_capacity(self.sessions.len());
+        let mut sessions = Vec::with_capacity(self.sessions.len());
+        for (session, (party1_eph_first_msg, message)) in self
+            .sessions
+            .into_iter()
+            .zip(party1_eph_first_msgs.msgs.iter().zip(messages.iter()))
+        {
+            let (msg, session) =
+                session.eph_keygen_create_guarded(party1_eph_first_msg, message, store)?;
+            msgs.push(msg);
+            sessions.push(session);
+        }
+        Ok((BatchMsg { msgs }, Party2BatchSigningSession { sessions }))
+    }
+}
+
+impl Party2BatchSigningSession<Party2EphKeyGenCreated> {
+    pub fn eph_keygen_verify(
+        self,
+        party1_eph_second_msgs: &BatchMsg<party_one::EphKeyGenSecondMsg>,
+    ) -> Result<Party2BatchSigningSession<Party2EphKeyGenDone>, Error> {
+        check_batch_size(
+            &party1_eph_second_msgs.msgs,
+            self.sessions.len(),
+            Round::EphKeyGen,
+        )?;
+        let sessions = self
+            .sessions
+            .into_iter()
+            .zip(party1_eph_second_msgs.msgs.iter())
+            .map(|(session, party1_eph_second_msg)| {
+                session
+                    .eph_keygen_verify(party1_eph_second_msg)
+                    .map(|(_, session)| session)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Party2BatchSigningSession { sessions })
+    }
+}
+
+impl Party2BatchSigningSession<Party2EphKeyGenDone> {
+    /// local_sign once store and policy accept every spend, details[i] is what spend i pays
+    pub fn local_sign_with_policy<P: SigningPolicy + ?Sized, S: NonceStore + ?Sized>(
+        self,
+        policy: &P,
+        store: &mut S,
+        caller: &str,
+        details: &[Option<TransactionDetails>],
+    ) -> Result<
+        (
+            BatchMsg<party_two::LocalSignatureMsg>,
+            Party2BatchSigningSession<Party2LocalSigned>,
+        ),
+        Held<Party2BatchSigningSession<Party2EphKeyGenDone>>,
+    > {
+        check_batch_size(details, self.sessions.len(), Round::Signing)?;
+        let contexts: Vec<SigningContext> = self
+            .sessions
+            .iter()
+            .zip(details.iter())
+            .map(|(session, details)| session.signing_context(caller, details.clone()))
+            .collect();
+        // every spend is checked, so all spends waiting for an operator are queued at once
+        let rejections: Vec<_> = contexts
+            .iter()
+            .filter_map(|context| policy.check(context).err())
+            .collect();
+        if let Some(&rejection) = rejections.first() {
+            return Err(Held::back(Error::PolicyRejected(rejection), self));
+        }
+        let mut msgs = Vec::with_capacity(self.sessions.len());
+        let mut sessions = Vec::with_capacity(self.sessions.len());
+        for (session, context) in self.sessions.into_iter().zip(contexts.iter()) {
+            policy.check(context).map_err(Error::PolicyRejected)?;
+            let (msg, session) = session.local_sign_guarded(store)?;
+            policy.record(context);
+            msgs.push(msg);
+            sessions.push(session);
+        }
+        Ok((BatchMsg { msgs }, Party2BatchSigningSession { sessions }))
+    }
+}
+
+impl Party2BatchSigningSession<Party2LocalSigned> {
+    /// signatures in spend order, each one verified against the vk of its spend
+    pub fn finalize(
+        self,
+        party1_local_sigs: &BatchMsg<party_one::LocalSignatureMsg>,
+    ) -> Result<Vec<Signature>, Error> {
+        check_batch_size(&party1_local_sigs.msgs, self.sessions.len(), Round::Signing)?;
+        self.sessions
+            .into_iter()
+            .zip(party1_local_sigs.msgs.iter())
+            .map(|(session, party1_local_sig)| session.finalize(party1_local_sig))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocols::two_party::batch::{Party1BatchSigningSession, Party2BatchSigningSession};
+    use protocols::two_party::nonce_store::MemoryNonceStore;
+    use protocols::two_party::policy::{Policies, SpendLimit};
+    use protocols::two_party::test::tests::{
+        details, two_party_batch_eph_keygen, two_party_keygen,
+    };
+    use protocols::two_party::{key_id, verify, SessionId};
+    use std::time::Duration;
+    use {Check, Error, Rejection, Round};
+
+    #[test]
+    fn test_2p_sign_batch() {
+        let mut party1_store = MemoryNonceStore::new();
+        let mut party2_store = MemoryNonceStore::new();
+        let (party1_batch, party2_batch, vks, messages, public_key) =
+            two_party_batch_eph_keygen(3, &mut party1_store, &mut party2_store);
+        // every spend has its own randomizer
+        assert!(vks[0] != vks[1] && vks[1] != vks[2]);
+        let spend_limit = SpendLimit::new(100, Duration::from_secs(3600));
+        let (party1_local_sigs, party1_batch) = party1_batch
+            .local_sign_guarded(&mut party1_store)
+            .expect("bad message");
+        let (party2_local_sigs, party2_batch) = party2_batch
+            .local_sign_with_policy(
+                &spend_limit,
+                &mut party2_store,
+                "wallet",
+                &[details(10, "a"), details(20, "b"), details(30, "c")],
+            )
+            .expect("bad message");
+        // every spend is counted
+        assert_eq!(spend_limit.spent(&key_id(&public_key)), 60);
+        let party1_sigs = party1_batch
+            .finalize(&party2_local_sigs)
+            .expect("bad signature");
+        let party2_sigs = party2_batch
+            .finalize(&party1_local_sigs)
+            .expect("bad signature");
+        assert_eq!(party1_sigs, party2_sigs);
+        for ((vk, message), sig) in vks.iter().zip(messages.iter()).zip(party1_sigs.iter()) {
+            assert!(verify(*vk, message, sig).is_ok());
+        }
+        // a signature does not verify under the vk of another spend
+        assert!(verify(vks[1], &messages[0], &party1_sigs[0]).is_err());
+    }
+
+    #[test]
+    fn test_2p_sign_batch_policy() {
+        let mut party2_store = MemoryNonceStore::new();
+        let (_, party2_batch, _, _, public_key) =
+            two_party_batch_eph_keygen(2, &mut MemoryNonceStore::new(), &mut party2_store);
+        let spend_limit = SpendLimit::new(50, Duration::from_secs(3600));
+        // the second spend goes over the limit, the first one stays counted
+        match party2_batch.local_sign_with_policy(
+            &spend_limit,
+            &mut party2_store,
+            "wallet",
+            &[details(20, "a"), details(40, "b")],
+        ) {
+            Err(e) => assert_eq!(
+                e.error,
+                Error::PolicyRejected(Rejection::SpendLimitExceeded)
+            ),
+            Ok(_) => panic!("released s2 over the spend limit"),
+        }
+        assert_eq!(spend_limit.spent(&key_id(&public_key)), 20);
+
+        // one details entry per spend
+        let mut party2_store = MemoryNonceStore::new();
+        let (_, party2_batch, _, _, _) =
+            two_party_batch_eph_keygen(2, &mut MemoryNonceStore::new(), &mut party2_store);
+        match party2_batch.local_sign_with_policy(
+            &Policies::new(),
+            &mut party2_store,
+            "wallet",
+            &[None],
+        ) {
+            Err(e) => assert_eq!(e.error, Error::protocol(Round::Signing, Check::BatchSize)),
+            Ok(_) => panic!("batch of the wrong size accepted"),
+        }
+    }
+
+    #[test]
+    fn test_2p_sign_batch_wrong_size() {
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let session_id = SessionId::new_random();
+        let party1_batch = Party1BatchSigningSession::new(party1_keys, public_key, &session_id, 2);
+        let party2_batch = Party2BatchSigningSession::new(party2_keys, public_key, &session_id, 3);
+
+        let (party1_cf_first_messages, _) = party1_batch.coin_flip_commit();
+        match party2_batch.coin_flip_share(&party1_cf_first_messages) {
+            Err(e) => assert_eq!(e, Error::protocol(Round::CoinFlip, Check::BatchSize)),
+            Ok(_) => panic!("batch of the wrong size accepted"),
+        }
+    }
+}
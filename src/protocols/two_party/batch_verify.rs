@@ -0,0 +1,139 @@
+#![allow(non_snake_case)]
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Batch verification of spend authorization signatures.
+//! Every queued signature satisfies s_i*G == R_i + c_i*vk_i on its own. The batch instead checks
+//! (sum z_i*s_i)*G == sum z_i*R_i + (z_i*c_i)*vk_i for random 128 bit z_i with one multi scalar
+//! multiplication, so a set containing an invalid signature passes with probability about 2^-128.
+//! When the combined check fails every entry is verified on its own to find the invalid ones.
+//!
+//! GE wraps the PrimeOrder point type of sapling-crypto, so every vk and R is in the prime order
+//! subgroup however it was built: vk_from_bytes and Signature::from_bytes reject any other point.
+//! A small order component, which random z_i could cancel, can therefore not occur, and unlike
+//! the Zcash batch equation no cofactor multiplication is needed.
+
+use super::{compute_challenge, verify, Signature, SpendAuthMessage};
+use curv::arithmetic::big_gmp::BigInt;
+use curv::arithmetic::traits::Samplable;
+use curv::elliptic::curves::curve_jubjub::FE;
+use curv::elliptic::curves::curve_jubjub::GE;
+use curv::elliptic::curves::traits::{ECPoint, ECScalar};
+
+const BATCH_SECURITY_BITS: usize = 128;
+
+#[derive(Clone, Debug, Default)]
+pub struct BatchVerifier {
+    entries: Vec<(GE, SpendAuthMessage, Signature)>,
+}
+
+impl BatchVerifier {
+    pub fn new() -> BatchVerifier {
+        BatchVerifier {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn queue(&mut self, vk: GE, message: SpendAuthMessage, sig: Signature) {
+        self.entries.push((vk, message, sig));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Err holds the indices, in queue order, of the signatures that do not verify
+    pub fn verify(&self) -> Result<(), Vec<usize>> {
+        if self.verify_combined() {
+            return Ok(());
+        }
+        let invalid = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, (vk, message, sig))| verify(*vk, message, sig).is_err())
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        match invalid.is_empty() {
+            // only reachable if the combined check failed with an unlucky z_i
+            true => Ok(()),
+            false => Err(invalid),
+        }
+    }
+
+    fn verify_combined(&self) -> bool {
+        if self.entries.is_empty() {
+            return true;
+        }
+        let mut terms = Vec::with_capacity(2 * self.entries.len());
+        let mut zs: FE = ECScalar::zero();
+        for (vk, message, sig) in &self.entries {
+            // the vk prefix of the message is part of what is signed
+            if &message.vk() != vk {
+                return false;
+            }
+            let z: FE = ECScalar::from(&BigInt::sample(BATCH_SECURITY_BITS));
+            let c = compute_challenge(&sig.R, message);
+            zs = zs + z * sig.s;
+            terms.push((z, sig.R));
+            terms.push((z * c, *vk));
+        }
+        let G: GE = ECPoint::generator();
+        G * &zs == GE::multi_scalar_mul(&terms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocols::two_party::batch_verify::BatchVerifier;
+    use protocols::two_party::test::tests::{two_party_keygen, two_party_sign_sighash};
+    use protocols::two_party::NonceMode;
+
+    #[test]
+    fn test_batch_verify() {
+        assert_eq!(BatchVerifier::new().verify(), Ok(()));
+
+        let (party1_keys, party2_keys, ak) = two_party_keygen();
+        let mut batch_verifier = BatchVerifier::new();
+        let mut signed = Vec::new();
+        for i in 0..3u8 {
+            let (message, sig) = two_party_sign_sighash(
+                party1_keys.clone(),
+                party2_keys.clone(),
+                ak,
+                &[i; 32],
+                NonceMode::Hedged,
+            )
+            .expect("signing failed");
+            batch_verifier.queue(message.vk(), message.clone(), sig.clone());
+            signed.push((message, sig));
+        }
+        assert_eq!(batch_verifier.len(), 3);
+        assert_eq!(batch_verifier.verify(), Ok(()));
+
+        // signature of spend 0 queued for the message of spend 2, and a message for another vk
+        let mut batch_verifier = BatchVerifier::new();
+        batch_verifier.queue(signed[0].0.vk(), signed[0].0.clone(), signed[0].1.clone());
+        batch_verifier.queue(signed[1].0.vk(), signed[1].0.clone(), signed[1].1.clone());
+        batch_verifier.queue(signed[2].0.vk(), signed[2].0.clone(), signed[0].1.clone());
+        batch_verifier.queue(ak, signed[1].0.clone(), signed[1].1.clone());
+        assert_eq!(batch_verifier.verify(), Err(vec![2, 3]));
+    }
+}
@@ -0,0 +1,1270 @@
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Party two as a service.
+//! Cosigner answers the rounds of party two as JSON over HTTP POST, one path per round. The
+//! client picks the session id, the in-flight state of every session is kept in memory and
+//! dropped on the first failed round, the shares from keygen go to a KeyStore on disk under their
+//! key id. A failed round answers 400 with the Error as body, CosignerClient returns that error.
+//! A session without a request for SESSION_TTL is dropped as well, and with MAX_SESSIONS in
+//! flight new sessions are refused with Error::Busy.
+//!
+//! CosignerClient is party one: it runs keygen against the service and signs with a stored key
+//! id. The cosigner computes its local signature last, on /sign/compute_s2, and the client checks
+//! it before combining, see finalize in the session module. The client then reports the
+//! signature on /sign/signature, the cosigner verifies it for the message of the session.
+//!
+//! Both sides keep their ephemeral keys in a NonceStore, MemoryNonceStore unless set otherwise,
+//! see the nonce_store module. Give the cosigner a FileNonceStore so a restart does not forget
+//! which keys have signed.
+//!
+//! With an AuditLog every completed round, released s2, reported signature and failed request is
+//! appended to it, see the audit module. An event that cannot be logged fails its request, s2 is
+//! only released once its release is logged.
+//!
+//! Before computing s2 the cosigner runs its SigningPolicy, see the policy module. The caller
+//! identity policies see is the x-paradise-city-caller header, which the proxy in front of the
+//! service must set from the identity it authenticated and strip from incoming requests. A
+//! rejection answers 400 with Error::PolicyRejected. A request waiting for manual approval keeps
+//! its session, CosignerClient retries it while waiting; any other rejection ends the session.
+//!
+//! A session belongs to the caller that opened it, a request of another caller for it fails and
+//! leaves the session alone. A key id is only signed with for the caller that generated it, to
+//! any other caller it is unknown.
+//!
+//! Policy state is not persisted. What a SpendLimit has counted, and every queued, approved or
+//! denied ManualApproval request, is gone when the cosigner restarts: a restart resets every
+//! spend limit window. Restart the service only after a full window has passed, or keep limits
+//! that also hold when a window is spent twice.
+//!
+//! The HTTP layer is only what the client needs: one request per connection, bodies sized by
+//! Content-Length. A request must arrive within READ_TIMEOUT, at most MAX_CONNECTIONS are served
+//! at the same time and further connections are answered 503 at once. Nothing is encrypted or
+//! authenticated, put the service behind a TLS proxy that authenticates party one, and store the
+//! key directory on an encrypted volume.
+
+use super::audit::{AuditEvent, AuditLog};
+use super::nonce_store::{MemoryNonceStore, NonceStore};
+use super::party_one;
+use super::party_two;
+use super::policy::{Policies, SigningPolicy, TransactionDetails, APPROVAL_POLL_INTERVAL};
+use super::session::{
+    Party1KeyGenCommitted, Party1SigningSession, Party2CoinFlipDone, Party2CoinFlipShared,
+    Party2EphKeyGenCreated, Party2EphKeyGenDone, Party2KeyGenCreated, Party2SigningSession,
+};
+use super::state::VersionedState;
+use super::VK_SIZE;
+use super::{from_hex, key_id, to_hex, verify, EcKeyPair, SessionId, Signature, SpendAuthMessage};
+use curv::elliptic::curves::curve_jubjub::GE;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use {Check, Error, Rejection, Round};
+
+pub const KEYGEN_FIRST_PATH: &str = "/keygen/first";
+pub const KEYGEN_SECOND_PATH: &str = "/keygen/second";
+pub const COIN_FLIP_FIRST_PATH: &str = "/sign/coin_flip/first";
+pub const COIN_FLIP_SECOND_PATH: &str = "/sign/coin_flip/second";
+pub const EPH_KEYGEN_FIRST_PATH: &str = "/sign/eph_keygen/first";
+pub const EPH_KEYGEN_SECOND_PATH: &str = "/sign/eph_keygen/second";
+pub const COMPUTE_S2_PATH: &str = "/sign/compute_s2";
+pub const SIGNATURE_PATH: &str = "/sign/signature";
+
+/// requests with a larger body are rejected before it is read
+pub const MAX_BODY_SIZE: usize = 1 << 20;
+
+/// start line and headers together
+pub const MAX_HEADER_SIZE: usize = 8 << 10;
+
+/// a whole request or response must be read within this time
+pub const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// connections served at the same time, each on its own thread
+pub const MAX_CONNECTIONS: usize = 64;
+
+/// a session is dropped once it has seen no request for this long
+pub const SESSION_TTL: Duration = Duration::from_secs(600);
+
+/// sessions in flight at the same time
+pub const MAX_SESSIONS: usize = 10_000;
+
+// pause after a failed accept, e.g. when out of file descriptors
+const ACCEPT_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// identity of party one, set by the authenticating proxy
+pub const CALLER_HEADER: &str = "x-paradise-city-caller";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyGenFirstRequest {
+    pub session_id: SessionId,
+    pub party1_first_msg: party_one::KeyGenFirstMsg,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyGenSecondRequest {
+    pub session_id: SessionId,
+    pub party1_second_msg: party_one::KeyGenSecondMsg,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyGenSecondResponse {
+    pub key_id: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CoinFlipFirstRequest {
+    pub session_id: SessionId,
+    pub key_id: String,
+    pub party1_cf_first_msg: party_one::CoinFlipFirstMsg,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CoinFlipSecondRequest {
+    pub session_id: SessionId,
+    pub party1_cf_second_msg: party_one::CoinFlipSecondMsg,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EphKeyGenFirstRequest {
+    pub session_id: SessionId,
+    pub sighash: Vec<u8>,
+    pub party1_eph_first_msg: party_one::EphKeyGenFirstMsg,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EphKeyGenSecondRequest {
+    pub session_id: SessionId,
+    pub party1_eph_second_msg: party_one::EphKeyGenSecondMsg,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ComputeS2Request {
+    pub session_id: SessionId,
+    /// what the sighash pays, for the policies of the cosigner
+    pub details: Option<TransactionDetails>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignatureRequest {
+    pub session_id: SessionId,
+    /// see Signature::to_bytes
+    pub signature: Vec<u8>,
+}
+
+// every request names its session, a failed request is logged under it
+#[derive(Deserialize)]
+struct RequestSession {
+    session_id: SessionId,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredKey {
+    keys: EcKeyPair,
+    ak: GE,
+    // the caller that ran keygen
+    caller: String,
+}
+
+/// shares of party two, one VersionedState file per key id
+pub struct KeyStore {
+    dir: PathBuf,
+}
+
+impl KeyStore {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<KeyStore, Error> {
+        fs::create_dir_all(dir.as_ref()).map_err(|_| Error::Storage)?;
+        Ok(KeyStore {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    // only well formed key ids map to a file, so a request cannot name a path of its own
+    fn path(&self, key_id: &str) -> Result<PathBuf, Error> {
+        match from_hex(key_id) {
+            Some(ref bytes) if bytes.len() == VK_SIZE => {
+                Ok(self.dir.join(format!("{}.json", key_id.to_lowercase())))
+            }
+            _ => Err(Error::InvalidKey),
+        }
+    }
+
+    /// returns the key id, an existing share is never overwritten
+    pub fn save(&self, keys: &EcKeyPair, ak: &GE, caller: &str) -> Result<String, Error> {
+        let key_id = key_id(ak);
+        let path = self.path(&key_id)?;
+        if path.exists() {
+            return Err(Error::Storage);
+        }
+        let stored = VersionedState::new(StoredKey {
+            keys: keys.clone(),
+            ak: *ak,
+            caller: caller.to_string(),
+        })
+        .to_json()?;
+        // written next to the final file and renamed, a crash leaves no half written share
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, stored)
+            .and_then(|_| fs::rename(&tmp_path, &path))
+            .map_err(|_| Error::Storage)?;
+        Ok(key_id)
+    }
+
+    /// a share saved for another caller is InvalidKey, as if there was none
+    pub fn load(&self, key_id: &str, caller: &str) -> Result<(EcKeyPair, GE), Error> {
+        let path = self.path(key_id)?;
+        if !path.exists() {
+            return Err(Error::InvalidKey);
+        }
+        let stored = fs::read_to_string(&path).map_err(|_| Error::Storage)?;
+        let stored: StoredKey = VersionedState::from_json(&stored)?.into_state()?;
+        match stored.caller == caller {
+            true => Ok((stored.keys, stored.ak)),
+            false => Err(Error::InvalidKey),
+        }
+    }
+}
+
+enum Pending {
+    KeyGen(Party2KeyGenCreated),
+    CoinFlipShared(Party2SigningSession<Party2CoinFlipShared>),
+    CoinFlipDone(Party2SigningSession<Party2CoinFlipDone>),
+    EphKeyGenCreated(Party2SigningSession<Party2EphKeyGenCreated>),
+    EphKeyGenDone(Party2SigningSession<Party2EphKeyGenDone>),
+    // s2 is out, waits for the signature report
+    Released(SpendAuthMessage),
+}
+
+pub struct Cosigner {
+    store: KeyStore,
+    // with the time of the last request of the session and the caller that opened it
+    pending: Mutex<HashMap<SessionId, (Instant, String, Pending)>>,
+    session_ttl: Duration,
+    max_sessions: usize,
+    max_connections: usize,
+    // one lock, so check and record of concurrent requests do not interleave
+    policy: Mutex<Box<dyn SigningPolicy>>,
+    nonce_store: Mutex<Box<dyn NonceStore>>,
+    audit: Option<Mutex<AuditLog>>,
+}
+
+fn unexpected(round: Round) -> Error {
+    Error::protocol(round, Check::MessageFormat)
+}
+
+impl Cosigner {
+    pub fn new(store: KeyStore) -> Cosigner {
+        Cosigner {
+            store,
+            pending: Mutex::new(HashMap::new()),
+            session_ttl: SESSION_TTL,
+            max_sessions: MAX_SESSIONS,
+            max_connections: MAX_CONNECTIONS,
+            policy: Mutex::new(Box::new(Policies::new())),
+            nonce_store: Mutex::new(Box::new(MemoryNonceStore::new())),
+            audit: None,
+        }
+    }
+
+    /// replaces the default policy, which accepts everything
+    pub fn with_policy<P: SigningPolicy + 'static>(self, policy: P) -> Cosigner {
+        Cosigner {
+            policy: Mutex::new(Box::new(policy)),
+            ..self
+        }
+    }
+
+    pub fn with_nonce_store<S: NonceStore + 'static>(self, nonce_store: S) -> Cosigner {
+        Cosigner {
+            nonce_store: Mutex::new(Box::new(nonce_store)),
+            ..self
+        }
+    }
+
+    pub fn with_audit_log(self, audit_log: AuditLog) -> Cosigner {
+        Cosigner {
+            audit: Some(Mutex::new(audit_log)),
+            ..self
+        }
+    }
+
+    /// replaces SESSION_TTL and MAX_SESSIONS
+    pub fn with_session_limits(self, session_ttl: Duration, max_sessions: usize) -> Cosigner {
+        Cosigner {
+            session_ttl,
+            max_sessions,
+            ..self
+        }
+    }
+
+    /// replaces MAX_CONNECTIONS
+    pub fn with_max_connections(self, max_connections: usize) -> Cosigner {
+        Cosigner {
+            max_connections,
+            ..self
+        }
+    }
+
+    fn audit(&self, session_id: &SessionId, event: AuditEvent) -> Result<(), Error> {
+        match self.audit {
+            Some(ref audit_log) => audit_log
+                .lock()
+                .unwrap()
+                .append(session_id, event)
+                .map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    // a session is taken out for the duration of a round and only put back on success, a session
+    // of another caller stays where it is
+    fn take(&self, session_id: &SessionId, caller: &str, round: Round) -> Result<Pending, Error> {
+        let mut sessions = self.pending.lock().unwrap();
+        match sessions.get(session_id) {
+            Some(&(_, ref owner, _)) if owner == caller => (),
+            _ => return Err(unexpected(round)),
+        }
+        match sessions.remove(session_id) {
+            Some((time, _, pending)) if time.elapsed() < self.session_ttl => Ok(pending),
+            _ => Err(unexpected(round)),
+        }
+    }
+
+    fn start(
+        &self,
+        session_id: &SessionId,
+        caller: &str,
+        pending: Pending,
+        round: Round,
+    ) -> Result<(), Error> {
+        let mut sessions = self.pending.lock().unwrap();
+        let session_ttl = self.session_ttl;
+        sessions.retain(|_, &mut (time, _, _)| time.elapsed() < session_ttl);
+        if sessions.contains_key(session_id) {
+            return Err(unexpected(round));
+        }
+        if sessions.len() >= self.max_sessions {
+            return Err(Error::Busy);
+        }
+        sessions.insert(
+            session_id.clone(),
+            (Instant::now(), caller.to_string(), pending),
+        );
+        Ok(())
+    }
+
+    fn put_back(&self, session_id: SessionId, caller: &str, pending: Pending) {
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(session_id, (Instant::now(), caller.to_string(), pending));
+    }
+
+    fn keygen_first(
+        &self,
+        caller: &str,
+        req: KeyGenFirstRequest,
+    ) -> Result<party_two::KeyGenFirstMsg, Error> {
+        let (first_msg, created) =
+            Party2KeyGenCreated::create(&req.party1_first_msg, &req.session_id);
+        self.start(
+            &req.session_id,
+            caller,
+            Pending::KeyGen(created),
+            Round::KeyGen,
+        )?;
+        Ok(first_msg)
+    }
+
+    fn keygen_second(
+        &self,
+        caller: &str,
+        req: KeyGenSecondRequest,
+    ) -> Result<KeyGenSecondResponse, Error> {
+        let created = match self.take(&req.session_id, caller, Round::KeyGen)? {
+            Pending::KeyGen(created) => created,
+            _ => return Err(unexpected(Round::KeyGen)),
+        };
+        let (keys, ak) = created.verify(&req.party1_second_msg)?;
+        let key_id = self.store.save(&keys, &ak, caller)?;
+        self.audit(
+            &req.session_id,
+            AuditEvent::KeyGenCompleted {
+                key_id: key_id.clone(),
+                ak,
+            },
+        )?;
+        Ok(KeyGenSecondResponse { key_id })
+    }
+
+    fn coin_flip_first(
+        &self,
+        caller: &str,
+        req: CoinFlipFirstRequest,
+    ) -> Result<party_two::CoinFlipFirstMsg, Error> {
+        let (keys, ak) = self.store.load(&req.key_id, caller)?;
+        let session = Party2SigningSession::new(keys, ak, req.session_id.clone());
+        let (cf_first_msg, session) = session.coin_flip_share(&req.party1_cf_first_msg)?;
+        self.start(
+            &req.session_id,
+            caller,
+            Pending::CoinFlipShared(session),
+            Round::CoinFlip,
+        )?;
+        // logged once the session is stored, a refused session id is only logged as failed
+        let event = AuditEvent::CoinFlipCommitted {
+            key_id: req.key_id.clone(),
+            alpha_commitment: req.party1_cf_first_msg.cf_msg1.proof.com,
+        };
+        if let Err(e) = self.audit(&req.session_id, event) {
+            let _ = self.take(&req.session_id, caller, Round::CoinFlip);
+            return Err(e);
+        }
+        Ok(cf_first_msg)
+    }
+
+    fn coin_flip_second(&self, caller: &str, req: CoinFlipSecondRequest) -> Result<(), Error> {
+        let session = match self.take(&req.session_id, caller, Round::CoinFlip)? {
+            Pending::CoinFlipShared(session) => session,
+            _ => return Err(unexpected(Round::CoinFlip)),
+        };
+        let session = session.coin_flip_finalize(&req.party1_cf_second_msg)?;
+        self.put_back(req.session_id, caller, Pending::CoinFlipDone(session));
+        Ok(())
+    }
+
+    fn eph_keygen_first(
+        &self,
+        caller: &str,
+        req: EphKeyGenFirstRequest,
+    ) -> Result<party_two::EphKeyGenFirstMsg, Error> {
+        let session = match self.take(&req.session_id, caller, Round::EphKeyGen)? {
+            Pending::CoinFlipDone(session) => session,
+            _ => return Err(unexpected(Round::EphKeyGen)),
+        };
+        // the message is always for the vk party two computed itself
+        let message = SpendAuthMessage::new(&session.vk(), &req.sighash)?;
+        self.audit(
+            &req.session_id,
+            AuditEvent::MessageBound {
+                vk: message.vk(),
+                sighash: to_hex(message.sighash()),
+            },
+        )?;
+        let (eph_first_msg, session) = session.eph_keygen_create_guarded(
+            &req.party1_eph_first_msg,
+            &message,
+            &mut **self.nonce_store.lock().unwrap(),
+        )?;
+        self.put_back(req.session_id, caller, Pending::EphKeyGenCreated(session));
+        Ok(eph_first_msg)
+    }
+
+    fn eph_keygen_second(
+        &self,
+        caller: &str,
+        req: EphKeyGenSecondRequest,
+    ) -> Result<party_two::EphKeyGenSecondMsg, Error> {
+        let session = match self.take(&req.session_id, caller, Round::EphKeyGen)? {
+            Pending::EphKeyGenCreated(session) => session,
+            _ => return Err(unexpected(Round::EphKeyGen)),
+        };
+        let (eph_second_msg, session) = session.eph_keygen_verify(&req.party1_eph_second_msg)?;
+        self.audit(&req.session_id, AuditEvent::NonceAgreed { R: session.R() })?;
+        self.put_back(req.session_id, caller, Pending::EphKeyGenDone(session));
+        Ok(eph_second_msg)
+    }
+
+    // the ephemeral key is gone once s2 is out, only the message is kept for the report
+    fn compute_s2(
+        &self,
+        caller: &str,
+        req: ComputeS2Request,
+    ) -> Result<party_two::LocalSignatureMsg, Error> {
+        let session = match self.take(&req.session_id, caller, Round::Signing)? {
+            Pending::EphKeyGenDone(session) => session,
+            _ => return Err(unexpected(Round::Signing)),
+        };
+        let context = session.signing_context(caller, req.details);
+        let policy = self.policy.lock().unwrap();
+        match policy.check(&context) {
+            Ok(()) => (),
+            Err(Rejection::PendingApproval) => {
+                self.put_back(req.session_id, caller, Pending::EphKeyGenDone(session));
+                return Err(Error::PolicyRejected(Rejection::PendingApproval));
+            }
+            Err(rejection) => return Err(Error::PolicyRejected(rejection)),
+        }
+        let message = SpendAuthMessage::new(&context.vk, &context.sighash)?;
+        let nonce = session.R();
+        let (local_sig, _) = session.local_sign_guarded(&mut **self.nonce_store.lock().unwrap())?;
+        self.audit(
+            &req.session_id,
+            AuditEvent::LocalSignatureReleased {
+                caller: context.caller.clone(),
+                details: context.details.clone(),
+                R: nonce,
+                s2: local_sig.s2,
+            },
+        )?;
+        policy.record(&context);
+        self.put_back(req.session_id, caller, Pending::Released(message));
+        Ok(local_sig)
+    }
+
+    // ends the session
+    fn report_signature(&self, caller: &str, req: SignatureRequest) -> Result<(), Error> {
+        let message = match self.take(&req.session_id, caller, Round::Signing)? {
+            Pending::Released(message) => message,
+            _ => return Err(unexpected(Round::Signing)),
+        };
+        let sig = Signature::from_bytes(&req.signature)?;
+        verify(message.vk(), &message, &sig)?;
+        self.audit(
+            &req.session_id,
+            AuditEvent::SignatureProduced {
+                signature: to_hex(&req.signature),
+            },
+        )
+    }
+
+    /// answers one request from caller, returns the HTTP status and the JSON body
+    pub fn handle(&self, path: &str, caller: &str, body: &str) -> (u16, String) {
+        let result = match path {
+            KEYGEN_FIRST_PATH => call(body, |req| self.keygen_first(caller, req)),
+            KEYGEN_SECOND_PATH => call(body, |req| self.keygen_second(caller, req)),
+            COIN_FLIP_FIRST_PATH => call(body, |req| self.coin_flip_first(caller, req)),
+            COIN_FLIP_SECOND_PATH => call(body, |req| self.coin_flip_second(caller, req)),
+            EPH_KEYGEN_FIRST_PATH => call(body, |req| self.eph_keygen_first(caller, req)),
+            EPH_KEYGEN_SECOND_PATH => call(body, |req| self.eph_keygen_second(caller, req)),
+            COMPUTE_S2_PATH => call(body, |req| self.compute_s2(caller, req)),
+            SIGNATURE_PATH => call(body, |req| self.report_signature(caller, req)),
+            _ => return (404, String::new()),
+        };
+        match result {
+            Ok(body) => (200, body),
+            Err(e) => {
+                self.audit_failure(path, body, e);
+                (400, serde_json::to_string(&e).unwrap_or_default())
+            }
+        }
+    }
+
+    // a request waiting for approval is retried, it is logged once released or rejected
+    fn audit_failure(&self, path: &str, body: &str, error: Error) {
+        if error == Error::PolicyRejected(Rejection::PendingApproval) {
+            return;
+        }
+        if let Ok(req) = serde_json::from_str::<RequestSession>(body) {
+            let event = AuditEvent::Failed {
+                request: path.to_string(),
+                error,
+            };
+            // the request failed already, a failure to log it changes nothing
+            let _ = self.audit(&req.session_id, event);
+        }
+    }
+
+    /// serves every connection on its own thread, a failed accept is logged to stderr
+    pub fn serve(self, listener: TcpListener) {
+        let cosigner = Arc::new(self);
+        let connections = Arc::new(AtomicUsize::new(0));
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("cosigner: failed to accept a connection: {}", e);
+                    thread::sleep(ACCEPT_RETRY_INTERVAL);
+                    continue;
+                }
+            };
+            let connection = Connection::open(&connections);
+            if connection.count > cosigner.max_connections {
+                let _ = write_message(&mut stream, "HTTP/1.1 503 Service Unavailable", "");
+                continue;
+            }
+            let cosigner = cosigner.clone();
+            thread::spawn(move || {
+                let _connection = connection;
+                cosigner.serve_connection(stream)
+            });
+        }
+    }
+
+    fn serve_connection(&self, mut stream: TcpStream) -> Result<(), Error> {
+        let (status, body) = match read_message(&mut stream)? {
+            (ref start_line, ref headers, ref body) if start_line.starts_with("POST ") => {
+                let path = start_line.split(' ').nth(1).unwrap_or("");
+                let caller = headers
+                    .get(CALLER_HEADER)
+                    .map_or("", |caller| caller.as_str());
+                self.handle(path, caller, body)
+            }
+            _ => (405, String::new()),
+        };
+        let reason = match status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            _ => "Method Not Allowed",
+        };
+        write_message(
+            &mut stream,
+            &format!("HTTP/1.1 {} {}", status, reason),
+            &body,
+        )
+    }
+}
+
+// counts a connection as served until dropped
+struct Connection {
+    connections: Arc<AtomicUsize>,
+    count: usize,
+}
+
+impl Connection {
+    fn open(connections: &Arc<AtomicUsize>) -> Connection {
+        Connection {
+            connections: connections.clone(),
+            count: connections.fetch_add(1, Ordering::SeqCst) + 1,
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn call<Req, Resp, F>(body: &str, f: F) -> Result<String, Error>
+where
+    Req: DeserializeOwned,
+    Resp: Serialize,
+    F: FnOnce(Req) -> Result<Resp, Error>,
+{
+    let req = serde_json::from_str(body).map_err(|_| Error::InvalidEncoding)?;
+    let resp = f(req)?;
+    serde_json::to_string(&resp).map_err(|_| Error::InvalidEncoding)
+}
+
+// every read waits at most until the deadline
+struct DeadlineReader<'a> {
+    stream: &'a mut TcpStream,
+    deadline: Instant,
+}
+
+impl<'a> Read for DeadlineReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(self.deadline - now))?;
+        self.stream.read(buf)
+    }
+}
+
+// a line cut off by the end of the stream or by MAX_HEADER_SIZE is refused
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, Error> {
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|_| Error::Transport)?;
+    match line.ends_with('\n') {
+        true => Ok(line.trim_end().to_string()),
+        false => Err(Error::InvalidEncoding),
+    }
+}
+
+// start line, headers with lowercase names and a body of Content-Length bytes, all of it read
+// within READ_TIMEOUT
+fn read_message(
+    stream: &mut TcpStream,
+) -> Result<(String, HashMap<String, String>, String), Error> {
+    let mut reader = BufReader::new(DeadlineReader {
+        stream,
+        deadline: Instant::now() + READ_TIMEOUT,
+    });
+    let mut head = (&mut reader).take(MAX_HEADER_SIZE as u64);
+    let start_line = read_line(&mut head)?;
+    let mut headers = HashMap::new();
+    loop {
+        let header = read_line(&mut head)?;
+        if header.is_empty() {
+            break;
+        }
+        let mut parts = header.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim().to_lowercase();
+        let value = parts.next().unwrap_or("").trim().to_string();
+        headers.insert(name, value);
+    }
+    let content_length: usize = match headers.get("content-length") {
+        Some(len) => len.parse().map_err(|_| Error::InvalidEncoding)?,
+        None => 0,
+    };
+    if content_length > MAX_BODY_SIZE {
+        return Err(Error::InvalidEncoding);
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).map_err(|_| Error::Transport)?;
+    let body = String::from_utf8(body).map_err(|_| Error::InvalidEncoding)?;
+    Ok((start_line, headers, body))
+}
+
+fn write_message(stream: &mut TcpStream, start_line: &str, body: &str) -> Result<(), Error> {
+    let message = format!(
+        "{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        start_line,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(message.as_bytes())
+        .and_then(|_| stream.flush())
+        .map_err(|_| Error::Transport)
+}
+
+/// party one, talking to a Cosigner at addr, e.g. "127.0.0.1:8000"
+pub struct CosignerClient {
+    addr: String,
+    caller: Option<String>,
+    approval_timeout: Duration,
+    nonce_store: Mutex<Box<dyn NonceStore>>,
+}
+
+impl CosignerClient {
+    pub fn new(addr: &str) -> CosignerClient {
+        CosignerClient {
+            addr: addr.to_string(),
+            caller: None,
+            approval_timeout: Duration::from_secs(0),
+            nonce_store: Mutex::new(Box::new(MemoryNonceStore::new())),
+        }
+    }
+
+    pub fn with_nonce_store<S: NonceStore + 'static>(self, nonce_store: S) -> CosignerClient {
+        CosignerClient {
+            nonce_store: Mutex::new(Box::new(nonce_store)),
+            ..self
+        }
+    }
+
+    /// sends caller as the caller header, for a cosigner reached without a proxy, e.g. in tests;
+    /// a caller with a line break fails every request with InvalidEncoding
+    pub fn with_caller(self, caller: &str) -> CosignerClient {
+        CosignerClient {
+            caller: Some(caller.to_string()),
+            ..self
+        }
+    }
+
+    /// how long sign waits for a manual approval, by default it returns PendingApproval at once
+    pub fn with_approval_timeout(self, approval_timeout: Duration) -> CosignerClient {
+        CosignerClient {
+            approval_timeout,
+            ..self
+        }
+    }
+
+    fn post<Req: Serialize, Resp: DeserializeOwned>(
+        &self,
+        path: &str,
+        req: &Req,
+    ) -> Result<Resp, Error> {
+        let mut headers = vec![("Host", self.addr.as_str())];
+        if let Some(ref caller) = self.caller {
+            headers.push((CALLER_HEADER, caller.as_str()));
+        }
+        // a line break in a value would end the header and start one chosen by the value
+        if headers
+            .iter()
+            .any(|&(_, value)| value.contains('\r') || value.contains('\n'))
+        {
+            return Err(Error::InvalidEncoding);
+        }
+        let body = serde_json::to_string(req).map_err(|_| Error::InvalidEncoding)?;
+        let mut stream = TcpStream::connect(self.addr.as_str()).map_err(|_| Error::Transport)?;
+        let mut start_line = format!("POST {} HTTP/1.1", path);
+        for (name, value) in headers {
+            start_line.push_str(&format!("\r\n{}: {}", name, value));
+        }
+        write_message(&mut stream, &start_line, &body)?;
+        let (status_line, _, body) = read_message(&mut stream)?;
+        match status_line.split(' ').nth(1) {
+            Some("200") => serde_json::from_str(&body).map_err(|_| Error::InvalidEncoding),
+            // the cosigner reports why the round failed
+            Some("400") => Err(serde_json::from_str(&body).unwrap_or(Error::Transport)),
+            _ => Err(Error::Transport),
+        }
+    }
+
+    /// returns the key id the cosigner stored its share under, the share of party one and ak
+    pub fn keygen(&self) -> Result<(String, EcKeyPair, GE), Error> {
+        let session_id = SessionId::new_random();
+        let (party1_first_msg, committed) = Party1KeyGenCommitted::commit(&session_id);
+        let party2_first_msg: party_two::KeyGenFirstMsg = self.post(
+            KEYGEN_FIRST_PATH,
+            &KeyGenFirstRequest {
+                session_id: session_id.clone(),
+                party1_first_msg,
+            },
+        )?;
+        let (party1_second_msg, keys, ak) = committed.decommit(&party2_first_msg)?;
+        let resp: KeyGenSecondResponse = self.post(
+            KEYGEN_SECOND_PATH,
+            &KeyGenSecondRequest {
+                session_id,
+                party1_second_msg,
+            },
+        )?;
+        if resp.key_id != key_id(&ak) {
+            return Err(Error::protocol(Round::KeyGen, Check::PublicShare));
+        }
+        Ok((resp.key_id, keys, ak))
+    }
+
+    /// signs sighash with the key stored under key_id, keys and ak are the ones keygen returned
+    pub fn sign(
+        &self,
+        key_id: &str,
+        keys: EcKeyPair,
+        ak: GE,
+        sighash: &[u8],
+    ) -> Result<(SpendAuthMessage, Signature), Error> {
+        self.sign_with_details(key_id, keys, ak, sighash, None)
+    }
+
+    /// sign, telling the policies of the cosigner what the transaction pays
+    pub fn sign_with_details(
+        &self,
+        key_id: &str,
+        keys: EcKeyPair,
+        ak: GE,
+        sighash: &[u8],
+        details: Option<TransactionDetails>,
+    ) -> Result<(SpendAuthMessage, Signature), Error> {
+        let session_id = SessionId::new_random();
+        let session = Party1SigningSession::new(keys, ak, session_id.clone());
+        let (party1_cf_first_msg, session) = session.coin_flip_commit();
+        let party2_cf_first_msg: party_two::CoinFlipFirstMsg = self.post(
+            COIN_FLIP_FIRST_PATH,
+            &CoinFlipFirstRequest {
+                session_id: session_id.clone(),
+                key_id: key_id.to_string(),
+                party1_cf_first_msg,
+            },
+        )?;
+        let (party1_cf_second_msg, session) = session.coin_flip_reveal(&party2_cf_first_msg)?;
+        let _: () = self.post(
+            COIN_FLIP_SECOND_PATH,
+            &CoinFlipSecondRequest {
+                session_id: session_id.clone(),
+                party1_cf_second_msg,
+            },
+        )?;
+
+        let message = SpendAuthMessage::new(&session.vk(), sighash)?;
+        let (party1_eph_first_msg, session) =
+            session.eph_keygen_commit_guarded(&message, &mut **self.nonce_store.lock().unwrap())?;
+        let party2_eph_first_msg: party_two::EphKeyGenFirstMsg = self.post(
+            EPH_KEYGEN_FIRST_PATH,
+            &EphKeyGenFirstRequest {
+                session_id: session_id.clone(),
+                sighash: sighash.to_vec(),
+                party1_eph_first_msg,
+            },
+        )?;
+        let (party1_eph_second_msg, session) =
+            session.eph_keygen_decommit(&party2_eph_first_msg)?;
+        let _: party_two::EphKeyGenSecondMsg = self.post(
+            EPH_KEYGEN_SECOND_PATH,
+            &EphKeyGenSecondRequest {
+                session_id: session_id.clone(),
+                party1_eph_second_msg,
+            },
+        )?;
+
+        let (_, session) = session.local_sign_guarded(&mut **self.nonce_store.lock().unwrap())?;
+        let req = ComputeS2Request {
+            session_id: session_id.clone(),
+            details,
+        };
+        let start = Instant::now();
+        let party2_local_sig: party_two::LocalSignatureMsg = loop {
+            match self.post(COMPUTE_S2_PATH, &req) {
+                Err(Error::PolicyRejected(Rejection::PendingApproval))
+                    if start.elapsed() < self.approval_timeout =>
+                {
+                    thread::sleep(APPROVAL_POLL_INTERVAL)
+                }
+                result => break result?,
+            }
+        };
+        let sig = session.finalize(&party2_local_sig)?;
+        let _: () = self.post(
+            SIGNATURE_PATH,
+            &SignatureRequest {
+                session_id,
+                signature: sig.to_bytes().to_vec(),
+            },
+        )?;
+        Ok((message, sig))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::elliptic::curves::rand::{thread_rng, Rng};
+    use protocols::two_party::audit::{
+        verify_chain, AuditEvent, AuditLog, AuditStorage, FileAuditStorage,
+    };
+    use protocols::two_party::cosigner::{
+        CoinFlipFirstRequest, ComputeS2Request, Cosigner, CosignerClient, KeyGenFirstRequest,
+        KeyGenSecondRequest, KeyStore, CALLER_HEADER,
+    };
+    use protocols::two_party::session::{Party1KeyGenCommitted, Party1SigningSession};
+    use protocols::two_party::test::tests::serve_cosigner;
+    use protocols::two_party::{verify, SessionId};
+    use serde_json;
+    use std::env;
+    use std::fs;
+    use std::net::TcpStream;
+    use std::path::Path;
+    use std::thread;
+    use std::time::Duration;
+    use {Check, Error, Round};
+
+    fn start_cosigner(store_dir: &Path) -> String {
+        serve_cosigner(Cosigner::new(
+            KeyStore::open(store_dir).expect("failed to open store"),
+        ))
+    }
+
+    #[test]
+    fn test_cosigner() {
+        let store_dir = env::temp_dir().join(format!(
+            "paradise-city-cosigner-{}",
+            thread_rng().gen::<u64>()
+        ));
+        let client = CosignerClient::new(&start_cosigner(&store_dir));
+        let (key_id, keys, ak) = client.keygen().expect("keygen failed");
+        let (message, sig) = client
+            .sign(&key_id, keys.clone(), ak, &[3u8; 32])
+            .expect("signing failed");
+        assert!(verify(message.vk(), &message, &sig).is_ok());
+
+        // the share survives a restart of the service
+        let client = CosignerClient::new(&start_cosigner(&store_dir));
+        let (message, sig) = client
+            .sign(&key_id, keys.clone(), ak, &[4u8; 32])
+            .expect("signing failed");
+        assert!(verify(message.vk(), &message, &sig).is_ok());
+
+        // errors of the service come back as they are
+        match client.sign(&"00".repeat(32), keys.clone(), ak, &[4u8; 32]) {
+            Err(e) => assert_eq!(e, Error::InvalidKey),
+            Ok(_) => panic!("signed with an unknown key id"),
+        }
+        match client.sign("../../etc/passwd", keys.clone(), ak, &[4u8; 32]) {
+            Err(e) => assert_eq!(e, Error::InvalidKey),
+            Ok(_) => panic!("signed with a malformed key id"),
+        }
+
+        // the key is unknown to any other caller, and a caller cannot add headers of its own
+        let addr = start_cosigner(&store_dir);
+        let other_client = CosignerClient::new(&addr).with_caller("mallory");
+        match other_client.sign(&key_id, keys.clone(), ak, &[4u8; 32]) {
+            Err(e) => assert_eq!(e, Error::InvalidKey),
+            Ok(_) => panic!("signed with the key of another caller"),
+        }
+        let injecting_client = CosignerClient::new(&addr)
+            .with_caller(&format!("mallory\r\n{}: wallet", CALLER_HEADER));
+        assert_eq!(
+            injecting_client.keygen().err(),
+            Some(Error::InvalidEncoding)
+        );
+        let _ = fs::remove_dir_all(&store_dir);
+    }
+
+    #[test]
+    fn test_cosigner_out_of_order() {
+        let store_dir = env::temp_dir().join(format!(
+            "paradise-city-cosigner-{}",
+            thread_rng().gen::<u64>()
+        ));
+        let cosigner = Cosigner::new(KeyStore::open(&store_dir).expect("failed to open store"));
+        let request = ComputeS2Request {
+            session_id: SessionId::new_random(),
+            details: None,
+        };
+        let (status, body) = cosigner.handle(
+            "/sign/compute_s2",
+            "wallet",
+            &serde_json::to_string(&request).unwrap(),
+        );
+        assert_eq!(status, 400);
+        let e: Error = serde_json::from_str(&body).expect("no error in body");
+        assert_eq!(e, Error::protocol(Round::Signing, Check::MessageFormat));
+        assert_eq!(cosigner.handle("/unknown", "wallet", "{}").0, 404);
+
+        // a session only answers the caller that opened it
+        let session_id = SessionId::new_random();
+        let (party1_first_msg, committed) = Party1KeyGenCommitted::commit(&session_id);
+        let request = KeyGenFirstRequest {
+            session_id: session_id.clone(),
+            party1_first_msg,
+        };
+        let (status, body) = cosigner.handle(
+            "/keygen/first",
+            "wallet",
+            &serde_json::to_string(&request).unwrap(),
+        );
+        assert_eq!(status, 200);
+        let party2_first_msg = serde_json::from_str(&body).unwrap();
+        let (party1_second_msg, _, _) = committed.decommit(&party2_first_msg).unwrap();
+        let request = serde_json::to_string(&KeyGenSecondRequest {
+            session_id,
+            party1_second_msg,
+        })
+        .unwrap();
+        let (status, body) = cosigner.handle("/keygen/second", "mallory", &request);
+        assert_eq!(status, 400);
+        assert_eq!(
+            serde_json::from_str::<Error>(&body).unwrap(),
+            Error::protocol(Round::KeyGen, Check::MessageFormat)
+        );
+        assert_eq!(cosigner.handle("/keygen/second", "wallet", &request).0, 200);
+        let _ = fs::remove_dir_all(&store_dir);
+    }
+
+    #[test]
+    fn test_cosigner_session_limits() {
+        let store_dir = env::temp_dir().join(format!(
+            "paradise-city-cosigner-{}",
+            thread_rng().gen::<u64>()
+        ));
+        let cosigner = Cosigner::new(KeyStore::open(&store_dir).expect("failed to open store"))
+            .with_session_limits(Duration::from_millis(500), 1);
+        let keygen_first = |session_id: &SessionId| {
+            let (party1_first_msg, committed) = Party1KeyGenCommitted::commit(session_id);
+            let request = KeyGenFirstRequest {
+                session_id: session_id.clone(),
+                party1_first_msg,
+            };
+            let (status, body) = cosigner.handle(
+                "/keygen/first",
+                "wallet",
+                &serde_json::to_string(&request).unwrap(),
+            );
+            (status, body, committed)
+        };
+        let abandoned = SessionId::new_random();
+        let (status, abandoned_body, abandoned_committed) = keygen_first(&abandoned);
+        assert_eq!(status, 200);
+
+        // the cap holds until the abandoned session expires
+        let session_id = SessionId::new_random();
+        let (status, body, _) = keygen_first(&session_id);
+        assert_eq!(status, 400);
+        assert_eq!(serde_json::from_str::<Error>(&body).unwrap(), Error::Busy);
+        thread::sleep(Duration::from_millis(600));
+        let (status, body, committed) = keygen_first(&session_id);
+        assert_eq!(status, 200);
+        let keygen_second =
+            |session_id: SessionId, body: &str, committed: Party1KeyGenCommitted| {
+                let party2_first_msg = serde_json::from_str(body).unwrap();
+                let (party1_second_msg, _, _) = committed.decommit(&party2_first_msg).unwrap();
+                let request = KeyGenSecondRequest {
+                    session_id,
+                    party1_second_msg,
+                };
+                cosigner.handle(
+                    "/keygen/second",
+                    "wallet",
+                    &serde_json::to_string(&request).unwrap(),
+                )
+            };
+        assert_eq!(keygen_second(session_id, &body, committed).0, 200);
+
+        // the abandoned session is gone
+        let (status, body) = keygen_second(abandoned, &abandoned_body, abandoned_committed);
+        assert_eq!(status, 400);
+        assert_eq!(
+            serde_json::from_str::<Error>(&body).unwrap(),
+            Error::protocol(Round::KeyGen, Check::MessageFormat)
+        );
+        let _ = fs::remove_dir_all(&store_dir);
+    }
+
+    #[test]
+    fn test_cosigner_connection_limit() {
+        let store_dir = env::temp_dir().join(format!(
+            "paradise-city-cosigner-{}",
+            thread_rng().gen::<u64>()
+        ));
+        let cosigner = Cosigner::new(KeyStore::open(&store_dir).expect("failed to open store"))
+            .with_max_connections(1);
+        let addr = serve_cosigner(cosigner);
+        let client = CosignerClient::new(&addr);
+
+        // a connection that sends nothing holds the only slot
+        let idle = TcpStream::connect(addr.as_str()).expect("failed to connect");
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(client.keygen().err(), Some(Error::Transport));
+        drop(idle);
+        let keygen = (0..50)
+            .filter_map(|_| {
+                thread::sleep(Duration::from_millis(100));
+                client.keygen().ok()
+            })
+            .next();
+        assert!(keygen.is_some());
+        let _ = fs::remove_dir_all(&store_dir);
+    }
+
+    #[test]
+    fn test_cosigner_audit_log() {
+        let dir = env::temp_dir().join(format!(
+            "paradise-city-cosigner-{}",
+            thread_rng().gen::<u64>()
+        ));
+        let store = KeyStore::open(dir.join("keys")).expect("failed to open store");
+        let log_path = dir.join("audit.log");
+        let audit_log = AuditLog::open(FileAuditStorage::open(&log_path).expect("no log file"))
+            .expect("failed to open");
+        let cosigner = Cosigner::new(store).with_audit_log(audit_log);
+        let addr = serve_cosigner(cosigner);
+        let client = CosignerClient::new(&addr);
+        let (key_id, keys, ak) = client.keygen().expect("keygen failed");
+        let (message, sig) = client
+            .sign(&key_id, keys.clone(), ak, &[8u8; 32])
+            .expect("signing failed");
+        assert!(client
+            .sign("../../etc/passwd", keys.clone(), ak, &[8u8; 32])
+            .is_err());
+
+        let entries = FileAuditStorage::open(&log_path)
+            .unwrap()
+            .entries()
+            .expect("failed to read log");
+        verify_chain(&entries).expect("chain broken");
+        assert_eq!(entries.len(), 7);
+        assert_eq!(
+            entries[0].event,
+            AuditEvent::KeyGenCompleted {
+                key_id: key_id.clone(),
+                ak,
+            }
+        );
+        match entries[1].event {
+            AuditEvent::CoinFlipCommitted { key_id: ref id, .. } => assert_eq!(id, &key_id),
+            ref event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(
+            entries[2].event,
+            AuditEvent::MessageBound {
+                vk: message.vk(),
+                sighash: "08".repeat(32),
+            }
+        );
+        assert_eq!(entries[3].event, AuditEvent::NonceAgreed { R: sig.R });
+        match entries[4].event {
+            AuditEvent::LocalSignatureReleased { R, .. } => assert_eq!(R, sig.R),
+            ref event => panic!("unexpected event {:?}", event),
+        }
+        let signature_hex: String = sig
+            .to_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(
+            entries[5].event,
+            AuditEvent::SignatureProduced {
+                signature: signature_hex,
+            }
+        );
+        assert_eq!(
+            entries[6].event,
+            AuditEvent::Failed {
+                request: "/sign/coin_flip/first".to_string(),
+                error: Error::InvalidKey,
+            }
+        );
+        // one session per signature, keygen in another
+        assert!(entries[1..6]
+            .iter()
+            .all(|entry| entry.session_id == entries[1].session_id));
+        assert!(entries[0].session_id != entries[1].session_id);
+
+        // the share of the cosigner is nowhere in the log
+        let log = fs::read_to_string(&log_path).unwrap();
+        let (party2_keys, _) = KeyStore::open(dir.join("keys"))
+            .unwrap()
+            .load(&key_id, "")
+            .unwrap();
+        assert!(!log.contains(&serde_json::to_string(&party2_keys.ask).unwrap()));
+
+        // a restarted cosigner continues the chain
+        let audit_log =
+            AuditLog::open(FileAuditStorage::open(&log_path).unwrap()).expect("failed to reopen");
+        assert_eq!(audit_log.head().entries, 7);
+
+        // a session id in use is refused before anything is logged for it
+        let cosigner =
+            Cosigner::new(KeyStore::open(dir.join("keys")).unwrap()).with_audit_log(audit_log);
+        let session_id = SessionId::new_random();
+        let (party1_cf_first_msg, _) =
+            Party1SigningSession::new(keys, ak, session_id.clone()).coin_flip_commit();
+        let request = serde_json::to_string(&CoinFlipFirstRequest {
+            session_id,
+            key_id,
+            party1_cf_first_msg,
+        })
+        .unwrap();
+        assert_eq!(
+            cosigner.handle("/sign/coin_flip/first", "", &request).0,
+            200
+        );
+        assert_eq!(
+            cosigner.handle("/sign/coin_flip/first", "", &request).0,
+            400
+        );
+        let entries = FileAuditStorage::open(&log_path)
+            .unwrap()
+            .entries()
+            .unwrap();
+        assert_eq!(entries.len(), 9);
+        match entries[7].event {
+            AuditEvent::CoinFlipCommitted { .. } => (),
+            ref event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(
+            entries[8].event,
+            AuditEvent::Failed {
+                request: "/sign/coin_flip/first".to_string(),
+                error: Error::protocol(Round::CoinFlip, Check::MessageFormat),
+            }
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
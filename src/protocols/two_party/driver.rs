@@ -0,0 +1,351 @@
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Runs the side of one party in keygen or signing over a Transport.
+//! The party_one_* and party_two_* functions walk the states of the session module and do the
+//! sends and receives in the order the other side expects, so an integrator only has to set up
+//! the transport and agree on the session id (and, for signing, the sighash) out of band.
+//!
+//! Both parties record their ephemeral keys in a NonceStore, and party two releases its local
+//! signature only once its SigningPolicy accepts, see local_sign_with_policy in the session
+//! module. A request waiting for an operator is retried every APPROVAL_POLL_INTERVAL for up to
+//! APPROVAL_TTL, the transport of party one must wait that long for the local signature.
+//!
+//! The transports block. run_keygen and run_signing run both parties at once on their own
+//! threads, e.g. over the two ends of ChannelTransport::pair, and return both results. The
+//! async_driver module runs the same messages over an AsyncTransport without blocking.
+
+use super::nonce_store::NonceStore;
+use super::party_one;
+use super::party_two;
+use super::policy::{Held, SigningPolicy, TransactionDetails};
+use super::policy::{APPROVAL_POLL_INTERVAL, APPROVAL_TTL};
+use super::session::{
+    Party1KeyGenCommitted, Party1SigningSession, Party1Start, Party2KeyGenCreated,
+    Party2SigningSession, Party2Start,
+};
+use super::transport::Transport;
+use super::zip32::ExtendedPublicKey;
+use super::{EcKeyPair, KeyGenRole, SessionId, Signature, SpendAuthMessage};
+use curv::elliptic::curves::curve_jubjub::{FE, GE};
+use std::thread;
+use std::time::Instant;
+use {Error, Rejection};
+
+// frame labels, one per message of the protocol, shared with async_driver
+pub(super) const KEYGEN_FIRST: &str = "keygen_first";
+pub(super) const KEYGEN_SECOND: &str = "keygen_second";
+pub(super) const COIN_FLIP_FIRST: &str = "coin_flip_first";
+pub(super) const COIN_FLIP_SECOND: &str = "coin_flip_second";
+pub(super) const EPH_KEYGEN_FIRST: &str = "eph_keygen_first";
+pub(super) const EPH_KEYGEN_SECOND: &str = "eph_keygen_second";
+pub(super) const LOCAL_SIGNATURE: &str = "local_signature";
+
+/// returns the share of party one and ak
+pub fn party_one_keygen<T: Transport>(
+    transport: &mut T,
+    session_id: &SessionId,
+) -> Result<(EcKeyPair, GE), Error> {
+    let (first_msg, committed) = Party1KeyGenCommitted::commit(session_id);
+    transport.send(KEYGEN_FIRST, &first_msg)?;
+    let party2_first_msg = transport.receive(KEYGEN_FIRST)?;
+    let (second_msg, keys, ak) = committed.decommit(&party2_first_msg)?;
+    transport.send(KEYGEN_SECOND, &second_msg)?;
+    Ok((keys, ak))
+}
+
+/// returns the share of party two and ak
+pub fn party_two_keygen<T: Transport>(
+    transport: &mut T,
+    session_id: &SessionId,
+) -> Result<(EcKeyPair, GE), Error> {
+    let party1_first_msg = transport.receive(KEYGEN_FIRST)?;
+    let (first_msg, created) = Party2KeyGenCreated::create(&party1_first_msg, session_id);
+    transport.send(KEYGEN_FIRST, &first_msg)?;
+    let party1_second_msg = transport.receive(KEYGEN_SECOND)?;
+    created.verify(&party1_second_msg)
+}
+
+/// keygen, then a coin flip for the ExtendedPublicKey of ak. Returns the share of party one, the
+/// key and nsk
+pub fn party_one_extended_keygen<T: Transport>(
+    transport: &mut T,
+    session_id: &SessionId,
+) -> Result<(EcKeyPair, ExtendedPublicKey, FE), Error> {
+    let (keys, ak) = party_one_keygen(transport, session_id)?;
+    let (cf_first_msg, cf_witness) =
+        party_one::CoinFlipFirstMsg::commit(session_id, KeyGenRole::PartyOne);
+    transport.send(COIN_FLIP_FIRST, &cf_first_msg)?;
+    let party2_cf_first_msg = transport.receive(COIN_FLIP_FIRST)?;
+    let (cf_second_msg, coin_flip) =
+        party_one::CoinFlipSecondMsg::reveal(&party2_cf_first_msg, cf_witness, session_id)?;
+    transport.send(COIN_FLIP_SECOND, &cf_second_msg)?;
+    let (key, nsk) = ExtendedPublicKey::create(&ak, &coin_flip);
+    Ok((keys, key, nsk))
+}
+
+/// keygen, then a coin flip for the ExtendedPublicKey of ak. Returns the share of party two, the
+/// key and nsk
+pub fn party_two_extended_keygen<T: Transport>(
+    transport: &mut T,
+    session_id: &SessionId,
+) -> Result<(EcKeyPair, ExtendedPublicKey, FE), Error> {
+    let (keys, ak) = party_two_keygen(transport, session_id)?;
+    let party1_cf_first_msg = transport.receive(COIN_FLIP_FIRST)?;
+    let cf_first_msg =
+        party_two::CoinFlipFirstMsg::share(&party1_cf_first_msg, session_id, KeyGenRole::PartyTwo)?;
+    transport.send(COIN_FLIP_FIRST, &cf_first_msg)?;
+    let party1_cf_second_msg = transport.receive(COIN_FLIP_SECOND)?;
+    let coin_flip = party_two::CoinFlipResult::finalize(
+        &party1_cf_second_msg,
+        &cf_first_msg,
+        &party1_cf_first_msg,
+        session_id,
+    )?
+    .party2_alpha;
+    let (key, nsk) = ExtendedPublicKey::create(&ak, &coin_flip);
+    Ok((keys, key, nsk))
+}
+
+/// signs sighash under the vk the coin flip of this session gives
+pub fn party_one_sign<T: Transport, S: NonceStore + ?Sized>(
+    transport: &mut T,
+    session: Party1SigningSession<Party1Start>,
+    sighash: &[u8],
+    store: &mut S,
+) -> Result<(SpendAuthMessage, Signature), Error> {
+    let (cf_first_msg, session) = session.coin_flip_commit();
+    transport.send(COIN_FLIP_FIRST, &cf_first_msg)?;
+    let party2_cf_first_msg = transport.receive(COIN_FLIP_FIRST)?;
+    let (cf_second_msg, session) = session.coin_flip_reveal(&party2_cf_first_msg)?;
+    transport.send(COIN_FLIP_SECOND, &cf_second_msg)?;
+
+    let message = SpendAuthMessage::new(&session.vk(), sighash)?;
+    let (eph_first_msg, session) = session.eph_keygen_commit_guarded(&message, store)?;
+    transport.send(EPH_KEYGEN_FIRST, &eph_first_msg)?;
+    let party2_eph_first_msg = transport.receive(EPH_KEYGEN_FIRST)?;
+    let (eph_second_msg, session) = session.eph_keygen_decommit(&party2_eph_first_msg)?;
+    transport.send(EPH_KEYGEN_SECOND, &eph_second_msg)?;
+
+    let (local_sig, session) = session.local_sign_guarded(store)?;
+    transport.send(LOCAL_SIGNATURE, &local_sig)?;
+    let party2_local_sig = transport.receive(LOCAL_SIGNATURE)?;
+    let sig = session.finalize(&party2_local_sig)?;
+    Ok((message, sig))
+}
+
+/// signs sighash under the vk the coin flip of this session gives, once policy accepts the
+/// signing context of caller and details
+pub fn party_two_sign<T: Transport, P: SigningPolicy + ?Sized, S: NonceStore + ?Sized>(
+    transport: &mut T,
+    session: Party2SigningSession<Party2Start>,
+    sighash: &[u8],
+    policy: &P,
+    store: &mut S,
+    caller: &str,
+    details: Option<TransactionDetails>,
+) -> Result<(SpendAuthMessage, Signature), Error> {
+    let party1_cf_first_msg = transport.receive(COIN_FLIP_FIRST)?;
+    let (cf_first_msg, session) = session.coin_flip_share(&party1_cf_first_msg)?;
+    transport.send(COIN_FLIP_FIRST, &cf_first_msg)?;
+    let party1_cf_second_msg = transport.receive(COIN_FLIP_SECOND)?;
+    let session = session.coin_flip_finalize(&party1_cf_second_msg)?;
+
+    let message = SpendAuthMessage::new(&session.vk(), sighash)?;
+    let party1_eph_first_msg = transport.receive(EPH_KEYGEN_FIRST)?;
+    let (eph_first_msg, session) =
+        session.eph_keygen_create_guarded(&party1_eph_first_msg, &message, store)?;
+    transport.send(EPH_KEYGEN_FIRST, &eph_first_msg)?;
+    let party1_eph_second_msg = transport.receive(EPH_KEYGEN_SECOND)?;
+    let (_, session) = session.eph_keygen_verify(&party1_eph_second_msg)?;
+
+    let start = Instant::now();
+    let mut session = session;
+    let (local_sig, session) = loop {
+        match session.local_sign_with_policy(policy, store, caller, details.clone()) {
+            Err(Held {
+                error: Error::PolicyRejected(Rejection::PendingApproval),
+                session: Some(held),
+            }) if start.elapsed() < APPROVAL_TTL => {
+                session = held;
+                thread::sleep(APPROVAL_POLL_INTERVAL);
+            }
+            result => break result?,
+        }
+    };
+    transport.send(LOCAL_SIGNATURE, &local_sig)?;
+    let party1_local_sig = transport.receive(LOCAL_SIGNATURE)?;
+    let sig = session.finalize(&party1_local_sig)?;
+    Ok((message, sig))
+}
+
+fn join<R>(handle: thread::JoinHandle<Result<R, Error>>) -> Result<R, Error> {
+    handle.join().unwrap_or(Err(Error::Transport))
+}
+
+/// runs keygen for both parties, returns (share of party one, share of party two, ak)
+pub fn run_keygen<T1, T2>(
+    mut party1_transport: T1,
+    mut party2_transport: T2,
+    session_id: &SessionId,
+) -> Result<(EcKeyPair, EcKeyPair, GE), Error>
+where
+    T1: Transport + Send + 'static,
+    T2: Transport + Send + 'static,
+{
+    let party1_session_id = session_id.clone();
+    let party2_session_id = session_id.clone();
+    let party1 = thread::spawn(move || party_one_keygen(&mut party1_transport, &party1_session_id));
+    let party2 = thread::spawn(move || party_two_keygen(&mut party2_transport, &party2_session_id));
+    let (party1_keys, party1_ak) = join(party1)?;
+    let (party2_keys, party2_ak) = join(party2)?;
+    if party1_ak != party2_ak {
+        return Err(Error::InvalidKey);
+    }
+    Ok((party1_keys, party2_keys, party1_ak))
+}
+
+/// runs signing for both parties, returns the message and the signature both of them computed.
+/// Each party records its ephemeral key in a clone of store, e.g. an Arc<Mutex<FileNonceStore>>.
+/// Party two checks policy with an empty caller and no details
+pub fn run_signing<T1, T2, S, P>(
+    mut party1_transport: T1,
+    mut party2_transport: T2,
+    party1_session: Party1SigningSession<Party1Start>,
+    party2_session: Party2SigningSession<Party2Start>,
+    sighash: &[u8],
+    store: S,
+    policy: P,
+) -> Result<(SpendAuthMessage, Signature), Error>
+where
+    T1: Transport + Send + 'static,
+    T2: Transport + Send + 'static,
+    S: NonceStore + Clone + 'static,
+    P: SigningPolicy + 'static,
+{
+    let party1_sighash = sighash.to_vec();
+    let party2_sighash = sighash.to_vec();
+    let mut party1_store = store.clone();
+    let mut party2_store = store;
+    let party1 = thread::spawn(move || {
+        party_one_sign(
+            &mut party1_transport,
+            party1_session,
+            &party1_sighash,
+            &mut party1_store,
+        )
+    });
+    let party2 = thread::spawn(move || {
+        party_two_sign(
+            &mut party2_transport,
+            party2_session,
+            &party2_sighash,
+            &policy,
+            &mut party2_store,
+            "",
+            None,
+        )
+    });
+    let (message, party1_sig) = join(party1)?;
+    let (_, party2_sig) = join(party2)?;
+    if party1_sig != party2_sig {
+        return Err(Error::InvalidSig);
+    }
+    Ok((message, party1_sig))
+}
+
+#[cfg(test)]
+mod tests {
+    use protocols::two_party::driver;
+    use protocols::two_party::nonce_store::MemoryNonceStore;
+    use protocols::two_party::policy::Policies;
+    use protocols::two_party::session::{Party1SigningSession, Party2SigningSession};
+    use protocols::two_party::transport::{ChannelTransport, TcpTransport};
+    use protocols::two_party::{verify, SessionId};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_driver_channel() {
+        let session_id = SessionId::new_random();
+        let (party1_transport, party2_transport) = ChannelTransport::pair();
+        let (party1_keys, party2_keys, ak) =
+            driver::run_keygen(party1_transport, party2_transport, &session_id)
+                .expect("keygen failed");
+
+        let session_id = SessionId::new_random();
+        let (party1_transport, party2_transport) = ChannelTransport::pair();
+        let (message, sig) = driver::run_signing(
+            party1_transport,
+            party2_transport,
+            Party1SigningSession::new(party1_keys, ak, session_id.clone()),
+            Party2SigningSession::new(party2_keys, ak, session_id),
+            &[7u8; 32],
+            Arc::new(Mutex::new(MemoryNonceStore::new())),
+            Policies::new(),
+        )
+        .expect("signing failed");
+        assert!(verify(message.vk(), &message, &sig).is_ok());
+    }
+
+    #[test]
+    fn test_driver_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind");
+        let addr = listener.local_addr().expect("no local address");
+        let keygen_session_id = SessionId::new_random();
+        let sign_session_id = SessionId::new_random();
+        let sighash = [1u8; 32];
+
+        // party two keeps the connection for keygen and signing
+        let (party2_keygen_session_id, party2_sign_session_id) =
+            (keygen_session_id.clone(), sign_session_id.clone());
+        let party2 = thread::spawn(move || {
+            let mut transport = TcpTransport::accept(&listener)?;
+            let (keys, ak) = driver::party_two_keygen(&mut transport, &party2_keygen_session_id)?;
+            let session = Party2SigningSession::new(keys, ak, party2_sign_session_id);
+            driver::party_two_sign(
+                &mut transport,
+                session,
+                &sighash,
+                &Policies::new(),
+                &mut MemoryNonceStore::new(),
+                "wallet",
+                None,
+            )
+        });
+
+        let mut transport = TcpTransport::connect(addr).expect("failed to connect");
+        transport
+            .set_timeout(Some(Duration::from_secs(60)))
+            .expect("failed to set timeout");
+        let (keys, ak) =
+            driver::party_one_keygen(&mut transport, &keygen_session_id).expect("keygen failed");
+        let session = Party1SigningSession::new(keys, ak, sign_session_id);
+        let (message, sig) = driver::party_one_sign(
+            &mut transport,
+            session,
+            &sighash,
+            &mut MemoryNonceStore::new(),
+        )
+        .expect("signing failed");
+        let (party2_message, party2_sig) = party2.join().unwrap().expect("signing failed");
+        assert_eq!(message, party2_message);
+        assert_eq!(sig, party2_sig);
+        assert!(verify(message.vk(), &message, &sig).is_ok());
+    }
+}
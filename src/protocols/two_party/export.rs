@@ -0,0 +1,376 @@
+#![allow(non_snake_case)]
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Consensual export of the full ask.
+//! Both parties sign off on the same ExportRequest by sending their share to the recipient
+//! together with a DLogProof bound to the request. The recipient checks both proofs, that each
+//! share matches its public share, and that the public shares add up to ak before returning
+//! ask. The ExportRecord keeps the request, the public shares and the proofs, but no secret, so
+//! an auditor can check later that both parties authorized this export.
+//!
+//! The proofs alone only show knowledge of the shares, which the recipient learns. So each party
+//! also signs the request and its public share with its long-term IdentityKey before the share
+//! leaves it, and the record keeps these signatures. The recipient and the auditor check them
+//! against the identity public keys they got out of band: holding ask is not enough to make a
+//! record for another request.
+//!
+//! An ExportShareMsg carries a secret share and must only go to the recipient over an
+//! authenticated, encrypted channel.
+
+use super::{EcKeyPair, SessionId};
+use super::{EXPORT_ROUND, PARTY_ONE, PARTY_TWO};
+use curv::cryptographic_primitives::hashing::transcript::Transcript;
+use curv::cryptographic_primitives::proofs::sigma_dlog::DLogProof;
+use curv::cryptographic_primitives::proofs::sigma_dlog::ProveDLog;
+use curv::elliptic::curves::curve_jubjub::FE;
+use curv::elliptic::curves::curve_jubjub::GE;
+use curv::elliptic::curves::traits::{ECPoint, ECScalar};
+use {Check, Error, Round};
+
+/// Long-term key of a party, signs the exports it authorizes. The public key is given to the
+/// recipient and to auditors out of band.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IdentityKey {
+    secret: FE,
+    pub public: GE,
+}
+
+impl IdentityKey {
+    pub fn new_random() -> IdentityKey {
+        let base: GE = ECPoint::generator();
+        let secret: FE = ECScalar::new_random();
+        IdentityKey {
+            public: base * &secret,
+            secret,
+        }
+    }
+}
+
+/// what both parties authorize
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExportRequest {
+    pub session_id: SessionId,
+    pub ak: GE,
+    pub recipient: String,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportShareMsg {
+    pub party: u8,
+    pub secret_share: FE,
+    pub d_log_proof: DLogProof,
+    /// by the identity key of party, over the request and the public share
+    pub identity_sig: DLogProof,
+}
+
+/// auditable trace of an export, contains no secret
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportRecord {
+    pub request: ExportRequest,
+    pub party1_d_log_proof: DLogProof,
+    pub party2_d_log_proof: DLogProof,
+    pub party1_identity_sig: DLogProof,
+    pub party2_identity_sig: DLogProof,
+}
+
+impl ExportRequest {
+    // binds the proofs to every field of the request
+    fn transcript(&self, party: u8) -> Transcript {
+        let mut transcript = self.session_id.transcript(party, EXPORT_ROUND);
+        transcript.append_point(b"ak", &self.ak);
+        transcript.append_message(b"recipient", self.recipient.as_bytes());
+        transcript.append_message(b"reason", self.reason.as_bytes());
+        transcript
+    }
+
+    // what the identity key of party signs: the request and the public share it reveals
+    fn identity_transcript(&self, party: u8, public_share: &GE) -> Transcript {
+        let mut transcript = self.transcript(party);
+        transcript.append_message(b"dom-sep", b"identity");
+        transcript.append_point(b"public_share", public_share);
+        transcript
+    }
+}
+
+impl ExportShareMsg {
+    /// party must be PARTY_ONE or PARTY_TWO, ak is the joint key this party holds a share of and
+    /// identity the long-term key of this party
+    pub fn authorize(
+        request: &ExportRequest,
+        keys: &EcKeyPair,
+        ak: &GE,
+        party: u8,
+        identity: &IdentityKey,
+    ) -> Result<ExportShareMsg, Error> {
+        if party != PARTY_ONE && party != PARTY_TWO {
+            return Err(Error::protocol(Round::Export, Check::MessageFormat));
+        }
+        // the share must still match the public share this party contributed at keygen
+        let base: GE = ECPoint::generator();
+        if &request.ak != ak || base * &keys.ask != keys.ak {
+            return Err(Error::protocol(Round::Export, Check::PublicShare));
+        }
+        let d_log_proof = DLogProof::prove(&keys.ask, &request.transcript(party));
+        let identity_sig = DLogProof::prove(
+            &identity.secret,
+            &request.identity_transcript(party, &keys.ak),
+        );
+        Ok(ExportShareMsg {
+            party,
+            secret_share: keys.ask,
+            d_log_proof,
+            identity_sig,
+        })
+    }
+}
+
+fn verify_share_proof(
+    request: &ExportRequest,
+    d_log_proof: &DLogProof,
+    party: u8,
+) -> Result<(), Error> {
+    DLogProof::verify(d_log_proof, &request.transcript(party))
+        .map_err(|_| Error::protocol(Round::Export, Check::DLogProof))
+}
+
+fn verify_identity_sig(
+    request: &ExportRequest,
+    identity_sig: &DLogProof,
+    identity: &GE,
+    public_share: &GE,
+    party: u8,
+) -> Result<(), Error> {
+    DLogProof::verify(
+        identity_sig,
+        &request.identity_transcript(party, public_share),
+    )
+    .map_err(|_| Error::protocol(Round::Export, Check::Identity))?;
+    match identity_sig.pk == *identity {
+        true => Ok(()),
+        false => Err(Error::protocol(Round::Export, Check::Identity)),
+    }
+}
+
+impl ExportRecord {
+    /// recipient side: checks both shares against the identities of the parties and returns ask
+    /// together with the record
+    pub fn reconstruct(
+        request: &ExportRequest,
+        party1_share_msg: &ExportShareMsg,
+        party2_share_msg: &ExportShareMsg,
+        party1_identity: &GE,
+        party2_identity: &GE,
+    ) -> Result<(FE, ExportRecord), Error> {
+        if party1_share_msg.party != PARTY_ONE || party2_share_msg.party != PARTY_TWO {
+            return Err(Error::protocol(Round::Export, Check::MessageFormat));
+        }
+        let base: GE = ECPoint::generator();
+        for share_msg in &[party1_share_msg, party2_share_msg] {
+            if base * &share_msg.secret_share != share_msg.d_log_proof.pk {
+                return Err(Error::protocol(Round::Export, Check::PublicShare));
+            }
+        }
+        let record = ExportRecord {
+            request: request.clone(),
+            party1_d_log_proof: party1_share_msg.d_log_proof.clone(),
+            party2_d_log_proof: party2_share_msg.d_log_proof.clone(),
+            party1_identity_sig: party1_share_msg.identity_sig.clone(),
+            party2_identity_sig: party2_share_msg.identity_sig.clone(),
+        };
+        record.verify(party1_identity, party2_identity)?;
+
+        let ask = party1_share_msg.secret_share + party2_share_msg.secret_share;
+        if base * &ask != request.ak {
+            return Err(Error::protocol(Round::Export, Check::PublicShare));
+        }
+        Ok((ask, record))
+    }
+
+    /// auditor side: both parties proved knowledge of shares of ak for this request, and signed
+    /// it with the identity keys the auditor expects
+    pub fn verify(&self, party1_identity: &GE, party2_identity: &GE) -> Result<(), Error> {
+        verify_share_proof(&self.request, &self.party1_d_log_proof, PARTY_ONE)?;
+        verify_share_proof(&self.request, &self.party2_d_log_proof, PARTY_TWO)?;
+        verify_identity_sig(
+            &self.request,
+            &self.party1_identity_sig,
+            party1_identity,
+            &self.party1_d_log_proof.pk,
+            PARTY_ONE,
+        )?;
+        verify_identity_sig(
+            &self.request,
+            &self.party2_identity_sig,
+            party2_identity,
+            &self.party2_d_log_proof.pk,
+            PARTY_TWO,
+        )?;
+        if self.party1_d_log_proof.pk + self.party2_d_log_proof.pk != self.request.ak {
+            return Err(Error::protocol(Round::Export, Check::PublicShare));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::elliptic::curves::curve_jubjub::GE;
+    use curv::elliptic::curves::traits::ECPoint;
+    use protocols::two_party::export::{ExportRecord, ExportRequest, ExportShareMsg, IdentityKey};
+    use protocols::two_party::test::tests::two_party_keygen;
+    use protocols::two_party::{EcKeyPair, SessionId, PARTY_ONE, PARTY_TWO};
+    use {Check, Error, Round};
+
+    #[test]
+    fn test_2p_export() {
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let party1_identity = IdentityKey::new_random();
+        let party2_identity = IdentityKey::new_random();
+        let request = ExportRequest {
+            session_id: SessionId::new_random(),
+            ak: public_key,
+            recipient: "escrow".to_string(),
+            reason: "migration".to_string(),
+        };
+        let party1_share_message = ExportShareMsg::authorize(
+            &request,
+            &party1_keys,
+            &public_key,
+            PARTY_ONE,
+            &party1_identity,
+        )
+        .expect("bad request");
+        let party2_share_message = ExportShareMsg::authorize(
+            &request,
+            &party2_keys,
+            &public_key,
+            PARTY_TWO,
+            &party2_identity,
+        )
+        .expect("bad request");
+        let (ask, record) = ExportRecord::reconstruct(
+            &request,
+            &party1_share_message,
+            &party2_share_message,
+            &party1_identity.public,
+            &party2_identity.public,
+        )
+        .expect("failed to verify shares");
+        assert_eq!(GE::generator() * &ask, public_key);
+        assert!(record
+            .verify(&party1_identity.public, &party2_identity.public)
+            .is_ok());
+        // the identities are not interchangeable
+        assert!(record
+            .verify(&party2_identity.public, &party1_identity.public)
+            .is_err());
+
+        // a proof made for another request does not authorize this one
+        let mut other_request = request.clone();
+        other_request.recipient = "someone else".to_string();
+        let other_share_message = ExportShareMsg::authorize(
+            &other_request,
+            &party2_keys,
+            &public_key,
+            PARTY_TWO,
+            &party2_identity,
+        )
+        .expect("bad request");
+        match ExportRecord::reconstruct(
+            &request,
+            &party1_share_message,
+            &other_share_message,
+            &party1_identity.public,
+            &party2_identity.public,
+        ) {
+            Err(e) => assert_eq!(e, Error::protocol(Round::Export, Check::DLogProof)),
+            Ok(_) => panic!("export without authorization accepted"),
+        }
+    }
+
+    #[test]
+    fn test_2p_export_forged_record() {
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let party1_identity = IdentityKey::new_random();
+        let party2_identity = IdentityKey::new_random();
+        let request = ExportRequest {
+            session_id: SessionId::new_random(),
+            ak: public_key,
+            recipient: "escrow".to_string(),
+            reason: "migration".to_string(),
+        };
+        let party1_share_message = ExportShareMsg::authorize(
+            &request,
+            &party1_keys,
+            &public_key,
+            PARTY_ONE,
+            &party1_identity,
+        )
+        .expect("bad request");
+        let party2_share_message = ExportShareMsg::authorize(
+            &request,
+            &party2_keys,
+            &public_key,
+            PARTY_TWO,
+            &party2_identity,
+        )
+        .expect("bad request");
+
+        // the recipient holds both shares and makes up a record for a request nobody authorized
+        let mut forged_request = request.clone();
+        forged_request.recipient = "the recipient itself".to_string();
+        let revealed_keys = |share_message: &ExportShareMsg| EcKeyPair {
+            ak: GE::generator() * &share_message.secret_share,
+            ask: share_message.secret_share,
+        };
+        let own_identity = IdentityKey::new_random();
+        let forge = |share_message: &ExportShareMsg, party: u8, identity: &IdentityKey| {
+            ExportShareMsg::authorize(
+                &forged_request,
+                &revealed_keys(share_message),
+                &public_key,
+                party,
+                identity,
+            )
+            .expect("bad request")
+        };
+        let party1_forged = forge(&party1_share_message, PARTY_ONE, &own_identity);
+        let party2_forged = forge(&party2_share_message, PARTY_TWO, &own_identity);
+        let forged_record = ExportRecord {
+            request: forged_request.clone(),
+            party1_d_log_proof: party1_forged.d_log_proof.clone(),
+            party2_d_log_proof: party2_forged.d_log_proof.clone(),
+            party1_identity_sig: party1_forged.identity_sig,
+            party2_identity_sig: party2_forged.identity_sig,
+        };
+        assert_eq!(
+            forged_record.verify(&party1_identity.public, &party2_identity.public),
+            Err(Error::protocol(Round::Export, Check::Identity))
+        );
+        // nor do the signatures of the parties carry over to the forged request
+        let replayed_record = ExportRecord {
+            party1_identity_sig: party1_share_message.identity_sig.clone(),
+            party2_identity_sig: party2_share_message.identity_sig.clone(),
+            ..forged_record
+        };
+        assert_eq!(
+            replayed_record.verify(&party1_identity.public, &party2_identity.public),
+            Err(Error::protocol(Round::Export, Check::Identity))
+        );
+    }
+}
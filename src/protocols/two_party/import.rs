@@ -0,0 +1,200 @@
+#![allow(non_snake_case)]
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Import of an existing single key ask into the two party scheme.
+//! The holder of ask (party one) splits it into ask1 < q/3 and ask2 = ask - ask1, hands ask2 to
+//! party two, and both run the regular keygen with the fixed shares. Each side checks that
+//! compute_ak gives back the original ak, so the imported key keeps its address.
+//!
+//! The ImportShareMsg carries a secret share and must go over an authenticated, encrypted
+//! channel. Party one should erase ask once both sides have finished.
+
+use super::party_one;
+use super::party_two;
+use super::{compute_ak, EcKeyPair, SessionId};
+use curv::arithmetic::big_gmp::BigInt;
+use curv::elliptic::curves::curve_jubjub::FE;
+use curv::elliptic::curves::curve_jubjub::GE;
+use curv::elliptic::curves::traits::{ECPoint, ECScalar};
+use {Check, Error, Round};
+
+/// share of party two, sent by party one
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImportShareMsg {
+    pub ak: GE,
+    pub secret_share: FE,
+}
+
+/// splits ask into the share of party one and the message for party two
+pub fn split_ask(ask: &FE) -> (FE, ImportShareMsg) {
+    let base: GE = ECPoint::generator();
+    let secret_share: FE = ECScalar::new_random();
+    //in Lindell's protocol range proof works only for x1<q/3
+    let party1_share: FE = ECScalar::from(&secret_share.to_big_int().div_floor(&BigInt::from(3)));
+    let party2_share = ask.sub(&party1_share.get_element());
+    (
+        party1_share,
+        ImportShareMsg {
+            ak: base * ask,
+            secret_share: party2_share,
+        },
+    )
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1ImportCommitted {
+    ak: GE,
+    session_id: SessionId,
+    comm_witness: party_one::CommWitness,
+    keys: EcKeyPair,
+}
+
+impl Party1ImportCommitted {
+    pub fn commit(
+        secret_share: FE,
+        ak: &GE,
+        session_id: &SessionId,
+    ) -> Result<(party_one::KeyGenFirstMsg, Party1ImportCommitted), Error> {
+        let (first_msg, comm_witness, keys) =
+            party_one::KeyGenFirstMsg::create_commitments_with_fixed_secret_share(
+                secret_share,
+                session_id,
+            )?;
+        Ok((
+            first_msg,
+            Party1ImportCommitted {
+                ak: *ak,
+                session_id: session_id.clone(),
+                comm_witness,
+                keys,
+            },
+        ))
+    }
+
+    /// returns the share of party one once the joint key is checked against the imported ak
+    pub fn decommit(
+        self,
+        party2_first_msg: &party_two::KeyGenFirstMsg,
+    ) -> Result<(party_one::KeyGenSecondMsg, EcKeyPair), Error> {
+        let second_msg = party_one::KeyGenSecondMsg::verify_and_decommit(
+            self.comm_witness,
+            party2_first_msg,
+            &self.session_id,
+        )?;
+        if compute_ak(&self.keys, &party2_first_msg.public_share) != self.ak {
+            return Err(Error::protocol(Round::KeyGen, Check::PublicShare));
+        }
+        Ok((second_msg, self.keys))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2ImportCreated {
+    ak: GE,
+    session_id: SessionId,
+    keys: EcKeyPair,
+}
+
+impl Party2ImportCreated {
+    pub fn create(
+        share_msg: &ImportShareMsg,
+        session_id: &SessionId,
+    ) -> (party_two::KeyGenFirstMsg, Party2ImportCreated) {
+        let (first_msg, keys) = party_two::KeyGenFirstMsg::create_with_fixed_secret_share(
+            share_msg.secret_share,
+            session_id,
+        );
+        (
+            first_msg,
+            Party2ImportCreated {
+                ak: share_msg.ak,
+                session_id: session_id.clone(),
+                keys,
+            },
+        )
+    }
+
+    /// returns the share of party two once the joint key is checked against the imported ak
+    pub fn verify(
+        self,
+        party1_first_msg: &party_one::KeyGenFirstMsg,
+        party1_second_msg: &party_one::KeyGenSecondMsg,
+    ) -> Result<EcKeyPair, Error> {
+        party_two::KeyGenSecondMsg::verify_commitments_and_dlog_proof(
+            party1_first_msg,
+            party1_second_msg,
+            &self.session_id,
+        )?;
+        if compute_ak(&self.keys, &party1_second_msg.comm_witness.public_share) != self.ak {
+            return Err(Error::protocol(Round::KeyGen, Check::PublicShare));
+        }
+        Ok(self.keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::elliptic::curves::curve_jubjub::{FE, GE};
+    use curv::elliptic::curves::traits::{ECPoint, ECScalar};
+    use protocols::two_party::compute_ak;
+    use protocols::two_party::import::{split_ask, Party1ImportCommitted, Party2ImportCreated};
+    use protocols::two_party::test::tests::two_party_sign;
+    use protocols::two_party::SessionId;
+    use {Check, Error, Round};
+
+    #[test]
+    fn test_2p_import() {
+        let ask: FE = ECScalar::new_random();
+        let ak = GE::generator() * &ask;
+        let session_id = SessionId::new_random();
+
+        let (party1_share, import_share_message) = split_ask(&ask);
+        assert_eq!(import_share_message.ak, ak);
+        let (party1_first_message, party1_committed) =
+            Party1ImportCommitted::commit(party1_share, &ak, &session_id)
+                .expect("share out of range");
+        let (party2_first_message, party2_created) =
+            Party2ImportCreated::create(&import_share_message, &session_id);
+        let (party1_second_message, party1_keys) = party1_committed
+            .decommit(&party2_first_message)
+            .expect("failed to verify and decommit");
+        let party2_keys = party2_created
+            .verify(&party1_first_message, &party1_second_message)
+            .expect("failed to verify commitments and DLog proof");
+
+        assert_eq!(compute_ak(&party1_keys, &party2_keys.ak), ak);
+        assert!(two_party_sign(party1_keys, party2_keys, ak).is_ok());
+    }
+
+    #[test]
+    fn test_2p_import_wrong_share() {
+        let ask: FE = ECScalar::new_random();
+        let ak = GE::generator() * &ask;
+        let session_id = SessionId::new_random();
+
+        let (party1_share, mut import_share_message) = split_ask(&ask);
+        import_share_message.secret_share = ECScalar::new_random();
+        let (_, party1_committed) = Party1ImportCommitted::commit(party1_share, &ak, &session_id)
+            .expect("share out of range");
+        let (party2_first_message, _) =
+            Party2ImportCreated::create(&import_share_message, &session_id);
+        match party1_committed.decommit(&party2_first_message) {
+            Err(e) => assert_eq!(e, Error::protocol(Round::KeyGen, Check::PublicShare)),
+            Ok(_) => panic!("import with a wrong share accepted"),
+        }
+    }
+}
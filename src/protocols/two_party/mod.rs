@@ -16,16 +16,141 @@
 */
 
 use Error::{self, InvalidSig};
+use {Check, Round};
+pub mod async_driver;
+pub mod audit;
+pub mod batch;
+pub mod batch_verify;
+pub mod cosigner;
+pub mod driver;
+pub mod export;
+pub mod import;
+pub mod nonce_store;
 pub mod party_one;
 pub mod party_two;
+pub mod policy;
+pub mod presign;
+pub mod redjubjub;
+pub mod refresh;
+pub mod session;
+pub mod share_file;
+pub mod state;
 pub mod test;
+pub mod transport;
+pub mod viewing_key;
+pub mod zip32;
 
+use blake2_rfc::blake2b::Blake2b;
 use curv::arithmetic::big_gmp::BigInt;
-use curv::arithmetic::traits::Converter;
-use curv::cryptographic_primitives::hashing::blake2b512::Blake;
+use curv::arithmetic::traits::{Converter, Samplable};
+use curv::cryptographic_primitives::hashing::transcript::Transcript;
 use curv::elliptic::curves::curve_jubjub::FE;
 use curv::elliptic::curves::curve_jubjub::GE;
 use curv::elliptic::curves::traits::{ECPoint, ECScalar};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+pub const PARTY_ONE: u8 = 1;
+pub const PARTY_TWO: u8 = 2;
+
+// round labels absorbed into proofs and commitments together with the session id
+const KEYGEN_ROUND: &[u8] = b"keygen";
+const COIN_FLIP_ROUND: &[u8] = b"coin_flip";
+const EPH_KEYGEN_ROUND: &[u8] = b"eph_keygen";
+const REFRESH_ROUND: &[u8] = b"refresh";
+const REFRESH_CONFIRM_ROUND: &[u8] = b"refresh_confirm";
+const REFRESH_SWITCH_ROUND: &[u8] = b"refresh_switch";
+const EXPORT_ROUND: &[u8] = b"export";
+const PRESIGN_ROUND: &[u8] = b"presign";
+
+const SESSION_ID_PERSONA: &[u8] = b"ParadiseCity_Sid";
+
+pub const SIGHASH_SIZE: usize = 32;
+pub const VK_SIZE: usize = 32;
+pub const SIGNATURE_SIZE: usize = 64;
+
+/// Identifies a single run of keygen or signing. Both parties must agree on it before the first
+/// message, every proof and commitment of the run is bound to it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SessionId(pub Vec<u8>);
+
+impl SessionId {
+    pub fn new_random() -> SessionId {
+        let mut bytes = BigInt::to_vec(&BigInt::sample(256));
+        // sampled value may have leading zero bytes, keep ids at a fixed 32 bytes
+        let mut id = vec![0u8; 32 - bytes.len()];
+        id.append(&mut bytes);
+        SessionId(id)
+    }
+
+    /// id of the index-th spend in a batch run under this session id,
+    /// H("child" || len(id) || id || index). Hashed, so no child id is another session id or the
+    /// child of another one
+    pub fn child(&self, index: u32) -> SessionId {
+        let mut digest = Blake2b::with_params(32, &[], &[], SESSION_ID_PERSONA);
+        digest.update(b"child");
+        digest.update(&(self.0.len() as u64).to_le_bytes());
+        digest.update(&self.0);
+        digest.update(&index.to_le_bytes());
+        SessionId(digest.finalize().as_bytes().to_vec())
+    }
+
+    /// transcript of (session id, party, round) the proofs and commitments of a round are
+    /// drawn from
+    pub fn transcript(&self, party: u8, round: &[u8]) -> Transcript {
+        let mut transcript = Transcript::new(b"paradise-city two party");
+        transcript.append_message(b"session_id", &self.0);
+        transcript.append_message(b"party", &[party]);
+        transcript.append_message(b"round", round);
+        transcript
+    }
+}
+
+/// Which share a party got at keygen. The share of party one is the q/3 bounded one and is the one
+/// alpha gets added to when signing, independent of which party starts a signing session.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyGenRole {
+    PartyOne,
+    PartyTwo,
+}
+
+impl KeyGenRole {
+    pub fn party(&self) -> u8 {
+        match *self {
+            KeyGenRole::PartyOne => PARTY_ONE,
+            KeyGenRole::PartyTwo => PARTY_TWO,
+        }
+    }
+
+    pub fn counterparty(&self) -> KeyGenRole {
+        match *self {
+            KeyGenRole::PartyOne => KeyGenRole::PartyTwo,
+            KeyGenRole::PartyTwo => KeyGenRole::PartyOne,
+        }
+    }
+
+    // alpha is added exactly once only if the counterparty has the other share
+    fn check_counterparty(&self, counter_role: KeyGenRole) -> Result<(), Error> {
+        match counter_role == self.counterparty() {
+            true => Ok(()),
+            false => Err(Error::protocol(Round::CoinFlip, Check::KeyGenRole)),
+        }
+    }
+}
+
+/// How a party derives the secret of its ephemeral share, chosen per signing session.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NonceMode {
+    /// hash of the secret share, session id, message and fresh randomness: a weak RNG alone does
+    /// not leak the share
+    Hedged,
+    /// the same hash without randomness, for reproducible test vectors and only built for tests:
+    /// two runs over the same message with different counter R_i reuse r_i under two challenges,
+    /// which gives away the share
+    #[cfg(test)]
+    Deterministic,
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EcKeyPair {
@@ -33,14 +158,54 @@ pub struct EcKeyPair {
     pub ask: FE,
 }
 
+/// The message a spend authorization signature is made over: vk || sighash. It can only be built
+/// from a vk and a 32 byte sighash, so the encoding is always 64 bytes and starts with the vk the
+/// signature is for.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpendAuthMessage {
+    vk: GE,
+    sighash: [u8; SIGHASH_SIZE],
+}
+
+impl SpendAuthMessage {
+    pub fn new(vk: &GE, sighash: &[u8]) -> Result<SpendAuthMessage, Error> {
+        if sighash.len() != SIGHASH_SIZE {
+            return Err(Error::protocol(Round::Signing, Check::MessageFormat));
+        }
+        let mut bytes = [0u8; SIGHASH_SIZE];
+        bytes.copy_from_slice(sighash);
+        Ok(SpendAuthMessage {
+            vk: *vk,
+            sighash: bytes,
+        })
+    }
+
+    pub fn vk(&self) -> GE {
+        self.vk
+    }
+
+    pub fn sighash(&self) -> &[u8; SIGHASH_SIZE] {
+        &self.sighash
+    }
+
+    /// vk || sighash, 64 bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vk_to_bytes(&self.vk).to_vec();
+        bytes.extend_from_slice(&self.sighash);
+        bytes
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct EphemeralKey {
-    pub message: BigInt,
+    pub message: SpendAuthMessage,
     pub vk: GE,
     pub R: GE,
 }
 
-#[derive(Clone, Debug)]
+// not Clone: an ephemeral key must be moved into exactly one local signature. Serializable so an
+// in-flight session can be stored, a stored copy must be deleted once it is loaded again
+#[derive(Debug, Serialize, Deserialize)]
 pub struct EphEcKeyPair {
     pub R_i: GE,
     r_i: FE,
@@ -52,6 +217,102 @@ pub struct Signature {
     pub R: GE,
 }
 
+impl Signature {
+    /// RedJubjub encoding as in a Sapling spend description: compressed R || s little endian
+    pub fn to_bytes(&self) -> [u8; SIGNATURE_SIZE] {
+        let mut bytes = [0u8; SIGNATURE_SIZE];
+        bytes[..32].copy_from_slice(&self.R.pk_to_key_slice());
+        bytes[32..].copy_from_slice(&fe_to_bytes(&self.s));
+        bytes
+    }
+
+    /// rejects non canonical encodings of R and s >= q
+    pub fn from_bytes(bytes: &[u8]) -> Result<Signature, Error> {
+        if bytes.len() != SIGNATURE_SIZE {
+            return Err(Error::InvalidEncoding);
+        }
+        let R = GE::from_key_slice(&bytes[..32]).map_err(|_| Error::InvalidEncoding)?;
+        let mut s_vec = bytes[32..].to_vec();
+        s_vec.reverse();
+        let s_bn = BigInt::from(&s_vec[..]);
+        if s_bn >= FE::q() {
+            return Err(Error::InvalidEncoding);
+        }
+        Ok(Signature {
+            s: ECScalar::from(&s_bn),
+            R,
+        })
+    }
+}
+
+// serialized as the hex of to_bytes
+impl Serialize for Signature {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&to_hex(&self.to_bytes()))
+    }
+}
+
+impl<'de> Deserialize<'de> for Signature {
+    fn deserialize<D>(deserializer: D) -> Result<Signature, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(SignatureVisitor)
+    }
+}
+
+struct SignatureVisitor;
+
+impl<'de> Visitor<'de> for SignatureVisitor {
+    type Value = Signature;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("hex encoded RedJubjub signature")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Signature, E> {
+        let bytes = from_hex(v).ok_or_else(|| E::custom("invalid signature hex"))?;
+        Signature::from_bytes(&bytes).map_err(|_| E::custom("invalid signature"))
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// 32 byte encoding of vk, as in the rk field of a Sapling spend description
+pub fn vk_to_bytes(vk: &GE) -> [u8; VK_SIZE] {
+    let mut bytes = [0u8; VK_SIZE];
+    bytes.copy_from_slice(&vk.pk_to_key_slice());
+    bytes
+}
+
+/// hex encoding of ak, names a key in storage and in signing policies
+pub fn key_id(ak: &GE) -> String {
+    to_hex(&vk_to_bytes(ak))
+}
+
+/// rejects non canonical encodings and points outside of the prime order subgroup
+pub fn vk_from_bytes(bytes: &[u8]) -> Result<GE, Error> {
+    if bytes.len() != VK_SIZE {
+        return Err(Error::InvalidEncoding);
+    }
+    GE::from_key_slice(bytes).map_err(|_| Error::InvalidEncoding)
+}
+
 pub fn compute_ak(local_share: &EcKeyPair, ak_counter_party: &GE) -> GE {
     ak_counter_party + &local_share.ak
 }
@@ -65,16 +326,123 @@ pub fn compute_R(local_share: &EphEcKeyPair, R_counter_party: &GE) -> GE {
     R_counter_party + &local_share.R_i
 }
 
-pub fn verify(vk: GE, message: &BigInt, sig: &Signature) -> Result<(), Error> {
-    let mut R_vec = BigInt::to_vec(&sig.R.bytes_compressed_to_big_int());
-    R_vec.reverse();
-    let R_bn = BigInt::from(&R_vec[..]);
-    let c = Blake::create_hash(&vec![&R_bn, message], b"Zcash_RedJubjubH");
-    let mut c_vec = BigInt::to_vec(&c);
-    c_vec.reverse();
-    let c = BigInt::from(&c_vec[..]);
+// 32 byte little endian encoding of a scalar
+fn fe_to_bytes(fe: &FE) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    let fe_vec = BigInt::to_vec(&fe.to_big_int());
+    // to_vec is big endian without leading zeros
+    for (i, byte) in fe_vec.iter().rev().enumerate() {
+        bytes[i] = *byte;
+    }
+    bytes
+}
+
+// reads a 64 byte digest little endian and reduces it mod q
+fn fe_from_digest(digest: &[u8]) -> FE {
+    let mut digest_vec = digest.to_vec();
+    digest_vec.reverse();
+    let fe_bn = BigInt::from(&digest_vec[..]).mod_floor(&FE::q());
+    ECScalar::from(&fe_bn)
+}
+
+// c = H(R || vk || sighash) over the exact encodings
+fn compute_challenge(R: &GE, message: &SpendAuthMessage) -> FE {
+    let mut digest = Blake2b::with_params(64, &[], &[], b"Zcash_RedJubjubH");
+    digest.update(&R.pk_to_key_slice());
+    digest.update(&message.to_bytes());
+    fe_from_digest(digest.finalize().as_ref())
+}
+
+// r_i = H(transcript, ask_i, vk || sighash [, randomness]), transcript is the ephemeral keygen
+// transcript of the party, i.e. it binds the session id
+fn derive_eph_secret(
+    ask: &FE,
+    message: &SpendAuthMessage,
+    transcript: &Transcript,
+    nonce_mode: NonceMode,
+) -> FE {
+    let mut transcript = transcript.clone();
+    transcript.append_message(b"dom-sep", b"eph_secret");
+    transcript.append_scalar(b"ask", ask);
+    transcript.append_message(b"message", &message.to_bytes());
+    if nonce_mode == NonceMode::Hedged {
+        let randomness: FE = ECScalar::new_random();
+        transcript.append_scalar(b"randomness", &randomness);
+    }
+    transcript.challenge_scalar(b"r")
+}
+
+// s_i = r_i + c * (ask_i [+ alpha]), alpha is passed for the share of keygen party one only
+fn compute_local_share(
+    R: &GE,
+    vk: &GE,
+    ask: &FE,
+    eph_key: EphEcKeyPair,
+    message: &SpendAuthMessage,
+    alpha: Option<&FE>,
+) -> Result<FE, Error> {
+    // the message must be for the vk of this session
+    if &message.vk != vk {
+        return Err(Error::protocol(Round::Signing, Check::MessageFormat));
+    }
+    let c = compute_challenge(R, message);
+    let x_i = match alpha {
+        Some(alpha) => *ask + alpha,
+        None => *ask,
+    };
+    Ok(eph_key.r_i + c * x_i)
+}
+
+/// public key a local signature is checked against: ak_i, plus alpha*G for keygen party one
+pub fn compute_local_pk(ak_i: &GE, alpha: Option<&FE>) -> GE {
+    match alpha {
+        Some(alpha) => compute_vk(ak_i, alpha),
+        None => *ak_i,
+    }
+}
 
-    let c_fe: FE = ECScalar::from(&c);
+/// checks s_i*G == R_i + c*pk_i for the local signature of `party`, before it is combined
+pub fn verify_local_share(
+    party: u8,
+    s_i: &FE,
+    R_i: &GE,
+    pk_i: &GE,
+    R: &GE,
+    message: &SpendAuthMessage,
+) -> Result<(), Error> {
+    let c = compute_challenge(R, message);
+    let G = GE::generator();
+    match G * s_i == *R_i + *pk_i * c {
+        true => Ok(()),
+        false => Err(Error::LocalSignature {
+            party,
+            s_i: fe_to_bytes(s_i),
+            R_i: vk_to_bytes(R_i),
+            pk_i: vk_to_bytes(pk_i),
+        }),
+    }
+}
+
+fn combine_local_shares(
+    R: GE,
+    vk: GE,
+    local_s: &FE,
+    counter_s: &FE,
+    message: &SpendAuthMessage,
+) -> Result<Signature, Error> {
+    let sig = Signature {
+        s: *local_s + counter_s,
+        R,
+    };
+    verify(vk, message, &sig).map_err(|_| Error::protocol(Round::Signing, Check::Signature))?;
+    Ok(sig)
+}
+
+pub fn verify(vk: GE, message: &SpendAuthMessage, sig: &Signature) -> Result<(), Error> {
+    if message.vk != vk {
+        return Err(InvalidSig);
+    }
+    let c_fe = compute_challenge(&sig.R, message);
     let R_plus_cvk = sig.R + vk * &c_fe;
     let G = GE::generator();
     let sG = G * &sig.s;
@@ -0,0 +1,336 @@
+#![allow(non_snake_case)]
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Guard against reusing an ephemeral key.
+//! Two local signatures s_i with the same r_i under different challenges give away ask, e.g. when
+//! one ephemeral key signs two messages, or when a session is restarted after R_i was revealed
+//! and the counterparty picks another R_j. A NonceStore records every R_i a party commits to in
+//! EphKeyGenFirstMsg, with the session and message it was committed for, and lets it compute s_i
+//! only once. The guarded methods of the session module consult it:
+//!
+//! - commit: R_i is new, or was committed in the same session for the same message and has not
+//!   signed yet (a restarted session only derives the same R_i with NonceMode::Deterministic,
+//!   which exists in test builds only)
+//! - sign: R_i was committed for this message and has not signed yet
+//!
+//! Anything else is Error::NonceReuse. A record is stored before the commitment or the local
+//! signature leaves the party, FileNonceStore syncs it to disk so the guard survives a restart.
+//! A file must not be shared by two processes.
+
+use super::{to_hex, vk_to_bytes, SessionId, SpendAuthMessage};
+use curv::elliptic::curves::curve_jubjub::GE;
+use serde_json;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use Error;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NonceRecord {
+    pub R_i: GE,
+    pub session_id: SessionId,
+    pub message: SpendAuthMessage,
+    /// s_i was computed with the ephemeral key of R_i
+    pub signed: bool,
+}
+
+pub trait NonceStore: Send {
+    fn get(&self, R_i: &GE) -> Result<Option<NonceRecord>, Error>;
+
+    /// stores record, replacing the one for the same R_i
+    fn put(&mut self, record: &NonceRecord) -> Result<(), Error>;
+
+    /// before the commitment to R_i is sent
+    fn commit(
+        &mut self,
+        R_i: &GE,
+        session_id: &SessionId,
+        message: &SpendAuthMessage,
+    ) -> Result<(), Error> {
+        let record = NonceRecord {
+            R_i: *R_i,
+            session_id: session_id.clone(),
+            message: message.clone(),
+            signed: false,
+        };
+        match self.get(R_i)? {
+            None => self.put(&record),
+            Some(ref stored) if *stored == record => Ok(()),
+            Some(_) => Err(Error::NonceReuse),
+        }
+    }
+
+    /// before s_i is computed with the ephemeral key of R_i
+    fn sign(&mut self, R_i: &GE, message: &SpendAuthMessage) -> Result<(), Error> {
+        match self.get(R_i)? {
+            Some(ref stored) if !stored.signed && stored.message == *message => {
+                self.put(&NonceRecord {
+                    signed: true,
+                    ..stored.clone()
+                })
+            }
+            _ => Err(Error::NonceReuse),
+        }
+    }
+}
+
+fn record_key(R_i: &GE) -> String {
+    to_hex(&vk_to_bytes(R_i))
+}
+
+/// forgets everything on restart, for parties that never resume a session
+#[derive(Default)]
+pub struct MemoryNonceStore {
+    records: HashMap<String, NonceRecord>,
+}
+
+impl MemoryNonceStore {
+    pub fn new() -> MemoryNonceStore {
+        MemoryNonceStore::default()
+    }
+}
+
+impl NonceStore for MemoryNonceStore {
+    fn get(&self, R_i: &GE) -> Result<Option<NonceRecord>, Error> {
+        Ok(self.records.get(&record_key(R_i)).cloned())
+    }
+
+    fn put(&mut self, record: &NonceRecord) -> Result<(), Error> {
+        self.records.insert(record_key(&record.R_i), record.clone());
+        Ok(())
+    }
+}
+
+/// Appends one JSON line per stored record, the last line of an R_i wins. Open reads the file
+/// back, so records are kept in memory too.
+pub struct FileNonceStore {
+    path: PathBuf,
+    records: MemoryNonceStore,
+}
+
+impl FileNonceStore {
+    /// creates the file if there is none
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileNonceStore, Error> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.as_ref())
+            .map_err(|_| Error::Storage)?;
+        let lines = fs::read_to_string(path.as_ref()).map_err(|_| Error::Storage)?;
+        let mut records = MemoryNonceStore::new();
+        for line in lines.lines() {
+            let record: NonceRecord =
+                serde_json::from_str(line).map_err(|_| Error::InvalidEncoding)?;
+            records.put(&record)?;
+        }
+        Ok(FileNonceStore {
+            path: path.as_ref().to_path_buf(),
+            records,
+        })
+    }
+}
+
+impl NonceStore for FileNonceStore {
+    fn get(&self, R_i: &GE) -> Result<Option<NonceRecord>, Error> {
+        self.records.get(R_i)
+    }
+
+    fn put(&mut self, record: &NonceRecord) -> Result<(), Error> {
+        let mut line = serde_json::to_string(record).map_err(|_| Error::InvalidEncoding)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|_| Error::Storage)?;
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.sync_data())
+            .map_err(|_| Error::Storage)?;
+        self.records.put(record)
+    }
+}
+
+/// One store shared by several parties or threads, e.g. both sides of driver::run_signing.
+/// commit and sign hold the lock from get to put.
+impl<S: NonceStore> NonceStore for Arc<Mutex<S>> {
+    fn get(&self, R_i: &GE) -> Result<Option<NonceRecord>, Error> {
+        self.lock().map_err(|_| Error::Storage)?.get(R_i)
+    }
+
+    fn put(&mut self, record: &NonceRecord) -> Result<(), Error> {
+        self.lock().map_err(|_| Error::Storage)?.put(record)
+    }
+
+    fn commit(
+        &mut self,
+        R_i: &GE,
+        session_id: &SessionId,
+        message: &SpendAuthMessage,
+    ) -> Result<(), Error> {
+        self.lock()
+            .map_err(|_| Error::Storage)?
+            .commit(R_i, session_id, message)
+    }
+
+    fn sign(&mut self, R_i: &GE, message: &SpendAuthMessage) -> Result<(), Error> {
+        self.lock().map_err(|_| Error::Storage)?.sign(R_i, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::elliptic::curves::rand::{thread_rng, Rng};
+    use protocols::two_party::nonce_store::{FileNonceStore, MemoryNonceStore, NonceStore};
+    use protocols::two_party::policy::Policies;
+    use protocols::two_party::session::{
+        Party1CoinFlipDone, Party1SigningSession, Party2SigningSession,
+    };
+    use protocols::two_party::test::tests::two_party_keygen;
+    use protocols::two_party::{verify, NonceMode, SessionId, SpendAuthMessage};
+    use serde_json;
+    use std::env;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use Error;
+
+    #[test]
+    fn test_nonce_store() {
+        let (party1_keys, party2_keys, ak) = two_party_keygen();
+        let session_id = SessionId::new_random();
+        let mut party1_store = MemoryNonceStore::new();
+        let mut party2_store = MemoryNonceStore::new();
+        let party1_session = Party1SigningSession::new(party1_keys, ak, session_id.clone())
+            .with_nonce_mode(NonceMode::Deterministic);
+        let party2_session = Party2SigningSession::new(party2_keys, ak, session_id.clone());
+        let (party1_cf_first_message, party1_session) = party1_session.coin_flip_commit();
+        let (party2_cf_first_message, party2_session) = party2_session
+            .coin_flip_share(&party1_cf_first_message)
+            .expect("coin flip failed");
+        let (party1_cf_second_message, party1_session) = party1_session
+            .coin_flip_reveal(&party2_cf_first_message)
+            .expect("bad keygen role");
+        let party2_session = party2_session
+            .coin_flip_finalize(&party1_cf_second_message)
+            .expect("coin flip failed");
+        let message = SpendAuthMessage::new(&party1_session.vk(), &[9u8; 32]).unwrap();
+        let other_message = SpendAuthMessage::new(&party1_session.vk(), &[10u8; 32]).unwrap();
+        // party one can be restarted from here
+        let stored = serde_json::to_string(&party1_session).unwrap();
+        let restart = || -> Party1SigningSession<Party1CoinFlipDone> {
+            serde_json::from_str(&stored).unwrap()
+        };
+
+        let (party1_eph_first_message, party1_session) = party1_session
+            .eph_keygen_commit_guarded(&message, &mut party1_store)
+            .expect("commit refused");
+        // a restart before signing derives the same R_1 and may commit it again
+        assert!(restart()
+            .eph_keygen_commit_guarded(&message, &mut party1_store)
+            .is_ok());
+        let (party2_eph_first_message, party2_session) = party2_session
+            .eph_keygen_create_guarded(&party1_eph_first_message, &message, &mut party2_store)
+            .expect("commit refused");
+        let (party1_eph_second_message, party1_session) = party1_session
+            .eph_keygen_decommit(&party2_eph_first_message)
+            .expect("decommit failed");
+        let (_, party2_session) = party2_session
+            .eph_keygen_verify(&party1_eph_second_message)
+            .expect("verify failed");
+        let (_, party1_session) = party1_session
+            .local_sign_guarded(&mut party1_store)
+            .expect("sign refused");
+        let (party2_local_sig, _) = party2_session
+            .local_sign_with_policy(&Policies::new(), &mut party2_store, "wallet", None)
+            .expect("sign refused");
+        let sig = party1_session
+            .finalize(&party2_local_sig)
+            .expect("finalize failed");
+        assert!(verify(message.vk(), &message, &sig).is_ok());
+
+        // once R_1 has signed, a restart cannot reveal it again
+        assert_eq!(
+            restart()
+                .eph_keygen_commit_guarded(&message, &mut party1_store)
+                .err(),
+            Some(Error::NonceReuse)
+        );
+        // another message derives another R_1
+        assert!(restart()
+            .eph_keygen_commit_guarded(&other_message, &mut party1_store)
+            .is_ok());
+
+        let party1_public_share = party1_eph_second_message.comm_witness.public_share;
+        let party2_public_share = party2_eph_first_message.public_share;
+        assert_eq!(
+            party2_store.commit(&party2_public_share, &session_id, &other_message),
+            Err(Error::NonceReuse)
+        );
+        assert_eq!(
+            party2_store.sign(&party2_public_share, &message),
+            Err(Error::NonceReuse)
+        );
+        assert_eq!(
+            MemoryNonceStore::new().sign(&party2_public_share, &message),
+            Err(Error::NonceReuse)
+        );
+        // a shared store refuses through every handle
+        let shared_store = Arc::new(Mutex::new(party2_store));
+        assert_eq!(
+            shared_store.clone().sign(&party2_public_share, &message),
+            Err(Error::NonceReuse)
+        );
+        assert_eq!(
+            shared_store
+                .clone()
+                .commit(&party2_public_share, &session_id, &other_message),
+            Err(Error::NonceReuse)
+        );
+
+        // the file store remembers across restarts
+        let path = env::temp_dir().join(format!(
+            "paradise-city-nonces-{}",
+            thread_rng().gen::<u64>()
+        ));
+        let mut file_store = FileNonceStore::open(&path).expect("failed to open");
+        assert!(file_store
+            .commit(&party1_public_share, &session_id, &message)
+            .is_ok());
+        let mut file_store = FileNonceStore::open(&path).expect("failed to reopen");
+        assert!(file_store
+            .commit(&party1_public_share, &session_id, &message)
+            .is_ok());
+        assert!(file_store.sign(&party1_public_share, &message).is_ok());
+        let mut file_store = FileNonceStore::open(&path).expect("failed to reopen");
+        assert!(
+            file_store
+                .get(&party1_public_share)
+                .unwrap()
+                .unwrap()
+                .signed
+        );
+        assert_eq!(
+            file_store.sign(&party1_public_share, &message),
+            Err(Error::NonceReuse)
+        );
+        assert_eq!(
+            file_store.commit(&party1_public_share, &session_id, &message),
+            Err(Error::NonceReuse)
+        );
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -17,27 +17,28 @@
 
 use super::party_two::CoinFlipFirstMsg as Party2CoinFlipFirstMsg;
 use super::party_two::EphKeyGenFirstMsg as Party2EphKeyGenFirstMsg;
+use super::party_two::KeyGenFirstMsg as Party2KeyGenFirstMsg;
 use super::party_two::LocalSignatureMsg as CounterLocalSig;
-use super::{EcKeyPair, EphEcKeyPair};
+use super::{combine_local_shares, compute_local_share, derive_eph_secret, verify_local_share};
+use super::{EcKeyPair, EphEcKeyPair, KeyGenRole, NonceMode, SessionId, SpendAuthMessage};
+use super::{COIN_FLIP_ROUND, EPH_KEYGEN_ROUND, KEYGEN_ROUND, PARTY_ONE, PARTY_TWO};
 use curv::arithmetic::big_gmp::BigInt;
-use curv::arithmetic::traits::Converter;
 use curv::arithmetic::traits::Samplable;
 use curv::cryptographic_primitives::commitments::hash_commitment::HashCommitment;
-use curv::cryptographic_primitives::commitments::traits::Commitment;
 use curv::cryptographic_primitives::hashing::blake2b512::Blake;
+use curv::cryptographic_primitives::hashing::transcript::Transcript;
 use curv::cryptographic_primitives::proofs::sigma_dlog::DLogProof;
 use curv::cryptographic_primitives::proofs::sigma_dlog::ProveDLog;
 use curv::cryptographic_primitives::proofs::sigma_ec_ddh::ECDDHProof;
 use curv::cryptographic_primitives::proofs::sigma_ec_ddh::ECDDHStatement;
 use curv::cryptographic_primitives::proofs::sigma_ec_ddh::ECDDHWitness;
 use curv::cryptographic_primitives::proofs::sigma_ec_ddh::NISigmaProof;
-use curv::cryptographic_primitives::proofs::ProofError;
 use curv::cryptographic_primitives::twoparty::coin_flip_optimal_rounds;
 use curv::elliptic::curves::curve_jubjub::FE;
 use curv::elliptic::curves::curve_jubjub::GE;
 use curv::elliptic::curves::traits::{ECPoint, ECScalar};
-use protocols::two_party::verify;
 use protocols::two_party::Signature;
+use {Check, Error, Round};
 
 const SECURITY_BITS: usize = 256;
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -62,6 +63,16 @@ pub struct CommWitness {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CoinFlipFirstMsg {
     pub cf_msg1: coin_flip_optimal_rounds::Party1FirstMessage,
+    /// keygen role of the sender, the counterparty must have the other one
+    pub role: KeyGenRole,
+}
+
+/// seed and blinding of the coin flip commitment, kept until the reveal
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CoinFlipWitness {
+    pub seed: FE,
+    pub blinding: FE,
+    pub role: KeyGenRole,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -95,8 +106,9 @@ pub struct LocalSignatureMsg {
 }
 
 impl KeyGenFirstMsg {
-    pub fn create_commitments() -> (KeyGenFirstMsg, CommWitness, EcKeyPair) {
+    pub fn create_commitments(session_id: &SessionId) -> (KeyGenFirstMsg, CommWitness, EcKeyPair) {
         let base: GE = ECPoint::generator();
+        let transcript = session_id.transcript(PARTY_ONE, KEYGEN_ROUND);
 
         let secret_share: FE = ECScalar::new_random();
         //in Lindell's protocol range proof works only for x1<q/3
@@ -105,20 +117,22 @@ impl KeyGenFirstMsg {
 
         let public_share = base.scalar_mul(&secret_share.get_element());
 
-        let d_log_proof = DLogProof::prove(&secret_share);
+        let d_log_proof = DLogProof::prove(&secret_share, &transcript);
         // we use hash based commitment
         let pk_commitment_blind_factor = BigInt::sample(SECURITY_BITS);
-        let pk_commitment = HashCommitment::create_commitment_with_user_defined_randomness(
+        let pk_commitment = HashCommitment::create_commitment_with_transcript(
             &public_share.bytes_compressed_to_big_int(),
             &pk_commitment_blind_factor,
+            &transcript,
         );
 
         let zk_pok_blind_factor = BigInt::sample(SECURITY_BITS);
-        let zk_pok_commitment = HashCommitment::create_commitment_with_user_defined_randomness(
+        let zk_pok_commitment = HashCommitment::create_commitment_with_transcript(
             &d_log_proof
                 .pk_t_rand_commitment
                 .bytes_compressed_to_big_int(),
             &zk_pok_blind_factor,
+            &transcript,
         );
         let ec_key_pair = EcKeyPair {
             ak: public_share,
@@ -141,35 +155,41 @@ impl KeyGenFirstMsg {
 
     pub fn create_commitments_with_fixed_secret_share(
         secret_share: FE,
-    ) -> (KeyGenFirstMsg, CommWitness, EcKeyPair) {
+        session_id: &SessionId,
+    ) -> Result<(KeyGenFirstMsg, CommWitness, EcKeyPair), Error> {
         //in Lindell's protocol range proof works only for x1<q/3
         let sk_bigint = secret_share.to_big_int();
         let q_third = FE::q();
-        assert!(&sk_bigint < &q_third.div_floor(&BigInt::from(3)));
+        if &sk_bigint >= &q_third.div_floor(&BigInt::from(3)) {
+            return Err(Error::protocol(Round::KeyGen, Check::SecretShareRange));
+        }
         let base: GE = ECPoint::generator();
+        let transcript = session_id.transcript(PARTY_ONE, KEYGEN_ROUND);
         let public_share = base.scalar_mul(&secret_share.get_element());
 
-        let d_log_proof = DLogProof::prove(&secret_share);
+        let d_log_proof = DLogProof::prove(&secret_share, &transcript);
 
         let pk_commitment_blind_factor = BigInt::sample(SECURITY_BITS);
-        let pk_commitment = HashCommitment::create_commitment_with_user_defined_randomness(
+        let pk_commitment = HashCommitment::create_commitment_with_transcript(
             &public_share.bytes_compressed_to_big_int(),
             &pk_commitment_blind_factor,
+            &transcript,
         );
 
         let zk_pok_blind_factor = BigInt::sample(SECURITY_BITS);
-        let zk_pok_commitment = HashCommitment::create_commitment_with_user_defined_randomness(
+        let zk_pok_commitment = HashCommitment::create_commitment_with_transcript(
             &d_log_proof
                 .pk_t_rand_commitment
                 .bytes_compressed_to_big_int(),
             &zk_pok_blind_factor,
+            &transcript,
         );
 
         let ec_key_pair = EcKeyPair {
             ak: public_share,
             ask: secret_share,
         };
-        (
+        Ok((
             KeyGenFirstMsg {
                 pk_commitment,
                 zk_pok_commitment,
@@ -181,60 +201,91 @@ impl KeyGenFirstMsg {
                 d_log_proof,
             },
             ec_key_pair,
-        )
+        ))
     }
 }
 
 impl KeyGenSecondMsg {
+    /// the proof of party two must be for the public share it sent along
     pub fn verify_and_decommit(
         comm_witness: CommWitness,
-        proof: &DLogProof,
-    ) -> Result<KeyGenSecondMsg, ProofError> {
-        DLogProof::verify(proof)?;
+        party_two_first_message: &Party2KeyGenFirstMsg,
+        session_id: &SessionId,
+    ) -> Result<KeyGenSecondMsg, Error> {
+        let transcript = session_id.transcript(PARTY_TWO, KEYGEN_ROUND);
+        let proof = &party_two_first_message.d_log_proof;
+        DLogProof::verify(proof, &transcript)
+            .map_err(|_| Error::protocol(Round::KeyGen, Check::DLogProof))?;
+        if proof.pk != party_two_first_message.public_share {
+            return Err(Error::protocol(Round::KeyGen, Check::PublicShare));
+        }
         Ok(KeyGenSecondMsg { comm_witness })
     }
 }
 
 impl CoinFlipFirstMsg {
-    pub fn commit() -> (CoinFlipFirstMsg, FE, FE) {
-        let (cf_msg1, seed, blinding) = coin_flip_optimal_rounds::Party1FirstMessage::commit();
-        (CoinFlipFirstMsg { cf_msg1 }, seed, blinding)
+    /// role is the keygen role of the sender
+    pub fn commit(session_id: &SessionId, role: KeyGenRole) -> (CoinFlipFirstMsg, CoinFlipWitness) {
+        let transcript = session_id.transcript(PARTY_ONE, COIN_FLIP_ROUND);
+        let (cf_msg1, seed, blinding) =
+            coin_flip_optimal_rounds::Party1FirstMessage::commit(&transcript);
+        (
+            CoinFlipFirstMsg { cf_msg1, role },
+            CoinFlipWitness {
+                seed,
+                blinding,
+                role,
+            },
+        )
     }
 }
 
 impl CoinFlipSecondMsg {
+    /// refuses to reveal if party two claims the same keygen role
     pub fn reveal(
         party2_first_message: &Party2CoinFlipFirstMsg,
-        seed: FE,
-        blinding: FE,
-    ) -> (CoinFlipSecondMsg, FE) {
+        witness: CoinFlipWitness,
+        session_id: &SessionId,
+    ) -> Result<(CoinFlipSecondMsg, FE), Error> {
+        witness.role.check_counterparty(party2_first_message.role)?;
+        let transcript = session_id.transcript(PARTY_ONE, COIN_FLIP_ROUND);
         let (cf_msg2, coin_flip) = coin_flip_optimal_rounds::Party1SecondMessage::reveal(
             &party2_first_message.cf_msg1.seed,
-            &seed,
-            &blinding,
+            &witness.seed,
+            &witness.blinding,
+            &transcript,
         );
 
-        (CoinFlipSecondMsg { cf_msg2 }, coin_flip)
+        Ok((CoinFlipSecondMsg { cf_msg2 }, coin_flip))
     }
 }
 
 impl EphKeyGenFirstMsg {
     pub fn create_commitments(
-        vk: &GE,
-        message: &BigInt,
+        ask: &FE,
+        message: &SpendAuthMessage,
+        session_id: &SessionId,
+        nonce_mode: NonceMode,
     ) -> (EphKeyGenFirstMsg, EphCommWitness, EphEcKeyPair) {
-        let base: GE = ECPoint::generator();
+        let transcript = session_id.transcript(PARTY_ONE, EPH_KEYGEN_ROUND);
+        let r_i = derive_eph_secret(ask, message, &transcript, nonce_mode);
+        EphKeyGenFirstMsg::create_commitments_with_secret(r_i, &transcript)
+    }
 
-        let randomness: FE = ECScalar::new_random();
-        let ft = Blake::create_hash(
-            &vec![
-                &vk.bytes_compressed_to_big_int(),
-                message,
-                &randomness.to_big_int(),
-            ],
-            b"Zcash_RedJubjubH",
-        );
-        let r_i = ECScalar::from(&ft);
+    // ephemeral share of a presignature, created before vk and message are known
+    pub fn create_commitments_offline(
+        session_id: &SessionId,
+    ) -> (EphKeyGenFirstMsg, EphCommWitness, EphEcKeyPair) {
+        let transcript = session_id.transcript(PARTY_ONE, EPH_KEYGEN_ROUND);
+        let r_i: FE = ECScalar::new_random();
+        EphKeyGenFirstMsg::create_commitments_with_secret(r_i, &transcript)
+    }
+
+    fn create_commitments_with_secret(
+        r_i: FE,
+        transcript: &Transcript,
+    ) -> (EphKeyGenFirstMsg, EphCommWitness, EphEcKeyPair) {
+        let base: GE = ECPoint::generator();
         let R_i = base * &r_i;
 
         let h: GE = GE::base_point2();
@@ -246,20 +297,22 @@ impl EphKeyGenFirstMsg {
             g2: h.clone(),
             h2: c.clone(),
         };
-        let d_log_proof = ECDDHProof::prove(&w, &delta);
+        let d_log_proof = ECDDHProof::prove(&w, &delta, transcript);
 
         // we use hash based commitment
         let pk_commitment_blind_factor = BigInt::sample(SECURITY_BITS);
-        let pk_commitment = HashCommitment::create_commitment_with_user_defined_randomness(
+        let pk_commitment = HashCommitment::create_commitment_with_transcript(
             &R_i.bytes_compressed_to_big_int(),
             &pk_commitment_blind_factor,
+            transcript,
         );
 
         let zk_pok_blind_factor = BigInt::sample(SECURITY_BITS);
-        let zk_pok_commitment = HashCommitment::create_commitment_with_user_defined_randomness(
+        let zk_pok_commitment = HashCommitment::create_commitment_with_transcript(
             &Blake::create_hash_from_ge(&[&d_log_proof.a1, &d_log_proof.a2], b"Zcash_RedJubjubH")
                 .to_big_int(),
             &zk_pok_blind_factor,
+            transcript,
         );
 
         let ec_key_pair = EphEcKeyPair { R_i, r_i };
@@ -284,56 +337,55 @@ impl EphKeyGenSecondMsg {
     pub fn verify_and_decommit(
         comm_witness: EphCommWitness,
         party_one_first_message: &Party2EphKeyGenFirstMsg,
-    ) -> Result<EphKeyGenSecondMsg, ProofError> {
+        session_id: &SessionId,
+    ) -> Result<EphKeyGenSecondMsg, Error> {
+        let transcript = session_id.transcript(PARTY_TWO, EPH_KEYGEN_ROUND);
         let delta = ECDDHStatement {
             g1: GE::generator(),
             h1: party_one_first_message.public_share.clone(),
             g2: GE::base_point2(),
             h2: party_one_first_message.c.clone(),
         };
-        party_one_first_message.d_log_proof.verify(&delta)?;
+        party_one_first_message
+            .d_log_proof
+            .verify(&delta, &transcript)
+            .map_err(|_| Error::protocol(Round::EphKeyGen, Check::ECDDHProof))?;
         Ok(EphKeyGenSecondMsg { comm_witness })
     }
 }
 
 impl LocalSignatureMsg {
-    pub fn compute_s1(
+    // no NonceStore, reached only through the session and presign modules, which consult one
+    pub(super) fn compute_s1(
         R: &GE,
         vk: &GE,
         key: EcKeyPair,
         eph_key: EphEcKeyPair,
-        message: &BigInt,
+        message: &SpendAuthMessage,
         alpha: &FE,
-    ) -> LocalSignatureMsg {
-        // check that message is 64 bytes and that first 32 bytes are vk:
-        let message_vec = BigInt::to_vec(&message);
-        assert_eq!(message_vec.len(), 64);
-        let vk_bytes = vk.pk_to_key_slice();
-        assert_eq!(&message_vec[0..32], &vk_bytes[..]);
-        let mut R_vec = BigInt::to_vec(&R.bytes_compressed_to_big_int());
-        R_vec.reverse();
-        let R_bn = BigInt::from(&R_vec[..]);
-        let hash_R_vk_m = Blake::create_hash(&vec![&R_bn, &message], b"Zcash_RedJubjubH");
-        let mut hash_R_vk_m_vec = BigInt::to_vec(&hash_R_vk_m);
-        hash_R_vk_m_vec.reverse();
-        let hash_R_vk_m = BigInt::from(&hash_R_vk_m_vec[..]);
-        let hash_fe: FE = ECScalar::from(&hash_R_vk_m);
-        let s1 = eph_key.r_i + hash_fe * (key.ask + alpha);
-        LocalSignatureMsg { s1 }
+    ) -> Result<LocalSignatureMsg, Error> {
+        let s1 = compute_local_share(R, vk, &key.ask, eph_key, message, Some(alpha))?;
+        Ok(LocalSignatureMsg { s1 })
     }
 
+    /// counter_R_i and counter_ak_i are the ephemeral and keygen public shares of party two
     pub fn compute(
         R: GE,
         vk: GE,
         local_sig: &LocalSignatureMsg,
         counter_sig: &CounterLocalSig,
-        message: &BigInt,
-    ) -> Signature {
-        let sig = Signature {
-            s: local_sig.s1 + counter_sig.s2,
-            R,
-        };
-        verify(vk, message, &sig).expect("bad signature");
-        sig
+        counter_R_i: &GE,
+        counter_ak_i: &GE,
+        message: &SpendAuthMessage,
+    ) -> Result<Signature, Error> {
+        verify_local_share(
+            PARTY_TWO,
+            &counter_sig.s2,
+            counter_R_i,
+            counter_ak_i,
+            &R,
+            message,
+        )?;
+        combine_local_shares(R, vk, &local_sig.s1, &counter_sig.s2, message)
     }
 }
@@ -22,26 +22,25 @@ use super::party_one::EphKeyGenSecondMsg as Party1EphKeyGenSecondMessage;
 use super::party_one::KeyGenFirstMsg as Party1KeyGenFirstMessage;
 use super::party_one::KeyGenSecondMsg as Party1KeyGenSecondMessage;
 use super::party_one::LocalSignatureMsg as CounterLocalSig;
-use super::{EcKeyPair, EphEcKeyPair};
+use super::{combine_local_shares, compute_local_share, derive_eph_secret, verify_local_share};
+use super::{EcKeyPair, EphEcKeyPair, KeyGenRole, NonceMode, SessionId, SpendAuthMessage};
+use super::{COIN_FLIP_ROUND, EPH_KEYGEN_ROUND, KEYGEN_ROUND, PARTY_ONE, PARTY_TWO};
 use curv::cryptographic_primitives::commitments::hash_commitment::HashCommitment;
-use curv::cryptographic_primitives::commitments::traits::Commitment;
 use curv::cryptographic_primitives::hashing::blake2b512::Blake;
+use curv::cryptographic_primitives::hashing::transcript::Transcript;
 
-use curv::arithmetic::big_gmp::BigInt;
-use curv::arithmetic::traits::Converter;
 use curv::cryptographic_primitives::proofs::sigma_dlog::DLogProof;
 use curv::cryptographic_primitives::proofs::sigma_dlog::ProveDLog;
 use curv::cryptographic_primitives::proofs::sigma_ec_ddh::ECDDHProof;
 use curv::cryptographic_primitives::proofs::sigma_ec_ddh::ECDDHStatement;
 use curv::cryptographic_primitives::proofs::sigma_ec_ddh::ECDDHWitness;
 use curv::cryptographic_primitives::proofs::sigma_ec_ddh::NISigmaProof;
-use curv::cryptographic_primitives::proofs::ProofError;
 use curv::cryptographic_primitives::twoparty::coin_flip_optimal_rounds;
 use curv::elliptic::curves::curve_jubjub::FE;
 use curv::elliptic::curves::curve_jubjub::GE;
 use curv::elliptic::curves::traits::{ECPoint, ECScalar};
-use protocols::two_party::verify;
 use protocols::two_party::Signature;
+use {Check, Error, Round};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct KeyGenFirstMsg {
@@ -55,6 +54,8 @@ pub struct KeyGenSecondMsg {}
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CoinFlipFirstMsg {
     pub cf_msg1: coin_flip_optimal_rounds::Party2FirstMessage,
+    /// keygen role of the sender, the counterparty must have the other one
+    pub role: KeyGenRole,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -78,11 +79,12 @@ pub struct LocalSignatureMsg {
 }
 
 impl KeyGenFirstMsg {
-    pub fn create() -> (KeyGenFirstMsg, EcKeyPair) {
+    pub fn create(session_id: &SessionId) -> (KeyGenFirstMsg, EcKeyPair) {
         let base: GE = ECPoint::generator();
+        let transcript = session_id.transcript(PARTY_TWO, KEYGEN_ROUND);
         let secret_share: FE = ECScalar::new_random();
         let public_share = base * &secret_share;
-        let d_log_proof = DLogProof::prove(&secret_share);
+        let d_log_proof = DLogProof::prove(&secret_share, &transcript);
         let ec_key_pair = EcKeyPair {
             ak: public_share,
             ask: secret_share,
@@ -96,10 +98,14 @@ impl KeyGenFirstMsg {
         )
     }
 
-    pub fn create_with_fixed_secret_share(secret_share: FE) -> (KeyGenFirstMsg, EcKeyPair) {
+    pub fn create_with_fixed_secret_share(
+        secret_share: FE,
+        session_id: &SessionId,
+    ) -> (KeyGenFirstMsg, EcKeyPair) {
         let base: GE = ECPoint::generator();
+        let transcript = session_id.transcript(PARTY_TWO, KEYGEN_ROUND);
         let public_share = base * &secret_share;
-        let d_log_proof = DLogProof::prove(&secret_share);
+        let d_log_proof = DLogProof::prove(&secret_share, &transcript);
         let ec_key_pair = EcKeyPair {
             ak: public_share,
             ask: secret_share,
@@ -118,7 +124,9 @@ impl KeyGenSecondMsg {
     pub fn verify_commitments_and_dlog_proof(
         party_one_first_message: &Party1KeyGenFirstMessage,
         party_one_second_message: &Party1KeyGenSecondMessage,
-    ) -> Result<KeyGenSecondMsg, ProofError> {
+        session_id: &SessionId,
+    ) -> Result<KeyGenSecondMsg, Error> {
+        let transcript = session_id.transcript(PARTY_ONE, KEYGEN_ROUND);
         let party_one_pk_commitment = &party_one_first_message.pk_commitment;
         let party_one_zk_pok_commitment = &party_one_first_message.zk_pok_commitment;
         let party_one_zk_pok_blind_factor =
@@ -129,38 +137,50 @@ impl KeyGenSecondMsg {
             .pk_commitment_blind_factor;
         let party_one_d_log_proof = &party_one_second_message.comm_witness.d_log_proof;
 
-        let mut flag = true;
-        match party_one_pk_commitment
-            == &HashCommitment::create_commitment_with_user_defined_randomness(
+        if party_one_pk_commitment
+            != &HashCommitment::create_commitment_with_transcript(
                 &party_one_public_share.bytes_compressed_to_big_int(),
                 &party_one_pk_commitment_blind_factor,
-            ) {
-            false => flag = false,
-            true => flag = flag,
-        };
-        match party_one_zk_pok_commitment
-            == &HashCommitment::create_commitment_with_user_defined_randomness(
+                &transcript,
+            )
+        {
+            return Err(Error::protocol(Round::KeyGen, Check::PkCommitment));
+        }
+        if party_one_zk_pok_commitment
+            != &HashCommitment::create_commitment_with_transcript(
                 &party_one_d_log_proof
                     .pk_t_rand_commitment
                     .bytes_compressed_to_big_int(),
                 &party_one_zk_pok_blind_factor,
-            ) {
-            false => flag = false,
-            true => flag = flag,
-        };
-        assert!(flag);
-        DLogProof::verify(&party_one_d_log_proof)?;
+                &transcript,
+            )
+        {
+            return Err(Error::protocol(Round::KeyGen, Check::ZkPokCommitment));
+        }
+        DLogProof::verify(&party_one_d_log_proof, &transcript)
+            .map_err(|_| Error::protocol(Round::KeyGen, Check::DLogProof))?;
+        if &party_one_d_log_proof.pk != party_one_public_share {
+            return Err(Error::protocol(Round::KeyGen, Check::PublicShare));
+        }
         Ok(KeyGenSecondMsg {})
     }
 }
 
 impl CoinFlipFirstMsg {
-    pub fn share(party1_first_message: &Party1CoinFlipFirstMsg) -> (CoinFlipFirstMsg) {
-        CoinFlipFirstMsg {
-            cf_msg1: coin_flip_optimal_rounds::Party2FirstMessage::share(
-                &party1_first_message.cf_msg1.proof,
-            ),
-        }
+    /// role is the keygen role of the sender, it must not be the one party one claims
+    pub fn share(
+        party1_first_message: &Party1CoinFlipFirstMsg,
+        session_id: &SessionId,
+        role: KeyGenRole,
+    ) -> Result<CoinFlipFirstMsg, Error> {
+        role.check_counterparty(party1_first_message.role)?;
+        let transcript = session_id.transcript(PARTY_ONE, COIN_FLIP_ROUND);
+        let cf_msg1 = coin_flip_optimal_rounds::Party2FirstMessage::share(
+            &party1_first_message.cf_msg1.proof,
+            &transcript,
+        )
+        .map_err(|_| Error::protocol(Round::CoinFlip, Check::PedersenProof))?;
+        Ok(CoinFlipFirstMsg { cf_msg1, role })
     }
 }
 
@@ -169,31 +189,46 @@ impl CoinFlipResult {
         party1_second_message: &Party1CoinFlipSecondMsg,
         party2_first_message: &CoinFlipFirstMsg,
         party1_first_message: &Party1CoinFlipFirstMsg,
-    ) -> CoinFlipResult {
+        session_id: &SessionId,
+    ) -> Result<CoinFlipResult, Error> {
+        let transcript = session_id.transcript(PARTY_ONE, COIN_FLIP_ROUND);
+        if party1_second_message.cf_msg2.proof.com != party1_first_message.cf_msg1.proof.com {
+            return Err(Error::protocol(Round::CoinFlip, Check::CoinFlipCommitment));
+        }
         let coin_flip = coin_flip_optimal_rounds::finalize(
             &party1_second_message.cf_msg2.proof,
             &party2_first_message.cf_msg1.seed,
             &party1_first_message.cf_msg1.proof.com,
-        );
-        CoinFlipResult {
+            &transcript,
+        )
+        .map_err(|_| Error::protocol(Round::CoinFlip, Check::PedersenBlindingProof))?;
+        Ok(CoinFlipResult {
             party2_alpha: coin_flip,
-        }
+        })
     }
 }
 
 impl EphKeyGenFirstMsg {
-    pub fn create(vk: &GE, message: &BigInt) -> (EphKeyGenFirstMsg, EphEcKeyPair) {
+    pub fn create(
+        ask: &FE,
+        message: &SpendAuthMessage,
+        session_id: &SessionId,
+        nonce_mode: NonceMode,
+    ) -> (EphKeyGenFirstMsg, EphEcKeyPair) {
+        let transcript = session_id.transcript(PARTY_TWO, EPH_KEYGEN_ROUND);
+        let r_i = derive_eph_secret(ask, message, &transcript, nonce_mode);
+        EphKeyGenFirstMsg::create_with_secret(r_i, &transcript)
+    }
+
+    // ephemeral share of a presignature, created before vk and message are known
+    pub fn create_offline(session_id: &SessionId) -> (EphKeyGenFirstMsg, EphEcKeyPair) {
+        let transcript = session_id.transcript(PARTY_TWO, EPH_KEYGEN_ROUND);
+        let r_i: FE = ECScalar::new_random();
+        EphKeyGenFirstMsg::create_with_secret(r_i, &transcript)
+    }
+
+    fn create_with_secret(r_i: FE, transcript: &Transcript) -> (EphKeyGenFirstMsg, EphEcKeyPair) {
         let base: GE = ECPoint::generator();
-        let randomness: FE = ECScalar::new_random();
-        let ft = Blake::create_hash(
-            &vec![
-                &vk.bytes_compressed_to_big_int(),
-                message,
-                &randomness.to_big_int(),
-            ],
-            b"Zcash_RedJubjubH",
-        );
-        let r_i = ECScalar::from(&ft);
         let R_i = base * &r_i;
 
         let h: GE = GE::base_point2();
@@ -205,7 +240,7 @@ impl EphKeyGenFirstMsg {
             g2: h.clone(),
             h2: c.clone(),
         };
-        let d_log_proof = ECDDHProof::prove(&w, &delta);
+        let d_log_proof = ECDDHProof::prove(&w, &delta, transcript);
         let ec_key_pair = EphEcKeyPair { R_i, r_i };
         (
             EphKeyGenFirstMsg {
@@ -222,7 +257,9 @@ impl EphKeyGenSecondMsg {
     pub fn verify_commitments_and_dlog_proof(
         party_one_first_message: &Party1EphKeyGenFirstMessage,
         party_one_second_message: &Party1EphKeyGenSecondMessage,
-    ) -> Result<EphKeyGenSecondMsg, ProofError> {
+        session_id: &SessionId,
+    ) -> Result<EphKeyGenSecondMsg, Error> {
+        let transcript = session_id.transcript(PARTY_ONE, EPH_KEYGEN_ROUND);
         let party_two_pk_commitment = &party_one_first_message.pk_commitment;
         let party_two_zk_pok_commitment = &party_one_first_message.zk_pok_commitment;
         let party_two_zk_pok_blind_factor =
@@ -232,76 +269,73 @@ impl EphKeyGenSecondMsg {
             .comm_witness
             .pk_commitment_blind_factor;
         let party_two_d_log_proof = &party_one_second_message.comm_witness.d_log_proof;
-        let mut flag = true;
-        match party_two_pk_commitment
-            == &HashCommitment::create_commitment_with_user_defined_randomness(
+        if party_two_pk_commitment
+            != &HashCommitment::create_commitment_with_transcript(
                 &party_two_public_share.bytes_compressed_to_big_int(),
                 &party_two_pk_commitment_blind_factor,
-            ) {
-            false => flag = false,
-            true => flag = flag,
-        };
-        match party_two_zk_pok_commitment
-            == &HashCommitment::create_commitment_with_user_defined_randomness(
+                &transcript,
+            )
+        {
+            return Err(Error::protocol(Round::EphKeyGen, Check::PkCommitment));
+        }
+        if party_two_zk_pok_commitment
+            != &HashCommitment::create_commitment_with_transcript(
                 &Blake::create_hash_from_ge(
                     &[&party_two_d_log_proof.a1, &party_two_d_log_proof.a2],
                     b"Zcash_RedJubjubH",
                 )
                 .to_big_int(),
                 &party_two_zk_pok_blind_factor,
-            ) {
-            false => flag = false,
-            true => flag = flag,
-        };
-        assert!(flag);
+                &transcript,
+            )
+        {
+            return Err(Error::protocol(Round::EphKeyGen, Check::ZkPokCommitment));
+        }
         let delta = ECDDHStatement {
             g1: GE::generator(),
             h1: party_two_public_share.clone(),
             g2: GE::base_point2(),
             h2: party_one_second_message.comm_witness.c.clone(),
         };
-        party_two_d_log_proof.verify(&delta)?;
+        party_two_d_log_proof
+            .verify(&delta, &transcript)
+            .map_err(|_| Error::protocol(Round::EphKeyGen, Check::ECDDHProof))?;
         Ok(EphKeyGenSecondMsg {})
     }
 }
 
 impl LocalSignatureMsg {
-    pub fn compute_s2(
+    // no policy, reached only through the session and presign modules, which run it
+    pub(super) fn compute_s2(
         R: &GE,
         vk: &GE,
         key: EcKeyPair,
         eph_key: EphEcKeyPair,
-        message: &BigInt,
-    ) -> LocalSignatureMsg {
-        // check that message is 64 bytes and that first 32 bytes are vk:
-        let message_vec = BigInt::to_vec(&message);
-        assert_eq!(message_vec.len(), 64);
-        let vk_bytes = vk.pk_to_key_slice();
-        assert_eq!(&message_vec[0..32], &vk_bytes[..]);
-        let mut R_vec = BigInt::to_vec(&R.bytes_compressed_to_big_int());
-        R_vec.reverse();
-        let R_bn = BigInt::from(&R_vec[..]);
-        let hash_R_vk_m = Blake::create_hash(&vec![&R_bn, &message], b"Zcash_RedJubjubH");
-        let mut hash_R_vk_m_vec = BigInt::to_vec(&hash_R_vk_m);
-        hash_R_vk_m_vec.reverse();
-        let hash_R_vk_m = BigInt::from(&hash_R_vk_m_vec[..]);
-        let hash_fe: FE = ECScalar::from(&hash_R_vk_m);
-        let s2 = eph_key.r_i + hash_fe * key.ask;
-        LocalSignatureMsg { s2 }
+        message: &SpendAuthMessage,
+    ) -> Result<LocalSignatureMsg, Error> {
+        let s2 = compute_local_share(R, vk, &key.ask, eph_key, message, None)?;
+        Ok(LocalSignatureMsg { s2 })
     }
 
+    /// counter_R_i and counter_pk_i are the ephemeral public share of party one and
+    /// compute_local_pk(ak_1, Some(alpha))
     pub fn compute(
         R: GE,
         vk: GE,
         local_sig: &LocalSignatureMsg,
         counter_sig: &CounterLocalSig,
-        message: &BigInt,
-    ) -> Signature {
-        let sig = Signature {
-            s: local_sig.s2 + counter_sig.s1,
-            R,
-        };
-        verify(vk, message, &sig).expect("bad signature");
-        sig
+        counter_R_i: &GE,
+        counter_pk_i: &GE,
+        message: &SpendAuthMessage,
+    ) -> Result<Signature, Error> {
+        verify_local_share(
+            PARTY_ONE,
+            &counter_sig.s1,
+            counter_R_i,
+            counter_pk_i,
+            &R,
+            message,
+        )?;
+        combine_local_shares(R, vk, &local_sig.s2, &counter_sig.s1, message)
     }
 }
@@ -0,0 +1,694 @@
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Signing policies of party two.
+//! Before party two releases s2 it hands the SigningContext of the session to its SigningPolicy.
+//! check decides, record is called once every policy accepted, right before s2 is released, so a
+//! policy that keeps state (spent amounts, approvals) only counts signatures that are released.
+//! check and record of one request must not interleave with another request, the Cosigner runs
+//! them under one lock.
+//!
+//! A step that releases s2 under a policy runs check before it consumes anything, and hands its
+//! session back in a Held when the policy rejects. A request waiting for a ManualApproval operator
+//! is retried on the same session until it is approved or denied.
+//!
+//! Transaction details are claimed by party one and are not checked against the sighash here:
+//! policies on them are only as good as the caller that sends them.
+
+use super::{key_id, SessionId};
+use curv::elliptic::curves::curve_jubjub::{FE, GE};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use {Error, Rejection};
+
+/// decoded details of the transaction the sighash is for
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransactionDetails {
+    /// value spent, in zatoshi
+    pub value: u64,
+    pub recipient: String,
+}
+
+/// what party two knows about a signature before releasing s2
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SigningContext {
+    pub key_id: String,
+    pub session_id: SessionId,
+    pub vk: GE,
+    pub sighash: [u8; 32],
+    pub alpha: FE,
+    /// identity of party one, as authenticated by the transport
+    pub caller: String,
+    pub details: Option<TransactionDetails>,
+}
+
+impl SigningContext {
+    pub fn new(
+        ak: &GE,
+        session_id: &SessionId,
+        vk: &GE,
+        sighash: &[u8; 32],
+        alpha: &FE,
+        caller: &str,
+        details: Option<TransactionDetails>,
+    ) -> SigningContext {
+        SigningContext {
+            key_id: key_id(ak),
+            session_id: session_id.clone(),
+            vk: *vk,
+            sighash: *sighash,
+            alpha: *alpha,
+            caller: caller.to_string(),
+            details,
+        }
+    }
+}
+
+/// The error of a step that releases s2 under a policy. session is the session of the step,
+/// handed back unchanged if the policy rejected before anything was consumed, and None once the
+/// ephemeral key is gone. Converts into its Error.
+pub struct Held<T> {
+    pub error: Error,
+    pub session: Option<T>,
+}
+
+impl<T> Held<T> {
+    pub(super) fn back(error: Error, session: T) -> Held<T> {
+        Held {
+            error,
+            session: Some(session),
+        }
+    }
+}
+
+impl<T> From<Error> for Held<T> {
+    fn from(error: Error) -> Held<T> {
+        Held {
+            error,
+            session: None,
+        }
+    }
+}
+
+impl<T> From<Held<T>> for Error {
+    fn from(held: Held<T>) -> Error {
+        held.error
+    }
+}
+
+// sessions hold secret shares, only the error is shown
+impl<T> fmt::Debug for Held<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Held")
+            .field("error", &self.error)
+            .field("session", &self.session.is_some())
+            .finish()
+    }
+}
+
+pub trait SigningPolicy: Send {
+    fn check(&self, context: &SigningContext) -> Result<(), Rejection>;
+
+    fn record(&self, _context: &SigningContext) {}
+}
+
+/// check then record, for a single policy or Policies
+pub fn enforce<P: SigningPolicy + ?Sized>(
+    policy: &P,
+    context: &SigningContext,
+) -> Result<(), Error> {
+    policy.check(context).map_err(Error::PolicyRejected)?;
+    policy.record(context);
+    Ok(())
+}
+
+// lets a policy be shared with the code that administers it, e.g. ManualApproval
+impl<P: SigningPolicy + Sync> SigningPolicy for Arc<P> {
+    fn check(&self, context: &SigningContext) -> Result<(), Rejection> {
+        (**self).check(context)
+    }
+
+    fn record(&self, context: &SigningContext) {
+        (**self).record(context)
+    }
+}
+
+/// All policies must accept, they are checked in order and the first rejection is returned.
+/// Put ManualApproval last, so only requests every other policy accepts wait for an operator.
+/// An empty Policies accepts everything.
+#[derive(Default)]
+pub struct Policies {
+    policies: Vec<Box<dyn SigningPolicy>>,
+}
+
+impl Policies {
+    pub fn new() -> Policies {
+        Policies::default()
+    }
+
+    pub fn with<P: SigningPolicy + 'static>(mut self, policy: P) -> Policies {
+        self.policies.push(Box::new(policy));
+        self
+    }
+}
+
+impl SigningPolicy for Policies {
+    fn check(&self, context: &SigningContext) -> Result<(), Rejection> {
+        self.policies
+            .iter()
+            .map(|policy| policy.check(context))
+            .collect()
+    }
+
+    fn record(&self, context: &SigningContext) {
+        for policy in &self.policies {
+            policy.record(context);
+        }
+    }
+}
+
+/// At most limit zatoshi per key within any window, requests without details are rejected.
+/// The spends are only kept in memory, a new SpendLimit starts from nothing.
+pub struct SpendLimit {
+    limit: u64,
+    window: Duration,
+    spends: Mutex<HashMap<String, Vec<(Instant, u64)>>>,
+}
+
+impl SpendLimit {
+    pub fn new(limit: u64, window: Duration) -> SpendLimit {
+        SpendLimit {
+            limit,
+            window,
+            spends: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// value signed for key_id within the last window
+    pub fn spent(&self, key_id: &str) -> u64 {
+        let mut spends = self.spends.lock().unwrap();
+        match spends.get_mut(key_id) {
+            Some(key_spends) => {
+                let window = self.window;
+                key_spends.retain(|&(time, _)| time.elapsed() < window);
+                key_spends.iter().map(|&(_, value)| value).sum()
+            }
+            None => 0,
+        }
+    }
+}
+
+impl SigningPolicy for SpendLimit {
+    fn check(&self, context: &SigningContext) -> Result<(), Rejection> {
+        let value = match context.details {
+            Some(ref details) => details.value,
+            None => return Err(Rejection::MissingDetails),
+        };
+        match self.spent(&context.key_id).checked_add(value) {
+            Some(total) if total <= self.limit => Ok(()),
+            _ => Err(Rejection::SpendLimitExceeded),
+        }
+    }
+
+    fn record(&self, context: &SigningContext) {
+        if let Some(ref details) = context.details {
+            self.spends
+                .lock()
+                .unwrap()
+                .entry(context.key_id.clone())
+                .or_insert_with(Vec::new)
+                .push((Instant::now(), details.value));
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ListField {
+    Caller,
+    Recipient,
+    Key,
+}
+
+/// Rejects entries on the deny list, and with an allow list everything not on it.
+pub struct AllowDenyList {
+    field: ListField,
+    allow: Option<HashSet<String>>,
+    deny: HashSet<String>,
+}
+
+impl AllowDenyList {
+    fn new(field: ListField, allow: Option<Vec<String>>, deny: Vec<String>) -> AllowDenyList {
+        AllowDenyList {
+            field,
+            allow: allow.map(|allow| allow.into_iter().collect()),
+            deny: deny.into_iter().collect(),
+        }
+    }
+
+    /// on the caller identity
+    pub fn callers(allow: Option<Vec<String>>, deny: Vec<String>) -> AllowDenyList {
+        AllowDenyList::new(ListField::Caller, allow, deny)
+    }
+
+    /// on the recipient in the transaction details, requests without details are rejected
+    pub fn recipients(allow: Option<Vec<String>>, deny: Vec<String>) -> AllowDenyList {
+        AllowDenyList::new(ListField::Recipient, allow, deny)
+    }
+
+    /// on the key id, see key_id
+    pub fn keys(allow: Option<Vec<String>>, deny: Vec<String>) -> AllowDenyList {
+        AllowDenyList::new(ListField::Key, allow, deny)
+    }
+}
+
+impl SigningPolicy for AllowDenyList {
+    fn check(&self, context: &SigningContext) -> Result<(), Rejection> {
+        let entry = match self.field {
+            ListField::Caller => &context.caller,
+            ListField::Key => &context.key_id,
+            ListField::Recipient => match context.details {
+                Some(ref details) => &details.recipient,
+                None => return Err(Rejection::MissingDetails),
+            },
+        };
+        if self.deny.contains(entry) {
+            return Err(Rejection::Denied);
+        }
+        match self.allow {
+            Some(ref allow) if !allow.contains(entry) => Err(Rejection::NotAllowed),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// how long a request waits for an operator, and an approval or denial for its retry
+pub const APPROVAL_TTL: Duration = Duration::from_secs(600);
+
+/// requests waiting for an operator at the same time
+pub const MAX_PENDING_APPROVALS: usize = 1000;
+
+/// pause between two retries of a request waiting for an operator
+pub const APPROVAL_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// every entry carries the time it was queued, approved or denied
+#[derive(Default)]
+struct Approvals {
+    pending: HashMap<SessionId, (Instant, SigningContext)>,
+    approved: HashMap<SessionId, (Instant, SigningContext)>,
+    denied: HashMap<SessionId, Instant>,
+}
+
+impl Approvals {
+    fn expire(&mut self, ttl: Duration) {
+        self.pending
+            .retain(|_, &mut (time, _)| time.elapsed() < ttl);
+        self.approved
+            .retain(|_, &mut (time, _)| time.elapsed() < ttl);
+        self.denied.retain(|_, &mut time| time.elapsed() < ttl);
+    }
+}
+
+/// Every session waits for an operator. The first check queues the request and rejects it with
+/// PendingApproval, party one retries the same session until it is approved or denied. An
+/// approval holds for exactly the context the operator saw, a retry with other details is queued
+/// again. Share it with the operator side through an Arc.
+///
+/// A request not retried within the ttl leaves the queue, so does an approval or denial its
+/// session does not pick up. With max_pending requests queued further ones are rejected with
+/// ApprovalQueueFull.
+pub struct ManualApproval {
+    ttl: Duration,
+    max_pending: usize,
+    approvals: Mutex<Approvals>,
+}
+
+impl Default for ManualApproval {
+    fn default() -> ManualApproval {
+        ManualApproval::with_limits(APPROVAL_TTL, MAX_PENDING_APPROVALS)
+    }
+}
+
+impl ManualApproval {
+    pub fn new() -> ManualApproval {
+        ManualApproval::default()
+    }
+
+    pub fn with_limits(ttl: Duration, max_pending: usize) -> ManualApproval {
+        ManualApproval {
+            ttl,
+            max_pending,
+            approvals: Mutex::new(Approvals::default()),
+        }
+    }
+
+    /// requests waiting for an operator
+    pub fn pending(&self) -> Vec<SigningContext> {
+        let mut approvals = self.approvals.lock().unwrap();
+        approvals.expire(self.ttl);
+        approvals
+            .pending
+            .values()
+            .map(|&(_, ref context)| context.clone())
+            .collect()
+    }
+
+    /// false if no request of this session is waiting
+    pub fn approve(&self, session_id: &SessionId) -> bool {
+        let mut approvals = self.approvals.lock().unwrap();
+        approvals.expire(self.ttl);
+        match approvals.pending.remove(session_id) {
+            Some((_, context)) => {
+                approvals
+                    .approved
+                    .insert(session_id.clone(), (Instant::now(), context));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// false if no request of this session is waiting
+    pub fn deny(&self, session_id: &SessionId) -> bool {
+        let mut approvals = self.approvals.lock().unwrap();
+        approvals.expire(self.ttl);
+        match approvals.pending.remove(session_id) {
+            Some(_) => approvals
+                .denied
+                .insert(session_id.clone(), Instant::now())
+                .is_none(),
+            None => false,
+        }
+    }
+}
+
+impl SigningPolicy for ManualApproval {
+    fn check(&self, context: &SigningContext) -> Result<(), Rejection> {
+        let mut approvals = self.approvals.lock().unwrap();
+        approvals.expire(self.ttl);
+        match approvals.approved.get(&context.session_id) {
+            Some(&(_, ref approved)) if approved == context => return Ok(()),
+            _ => (),
+        }
+        approvals.approved.remove(&context.session_id);
+        if approvals.denied.remove(&context.session_id).is_some() {
+            return Err(Rejection::ApprovalDenied);
+        }
+        // a retry only refreshes its entry
+        if !approvals.pending.contains_key(&context.session_id)
+            && approvals.pending.len() >= self.max_pending
+        {
+            return Err(Rejection::ApprovalQueueFull);
+        }
+        approvals.pending.insert(
+            context.session_id.clone(),
+            (Instant::now(), context.clone()),
+        );
+        Err(Rejection::PendingApproval)
+    }
+
+    // an approval is good for one signature
+    fn record(&self, context: &SigningContext) {
+        let mut approvals = self.approvals.lock().unwrap();
+        approvals.approved.remove(&context.session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::elliptic::curves::curve_jubjub::FE;
+    use curv::elliptic::curves::rand::{thread_rng, Rng};
+    use curv::elliptic::curves::traits::ECScalar;
+    use protocols::two_party::cosigner::{Cosigner, CosignerClient, KeyStore};
+    use protocols::two_party::driver;
+    use protocols::two_party::nonce_store::MemoryNonceStore;
+    use protocols::two_party::policy::{
+        AllowDenyList, ManualApproval, Policies, SigningContext, SigningPolicy, SpendLimit,
+        TransactionDetails,
+    };
+    use protocols::two_party::session::{Party1SigningSession, Party2SigningSession};
+    use protocols::two_party::test::tests::{details, serve_cosigner, two_party_keygen};
+    use protocols::two_party::transport::ChannelTransport;
+    use protocols::two_party::{verify, SessionId, SpendAuthMessage};
+    use std::env;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use {Error, Rejection};
+
+    #[test]
+    fn test_signing_policies() {
+        let store_dir = env::temp_dir().join(format!(
+            "paradise-city-cosigner-{}",
+            thread_rng().gen::<u64>()
+        ));
+        let spend_limit = Arc::new(SpendLimit::new(100, Duration::from_secs(3600)));
+        let policy = Policies::new()
+            .with(AllowDenyList::callers(
+                Some(vec!["wallet".to_string()]),
+                vec![],
+            ))
+            .with(AllowDenyList::recipients(None, vec!["blocked".to_string()]))
+            .with(spend_limit.clone());
+        let cosigner = Cosigner::new(KeyStore::open(&store_dir).expect("failed to open store"))
+            .with_policy(policy);
+        let addr = serve_cosigner(cosigner);
+        let client = CosignerClient::new(&addr).with_caller("wallet");
+        let (key_id, keys, ak) = client.keygen().expect("keygen failed");
+        let sign = |client: &CosignerClient, details: Option<TransactionDetails>| {
+            client.sign_with_details(&key_id, keys.clone(), ak, &[5u8; 32], details)
+        };
+
+        let (message, sig) = sign(&client, details(60, "shop")).expect("signing failed");
+        assert!(verify(message.vk(), &message, &sig).is_ok());
+        assert_eq!(spend_limit.spent(&key_id), 60);
+        let rejected = |rejection| Some(Error::PolicyRejected(rejection));
+        assert_eq!(
+            sign(&client, details(50, "shop")).err(),
+            rejected(Rejection::SpendLimitExceeded)
+        );
+        assert!(sign(&client, details(40, "shop")).is_ok());
+        assert_eq!(spend_limit.spent(&key_id), 100);
+        assert_eq!(
+            sign(&client, None).err(),
+            rejected(Rejection::MissingDetails)
+        );
+        assert_eq!(
+            sign(&client, details(0, "blocked")).err(),
+            rejected(Rejection::Denied)
+        );
+        let other_client = CosignerClient::new(&addr).with_caller("mallory");
+        let (other_key_id, other_keys, other_ak) = other_client.keygen().expect("keygen failed");
+        assert_eq!(
+            other_client
+                .sign_with_details(
+                    &other_key_id,
+                    other_keys,
+                    other_ak,
+                    &[5u8; 32],
+                    details(0, "shop")
+                )
+                .err(),
+            rejected(Rejection::NotAllowed)
+        );
+        // rejected requests are not counted
+        assert_eq!(spend_limit.spent(&key_id), 100);
+        let _ = fs::remove_dir_all(&store_dir);
+    }
+
+    #[test]
+    fn test_manual_approval() {
+        let store_dir = env::temp_dir().join(format!(
+            "paradise-city-cosigner-{}",
+            thread_rng().gen::<u64>()
+        ));
+        let approval = Arc::new(ManualApproval::new());
+        let cosigner = Cosigner::new(KeyStore::open(&store_dir).expect("failed to open store"))
+            .with_policy(approval.clone());
+        let addr = serve_cosigner(cosigner);
+        let client = CosignerClient::new(&addr).with_caller("wallet");
+        let (key_id, keys, ak) = client.keygen().expect("keygen failed");
+
+        // without waiting the client gets the rejection at once
+        match client.sign_with_details(&key_id, keys.clone(), ak, &[6u8; 32], details(1, "shop")) {
+            Err(e) => assert_eq!(e, Error::PolicyRejected(Rejection::PendingApproval)),
+            Ok(_) => panic!("signed without an approval"),
+        }
+        assert_eq!(approval.pending().len(), 1);
+
+        for &approve in &[true, false] {
+            let client = CosignerClient::new(&addr)
+                .with_caller("wallet")
+                .with_approval_timeout(Duration::from_secs(60));
+            let (client_key_id, client_keys) = (key_id.clone(), keys.clone());
+            let signer = thread::spawn(move || {
+                client.sign_with_details(
+                    &client_key_id,
+                    client_keys,
+                    ak,
+                    &[7u8; 32],
+                    details(2, "shop"),
+                )
+            });
+            // the request of the earlier client is still queued
+            let context = loop {
+                match approval
+                    .pending()
+                    .into_iter()
+                    .find(|context| context.details == details(2, "shop"))
+                {
+                    Some(context) => break context,
+                    None => thread::sleep(Duration::from_millis(100)),
+                }
+            };
+            assert_eq!(context.key_id, key_id);
+            assert_eq!(context.caller, "wallet");
+            assert_eq!(&context.sighash, &[7u8; 32]);
+            if approve {
+                assert!(approval.approve(&context.session_id));
+                let (message, sig) = signer.join().unwrap().expect("signing failed");
+                assert!(verify(message.vk(), &message, &sig).is_ok());
+                assert_eq!(message.vk(), context.vk);
+            } else {
+                assert!(approval.deny(&context.session_id));
+                match signer.join().unwrap() {
+                    Err(e) => assert_eq!(e, Error::PolicyRejected(Rejection::ApprovalDenied)),
+                    Ok(_) => panic!("signed a denied request"),
+                }
+            }
+            assert!(!approval.approve(&context.session_id));
+        }
+        let _ = fs::remove_dir_all(&store_dir);
+    }
+
+    #[test]
+    fn test_manual_approval_session() {
+        let (party1_keys, party2_keys, ak) = two_party_keygen();
+        let session_id = SessionId::new_random();
+        let mut party1_store = MemoryNonceStore::new();
+        let mut party2_store = MemoryNonceStore::new();
+        let party1_session = Party1SigningSession::new(party1_keys.clone(), ak, session_id.clone());
+        let party2_session = Party2SigningSession::new(party2_keys.clone(), ak, session_id.clone());
+        let (party1_cf_first_message, party1_session) = party1_session.coin_flip_commit();
+        let (party2_cf_first_message, party2_session) = party2_session
+            .coin_flip_share(&party1_cf_first_message)
+            .expect("bad coin flip proof");
+        let (party1_cf_second_message, party1_session) = party1_session
+            .coin_flip_reveal(&party2_cf_first_message)
+            .expect("bad keygen role");
+        let party2_session = party2_session
+            .coin_flip_finalize(&party1_cf_second_message)
+            .expect("bad coin flip proof");
+        let message = SpendAuthMessage::new(&party1_session.vk(), &[5u8; 32]).unwrap();
+        let (party1_eph_first_message, party1_session) = party1_session
+            .eph_keygen_commit_guarded(&message, &mut party1_store)
+            .expect("commit refused");
+        let (party2_eph_first_message, party2_session) = party2_session
+            .eph_keygen_create_guarded(&party1_eph_first_message, &message, &mut party2_store)
+            .expect("commit refused");
+        let (party1_eph_second_message, party1_session) = party1_session
+            .eph_keygen_decommit(&party2_eph_first_message)
+            .expect("decommit failed");
+        let (_, party2_session) = party2_session
+            .eph_keygen_verify(&party1_eph_second_message)
+            .expect("verify failed");
+
+        // the session comes back while the request waits, and again on every retry
+        let approval = ManualApproval::new();
+        let mut party2_session = Some(party2_session);
+        for _ in 0..2 {
+            match party2_session.take().unwrap().local_sign_with_policy(
+                &approval,
+                &mut party2_store,
+                "wallet",
+                details(3, "shop"),
+            ) {
+                Err(held) => {
+                    assert_eq!(
+                        held.error,
+                        Error::PolicyRejected(Rejection::PendingApproval)
+                    );
+                    party2_session = held.session;
+                }
+                Ok(_) => panic!("signed without an approval"),
+            }
+        }
+        assert!(approval.approve(&session_id));
+        let (party2_local_sig, _) = party2_session
+            .unwrap()
+            .local_sign_with_policy(&approval, &mut party2_store, "wallet", details(3, "shop"))
+            .expect("approved request refused");
+        let (_, party1_session) = party1_session
+            .local_sign_guarded(&mut party1_store)
+            .expect("sign refused");
+        let sig = party1_session
+            .finalize(&party2_local_sig)
+            .expect("bad signature");
+        assert!(verify(message.vk(), &message, &sig).is_ok());
+
+        // the driver waits for the operator
+        let approval = Arc::new(ManualApproval::new());
+        let operator = approval.clone();
+        thread::spawn(move || loop {
+            match operator.pending().first() {
+                Some(context) => break assert!(operator.approve(&context.session_id)),
+                None => thread::sleep(Duration::from_millis(100)),
+            }
+        });
+        let session_id = SessionId::new_random();
+        let (party1_transport, party2_transport) = ChannelTransport::pair();
+        let (message, sig) = driver::run_signing(
+            party1_transport,
+            party2_transport,
+            Party1SigningSession::new(party1_keys, ak, session_id.clone()),
+            Party2SigningSession::new(party2_keys, ak, session_id),
+            &[6u8; 32],
+            Arc::new(Mutex::new(MemoryNonceStore::new())),
+            approval,
+        )
+        .expect("signing failed");
+        assert!(verify(message.vk(), &message, &sig).is_ok());
+    }
+
+    #[test]
+    fn test_manual_approval_limits() {
+        let (_, _, ak) = two_party_keygen();
+        let alpha: FE = ECScalar::new_random();
+        let context = |session_id: &SessionId| {
+            SigningContext::new(&ak, session_id, &ak, &[8u8; 32], &alpha, "wallet", None)
+        };
+        let approval = ManualApproval::with_limits(Duration::from_millis(500), 2);
+        let sessions: Vec<SessionId> = (0..3).map(|_| SessionId::new_random()).collect();
+        let check = |i: usize| approval.check(&context(&sessions[i]));
+        assert_eq!(check(0), Err(Rejection::PendingApproval));
+        assert_eq!(check(1), Err(Rejection::PendingApproval));
+        assert_eq!(check(2), Err(Rejection::ApprovalQueueFull));
+        // a retry of a queued request is not refused
+        assert_eq!(check(1), Err(Rejection::PendingApproval));
+        assert!(approval.approve(&sessions[0]));
+        assert_eq!(check(2), Err(Rejection::PendingApproval));
+
+        // requests, approvals and denials expire
+        assert!(approval.deny(&sessions[1]));
+        thread::sleep(Duration::from_millis(600));
+        assert!(approval.pending().is_empty());
+        assert!(!approval.approve(&sessions[2]));
+        assert_eq!(check(0), Err(Rejection::PendingApproval));
+        assert_eq!(check(1), Err(Rejection::PendingApproval));
+    }
+}
@@ -0,0 +1,654 @@
+#![allow(non_snake_case)]
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Offline / online split of two party signing.
+//! Offline: the coin flip for alpha and two runs of the commit-decommit ephemeral key generation
+//! are done ahead of time, without the message. Each party gets a hiding nonce (d_i, D_i) and a
+//! binding nonce (e_i, E_i), generated under the session ids id.child(0) and id.child(1) of the
+//! offline run, and stores the resulting one-time presignature in a pool keyed by id.
+//! Online: once the message is known party one takes a presignature out of its pool and sends a
+//! single request, party two takes the matching presignature out of its own pool and answers with
+//! s2. Taking removes the presignature, so each one is used at most once.
+//!
+//! R is only fixed together with the message, as in FROST: the nonce of party i is
+//! R_i = D_i + rho_i*E_i, with the binding factor rho_i = H(id, i, D_1, E_1, D_2, E_2, message).
+//! Without it R would be known before the message is picked, and party one could pick the
+//! messages of many concurrent presignatures so that their s2 add up to a forgery (ROS attack).
+//!
+//! Both parties derive vk from the alpha of the coin flip and their own ak, party two checks the
+//! message is for it. A presignature is serializable, a copy of it can be put back into a pool:
+//! create and respond record the hiding nonce in a NonceStore, so a presignature signs one message
+//! only, and respond releases s2 only once the SigningPolicy of party two accepts the request. A
+//! rejection hands the accepted request back, so it is retried once an operator decided.
+
+use std::collections::HashMap;
+
+use super::nonce_store::NonceStore;
+use super::party_one;
+use super::party_two;
+use super::policy::{Held, SigningContext, SigningPolicy, TransactionDetails};
+use super::SpendAuthMessage;
+use super::{combine_local_shares, compute_local_pk, verify_local_share};
+use super::{compute_R, compute_vk, EcKeyPair, EphEcKeyPair, KeyGenRole, SessionId, Signature};
+use super::{PARTY_ONE, PARTY_TWO, PRESIGN_ROUND};
+use curv::elliptic::curves::curve_jubjub::FE;
+use curv::elliptic::curves::curve_jubjub::GE;
+use curv::elliptic::curves::traits::ECPoint;
+use {Check, Error, Round};
+
+const HIDING_NONCE: u32 = 0;
+const BINDING_NONCE: u32 = 1;
+
+/// the hiding and the binding nonce of one party, or their round messages
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NoncePair<T> {
+    pub hiding: T,
+    pub binding: T,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Party1PresignFirstMsg {
+    pub cf_first_msg: party_one::CoinFlipFirstMsg,
+    pub eph_first_msgs: NoncePair<party_one::EphKeyGenFirstMsg>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Party2PresignFirstMsg {
+    pub cf_first_msg: party_two::CoinFlipFirstMsg,
+    pub eph_first_msgs: NoncePair<party_two::EphKeyGenFirstMsg>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Party1PresignSecondMsg {
+    pub cf_second_msg: party_one::CoinFlipSecondMsg,
+    pub eph_second_msgs: NoncePair<party_one::EphKeyGenSecondMsg>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Presignature {
+    pub id: SessionId,
+    alpha: FE,
+    eph_keys: NoncePair<EphEcKeyPair>,
+    // D and E of the counterparty, as decommitted in the offline run
+    counter_nonces: NoncePair<GE>,
+}
+
+impl Presignature {
+    /// the alpha both parties sign with, known before the message
+    pub fn alpha(&self) -> FE {
+        self.alpha
+    }
+
+    /// the vk a message signed with this presignature must be for
+    pub fn vk(&self, ak: &GE) -> GE {
+        compute_vk(ak, &self.alpha)
+    }
+
+    fn nonces(&self) -> NoncePair<GE> {
+        NoncePair {
+            hiding: self.eph_keys.hiding.R_i,
+            binding: self.eph_keys.binding.R_i,
+        }
+    }
+}
+
+pub struct PresignaturePool {
+    presignatures: HashMap<SessionId, Presignature>,
+}
+
+impl PresignaturePool {
+    pub fn new() -> PresignaturePool {
+        PresignaturePool {
+            presignatures: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, presignature: Presignature) {
+        self.presignatures
+            .insert(presignature.id.clone(), presignature);
+    }
+
+    /// e.g. to read alpha before building the message
+    pub fn get(&self, id: &SessionId) -> Option<&Presignature> {
+        self.presignatures.get(id)
+    }
+
+    /// removes the presignature from the pool, it can not be taken again
+    pub fn take(&mut self, id: &SessionId) -> Option<Presignature> {
+        self.presignatures.remove(id)
+    }
+
+    pub fn ids(&self) -> Vec<SessionId> {
+        self.presignatures.keys().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.presignatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.presignatures.is_empty()
+    }
+}
+
+// rho_i = H(id, party, D_1, E_1, D_2, E_2, vk || sighash)
+fn binding_factor(
+    id: &SessionId,
+    party: u8,
+    party1_nonces: &NoncePair<GE>,
+    party2_nonces: &NoncePair<GE>,
+    message: &SpendAuthMessage,
+) -> FE {
+    let mut transcript = id.transcript(party, PRESIGN_ROUND);
+    transcript.append_point(b"D_1", &party1_nonces.hiding);
+    transcript.append_point(b"E_1", &party1_nonces.binding);
+    transcript.append_point(b"D_2", &party2_nonces.hiding);
+    transcript.append_point(b"E_2", &party2_nonces.binding);
+    transcript.append_message(b"message", &message.to_bytes());
+    transcript.challenge_scalar(b"rho")
+}
+
+// the ephemeral key (r_i, R_i) = (d_i + rho_i*e_i, D_i + rho_i*E_i)
+fn bind(eph_keys: NoncePair<EphEcKeyPair>, rho: &FE) -> EphEcKeyPair {
+    EphEcKeyPair {
+        R_i: bind_public(
+            &NoncePair {
+                hiding: eph_keys.hiding.R_i,
+                binding: eph_keys.binding.R_i,
+            },
+            rho,
+        ),
+        r_i: eph_keys.hiding.r_i + &(eph_keys.binding.r_i * rho),
+    }
+}
+
+fn bind_public(nonces: &NoncePair<GE>, rho: &FE) -> GE {
+    nonces.binding * rho + &nonces.hiding
+}
+
+// the bound ephemeral key of the party and the R_i of its counterparty
+fn bind_nonces(
+    presignature: Presignature,
+    party: u8,
+    message: &SpendAuthMessage,
+) -> (EphEcKeyPair, GE) {
+    let own_nonces = presignature.nonces();
+    let (party1_nonces, party2_nonces) = match party {
+        PARTY_ONE => (&own_nonces, &presignature.counter_nonces),
+        _ => (&presignature.counter_nonces, &own_nonces),
+    };
+    let rho_1 = binding_factor(
+        &presignature.id,
+        PARTY_ONE,
+        party1_nonces,
+        party2_nonces,
+        message,
+    );
+    let rho_2 = binding_factor(
+        &presignature.id,
+        PARTY_TWO,
+        party1_nonces,
+        party2_nonces,
+        message,
+    );
+    let (own_rho, counter_rho) = match party {
+        PARTY_ONE => (rho_1, rho_2),
+        _ => (rho_2, rho_1),
+    };
+    let counter_R_i = bind_public(&presignature.counter_nonces, &counter_rho);
+    (bind(presignature.eph_keys, &own_rho), counter_R_i)
+}
+
+// a presignature signs once: the first message its hiding nonce is recorded for
+fn record_use<S: NonceStore + ?Sized>(
+    store: &mut S,
+    presignature: &Presignature,
+    message: &SpendAuthMessage,
+) -> Result<(), Error> {
+    let D_i = presignature.eph_keys.hiding.R_i;
+    store.commit(&D_i, &presignature.id, message)?;
+    store.sign(&D_i, message)
+}
+
+// the keygen public share of the counterparty, ak = ak_1 + ak_2
+fn counter_ak(keys: &EcKeyPair, ak: &GE) -> GE {
+    ak.sub_point(&keys.ak.get_element())
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1PresignCommitted {
+    session_id: SessionId,
+    cf_witness: party_one::CoinFlipWitness,
+    comm_witnesses: NoncePair<party_one::EphCommWitness>,
+    eph_keys: NoncePair<EphEcKeyPair>,
+}
+
+impl Party1PresignCommitted {
+    pub fn commit(session_id: &SessionId) -> (Party1PresignFirstMsg, Party1PresignCommitted) {
+        let (cf_first_msg, cf_witness) =
+            party_one::CoinFlipFirstMsg::commit(session_id, KeyGenRole::PartyOne);
+        let (hiding_first_msg, hiding_witness, hiding_key) =
+            party_one::EphKeyGenFirstMsg::create_commitments_offline(
+                &session_id.child(HIDING_NONCE),
+            );
+        let (binding_first_msg, binding_witness, binding_key) =
+            party_one::EphKeyGenFirstMsg::create_commitments_offline(
+                &session_id.child(BINDING_NONCE),
+            );
+        (
+            Party1PresignFirstMsg {
+                cf_first_msg,
+                eph_first_msgs: NoncePair {
+                    hiding: hiding_first_msg,
+                    binding: binding_first_msg,
+                },
+            },
+            Party1PresignCommitted {
+                session_id: session_id.clone(),
+                cf_witness,
+                comm_witnesses: NoncePair {
+                    hiding: hiding_witness,
+                    binding: binding_witness,
+                },
+                eph_keys: NoncePair {
+                    hiding: hiding_key,
+                    binding: binding_key,
+                },
+            },
+        )
+    }
+
+    pub fn decommit(
+        self,
+        party2_first_msg: &Party2PresignFirstMsg,
+    ) -> Result<(Party1PresignSecondMsg, Presignature), Error> {
+        let party2_eph_first_msgs = &party2_first_msg.eph_first_msgs;
+        let hiding_second_msg = party_one::EphKeyGenSecondMsg::verify_and_decommit(
+            self.comm_witnesses.hiding,
+            &party2_eph_first_msgs.hiding,
+            &self.session_id.child(HIDING_NONCE),
+        )?;
+        let binding_second_msg = party_one::EphKeyGenSecondMsg::verify_and_decommit(
+            self.comm_witnesses.binding,
+            &party2_eph_first_msgs.binding,
+            &self.session_id.child(BINDING_NONCE),
+        )?;
+        let (cf_second_msg, alpha) = party_one::CoinFlipSecondMsg::reveal(
+            &party2_first_msg.cf_first_msg,
+            self.cf_witness,
+            &self.session_id,
+        )?;
+        Ok((
+            Party1PresignSecondMsg {
+                cf_second_msg,
+                eph_second_msgs: NoncePair {
+                    hiding: hiding_second_msg,
+                    binding: binding_second_msg,
+                },
+            },
+            Presignature {
+                id: self.session_id,
+                alpha,
+                eph_keys: self.eph_keys,
+                counter_nonces: NoncePair {
+                    hiding: party2_eph_first_msgs.hiding.public_share,
+                    binding: party2_eph_first_msgs.binding.public_share,
+                },
+            },
+        ))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2PresignCreated {
+    session_id: SessionId,
+    party1_first_msg: Party1PresignFirstMsg,
+    cf_first_msg: party_two::CoinFlipFirstMsg,
+    eph_keys: NoncePair<EphEcKeyPair>,
+}
+
+impl Party2PresignCreated {
+    pub fn create(
+        session_id: &SessionId,
+        party1_first_msg: &Party1PresignFirstMsg,
+    ) -> Result<(Party2PresignFirstMsg, Party2PresignCreated), Error> {
+        let cf_first_msg = party_two::CoinFlipFirstMsg::share(
+            &party1_first_msg.cf_first_msg,
+            session_id,
+            KeyGenRole::PartyTwo,
+        )?;
+        let (hiding_first_msg, hiding_key) =
+            party_two::EphKeyGenFirstMsg::create_offline(&session_id.child(HIDING_NONCE));
+        let (binding_first_msg, binding_key) =
+            party_two::EphKeyGenFirstMsg::create_offline(&session_id.child(BINDING_NONCE));
+        Ok((
+            Party2PresignFirstMsg {
+                cf_first_msg: cf_first_msg.clone(),
+                eph_first_msgs: NoncePair {
+                    hiding: hiding_first_msg,
+                    binding: binding_first_msg,
+                },
+            },
+            Party2PresignCreated {
+                session_id: session_id.clone(),
+                party1_first_msg: party1_first_msg.clone(),
+                cf_first_msg,
+                eph_keys: NoncePair {
+                    hiding: hiding_key,
+                    binding: binding_key,
+                },
+            },
+        ))
+    }
+
+    pub fn verify(self, party1_second_msg: &Party1PresignSecondMsg) -> Result<Presignature, Error> {
+        let party1_eph_first_msgs = &self.party1_first_msg.eph_first_msgs;
+        let party1_eph_second_msgs = &party1_second_msg.eph_second_msgs;
+        party_two::EphKeyGenSecondMsg::verify_commitments_and_dlog_proof(
+            &party1_eph_first_msgs.hiding,
+            &party1_eph_second_msgs.hiding,
+            &self.session_id.child(HIDING_NONCE),
+        )?;
+        party_two::EphKeyGenSecondMsg::verify_commitments_and_dlog_proof(
+            &party1_eph_first_msgs.binding,
+            &party1_eph_second_msgs.binding,
+            &self.session_id.child(BINDING_NONCE),
+        )?;
+        let coin_flip_res = party_two::CoinFlipResult::finalize(
+            &party1_second_msg.cf_second_msg,
+            &self.cf_first_msg,
+            &self.party1_first_msg.cf_first_msg,
+            &self.session_id,
+        )?;
+        Ok(Presignature {
+            id: self.session_id,
+            alpha: coin_flip_res.party2_alpha,
+            eph_keys: self.eph_keys,
+            counter_nonces: NoncePair {
+                hiding: party1_eph_second_msgs.hiding.comm_witness.public_share,
+                binding: party1_eph_second_msgs.binding.comm_witness.public_share,
+            },
+        })
+    }
+}
+
+/// the single online message of party one
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PresignedSignRequest {
+    pub presignature_id: SessionId,
+    pub message: SpendAuthMessage,
+    pub local_sig: party_one::LocalSignatureMsg,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1PresignedSignPending {
+    vk: GE,
+    message: SpendAuthMessage,
+    R: GE,
+    counter_R_i: GE,
+    counter_ak_i: GE,
+    local_sig: party_one::LocalSignatureMsg,
+}
+
+/// party two, after taking the presignature of a request and before releasing s2
+#[derive(Serialize, Deserialize)]
+pub struct Party2PresignedSignAccepted {
+    keys: EcKeyPair,
+    ak: GE,
+    presignature: Presignature,
+    request: PresignedSignRequest,
+}
+
+impl PresignedSignRequest {
+    /// party one: message must be for the vk of the presignature, see Presignature::vk
+    pub fn create<S: NonceStore + ?Sized>(
+        pool: &mut PresignaturePool,
+        presignature_id: &SessionId,
+        keys: &EcKeyPair,
+        ak: &GE,
+        message: &SpendAuthMessage,
+        store: &mut S,
+    ) -> Result<(PresignedSignRequest, Party1PresignedSignPending), Error> {
+        let presignature = pool
+            .take(presignature_id)
+            .ok_or(Error::protocol(Round::Signing, Check::Presignature))?;
+        record_use(store, &presignature, message)?;
+        let id = presignature.id.clone();
+        let alpha = presignature.alpha;
+        let vk = presignature.vk(ak);
+        let (eph_key, counter_R_i) = bind_nonces(presignature, PARTY_ONE, message);
+        let R = compute_R(&eph_key, &counter_R_i);
+        let local_sig = party_one::LocalSignatureMsg::compute_s1(
+            &R,
+            &vk,
+            keys.clone(),
+            eph_key,
+            message,
+            &alpha,
+        )?;
+        Ok((
+            PresignedSignRequest {
+                presignature_id: id,
+                message: message.clone(),
+                local_sig: local_sig.clone(),
+            },
+            Party1PresignedSignPending {
+                vk,
+                message: message.clone(),
+                R,
+                counter_R_i,
+                counter_ak_i: counter_ak(keys, ak),
+                local_sig,
+            },
+        ))
+    }
+
+    /// party two: consumes the matching presignature, the request must be for the vk party two
+    /// derives from it
+    pub fn accept(
+        &self,
+        pool: &mut PresignaturePool,
+        keys: &EcKeyPair,
+        ak: &GE,
+    ) -> Result<Party2PresignedSignAccepted, Error> {
+        let presignature = pool
+            .take(&self.presignature_id)
+            .ok_or(Error::protocol(Round::Signing, Check::Presignature))?;
+        if self.message.vk() != presignature.vk(ak) {
+            return Err(Error::protocol(Round::Signing, Check::MessageFormat));
+        }
+        Ok(Party2PresignedSignAccepted {
+            keys: keys.clone(),
+            ak: *ak,
+            presignature,
+            request: self.clone(),
+        })
+    }
+}
+
+impl Party2PresignedSignAccepted {
+    /// what a SigningPolicy decides on, the presignature id is the session id
+    pub fn signing_context(
+        &self,
+        caller: &str,
+        details: Option<TransactionDetails>,
+    ) -> SigningContext {
+        SigningContext::new(
+            &self.ak,
+            &self.presignature.id,
+            &self.request.message.vk(),
+            self.request.message.sighash(),
+            &self.presignature.alpha,
+            caller,
+            details,
+        )
+    }
+
+    /// releases s2 once policy accepts, store has not seen the presignature sign before and s1
+    /// checks out. Returns s2 and the signature, a rejection of policy hands self back
+    pub fn respond<P: SigningPolicy + ?Sized, S: NonceStore + ?Sized>(
+        self,
+        policy: &P,
+        store: &mut S,
+        caller: &str,
+        details: Option<TransactionDetails>,
+    ) -> Result<(party_two::LocalSignatureMsg, Signature), Held<Party2PresignedSignAccepted>> {
+        let context = self.signing_context(caller, details);
+        if let Err(rejection) = policy.check(&context) {
+            return Err(Held::back(Error::PolicyRejected(rejection), self));
+        }
+        let message = self.request.message;
+        let vk = message.vk();
+        let counter_pk_i = compute_local_pk(
+            &counter_ak(&self.keys, &self.ak),
+            Some(&self.presignature.alpha),
+        );
+        record_use(store, &self.presignature, &message)?;
+        let (eph_key, counter_R_i) = bind_nonces(self.presignature, PARTY_TWO, &message);
+        let R = compute_R(&eph_key, &counter_R_i);
+        verify_local_share(
+            PARTY_ONE,
+            &self.request.local_sig.s1,
+            &counter_R_i,
+            &counter_pk_i,
+            &R,
+            &message,
+        )?;
+        policy.record(&context);
+        let local_sig =
+            party_two::LocalSignatureMsg::compute_s2(&R, &vk, self.keys, eph_key, &message)?;
+        let sig = combine_local_shares(R, vk, &local_sig.s2, &self.request.local_sig.s1, &message)?;
+        Ok((local_sig, sig))
+    }
+}
+
+impl Party1PresignedSignPending {
+    pub fn finalize(
+        self,
+        party2_local_sig: &party_two::LocalSignatureMsg,
+    ) -> Result<Signature, Error> {
+        party_one::LocalSignatureMsg::compute(
+            self.R,
+            self.vk,
+            &self.local_sig,
+            party2_local_sig,
+            &self.counter_R_i,
+            &self.counter_ak_i,
+            &self.message,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::elliptic::curves::traits::ECScalar;
+    use protocols::two_party::compute_vk;
+    use protocols::two_party::nonce_store::MemoryNonceStore;
+    use protocols::two_party::policy::Policies;
+    use protocols::two_party::presign::{
+        Party1PresignCommitted, Party2PresignCreated, PresignaturePool, PresignedSignRequest,
+    };
+    use protocols::two_party::test::tests::two_party_keygen;
+    use protocols::two_party::{verify, SessionId, SpendAuthMessage};
+    use serde_json;
+    use {Check, Error, Round};
+
+    #[test]
+    fn test_2p_sign_presigned() {
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let mut party1_pool = PresignaturePool::new();
+        let mut party2_pool = PresignaturePool::new();
+        let mut party1_store = MemoryNonceStore::new();
+        let mut party2_store = MemoryNonceStore::new();
+
+        // offline phase: fill both pools
+        for _ in 0..3 {
+            let presign_id = SessionId::new_random();
+            let (party1_first_message, party1_committed) =
+                Party1PresignCommitted::commit(&presign_id);
+            let (party2_first_message, party2_created) =
+                Party2PresignCreated::create(&presign_id, &party1_first_message)
+                    .expect("bad coin flip proof");
+            let (party1_second_message, party1_presignature) = party1_committed
+                .decommit(&party2_first_message)
+                .expect("failed to verify and decommit");
+            let party2_presignature = party2_created
+                .verify(&party1_second_message)
+                .expect("failed to verify commitments and DLog proof");
+            assert_eq!(party1_presignature.alpha(), party2_presignature.alpha());
+            party1_pool.insert(party1_presignature);
+            party2_pool.insert(party2_presignature);
+        }
+
+        // online phase: a single message exchange, both parties derive vk themselves
+        let presign_id = party1_pool.ids()[0].clone();
+        let vk = party1_pool.get(&presign_id).unwrap().vk(&public_key);
+        let message = SpendAuthMessage::new(&vk, &[1u8; 32]).unwrap();
+        let (request, party1_pending) = PresignedSignRequest::create(
+            &mut party1_pool,
+            &presign_id,
+            &party1_keys,
+            &public_key,
+            &message,
+            &mut party1_store,
+        )
+        .expect("bad message");
+        let stored = serde_json::to_string(party2_pool.get(&presign_id).unwrap()).unwrap();
+        let (party2_local_sig, party2_sig) = request
+            .accept(&mut party2_pool, &party2_keys, &public_key)
+            .expect("bad message")
+            .respond(&Policies::new(), &mut party2_store, "", None)
+            .expect("bad signature");
+        let party1_sig = party1_pending
+            .finalize(&party2_local_sig)
+            .expect("bad signature");
+        assert_eq!(party1_sig, party2_sig);
+        assert!(verify(vk, &message, &party1_sig).is_ok());
+
+        // the presignature is gone from both pools
+        assert_eq!(party1_pool.len(), 2);
+        assert_eq!(party2_pool.len(), 2);
+        assert!(request
+            .accept(&mut party2_pool, &party2_keys, &public_key)
+            .is_err());
+
+        // a stored copy put back into the pool does not sign another message
+        party2_pool.insert(serde_json::from_str(&stored).unwrap());
+        let mut replayed = request.clone();
+        replayed.message = SpendAuthMessage::new(&vk, &[2u8; 32]).unwrap();
+        match replayed
+            .accept(&mut party2_pool, &party2_keys, &public_key)
+            .expect("bad message")
+            .respond(&Policies::new(), &mut party2_store, "", None)
+        {
+            Err(e) => assert_eq!(e.error, Error::NonceReuse),
+            Ok(_) => panic!("presignature used twice"),
+        }
+
+        // party two only signs for the vk of its own alpha
+        let presign_id = party1_pool.ids()[0].clone();
+        let other_vk = compute_vk(&public_key, &ECScalar::new_random());
+        let message = SpendAuthMessage::new(&other_vk, &[1u8; 32]).unwrap();
+        let mut request = request.clone();
+        request.presignature_id = presign_id;
+        request.message = message;
+        match request.accept(&mut party2_pool, &party2_keys, &public_key) {
+            Err(e) => assert_eq!(e, Error::protocol(Round::Signing, Check::MessageFormat)),
+            Ok(_) => panic!("message for another vk accepted"),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Conversion to the reference RedJubjub implementation of sapling-crypto.
+//! Signatures and keys are converted through their wire encodings, so a signature that passes
+//! verify here is exactly the spend_auth_sig a Sapling node checks against rk.
+
+use super::{vk_from_bytes, vk_to_bytes, Signature, SpendAuthMessage, SIGNATURE_SIZE};
+use curv::elliptic::curves::curve_jubjub::GE;
+use curv::elliptic::curves::pairing::bls12_381::Bls12;
+use curv::elliptic::curves::sapling_crypto::jubjub::{FixedGenerators, JubjubBls12};
+use curv::elliptic::curves::sapling_crypto::redjubjub;
+use Error::{self, InvalidSig};
+
+pub fn signature_to_reference(sig: &Signature) -> redjubjub::Signature {
+    // reading 64 bytes cannot fail, the reference checks R and s only when verifying
+    redjubjub::Signature::read(&sig.to_bytes()[..]).expect("signature is 64 bytes")
+}
+
+pub fn signature_from_reference(sig: &redjubjub::Signature) -> Result<Signature, Error> {
+    let mut bytes = Vec::with_capacity(SIGNATURE_SIZE);
+    sig.write(&mut bytes).map_err(|_| Error::InvalidEncoding)?;
+    Signature::from_bytes(&bytes)
+}
+
+pub fn vk_to_reference(vk: &GE) -> Result<redjubjub::PublicKey<Bls12>, Error> {
+    let params = &JubjubBls12::new();
+    redjubjub::PublicKey::read(&vk_to_bytes(vk)[..], params).map_err(|_| Error::InvalidKey)
+}
+
+pub fn vk_from_reference(vk: &redjubjub::PublicKey<Bls12>) -> Result<GE, Error> {
+    let mut bytes = vec![];
+    vk.write(&mut bytes).map_err(|_| Error::InvalidEncoding)?;
+    vk_from_bytes(&bytes)
+}
+
+/// same as two_party::verify, but checked by the reference implementation
+pub fn verify(vk: &GE, message: &SpendAuthMessage, sig: &Signature) -> Result<(), Error> {
+    if &message.vk() != vk {
+        return Err(InvalidSig);
+    }
+    let params = &JubjubBls12::new();
+    let valid = vk_to_reference(vk)?.verify(
+        &message.to_bytes(),
+        &signature_to_reference(sig),
+        FixedGenerators::SpendingKeyGenerator,
+        params,
+    );
+    match valid {
+        true => Ok(()),
+        false => Err(InvalidSig),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::arithmetic::big_gmp::BigInt;
+    use curv::elliptic::curves::curve_jubjub::{FE, GE};
+    use curv::elliptic::curves::pairing::bls12_381::Bls12;
+    use curv::elliptic::curves::rand::{thread_rng, Rng};
+    use curv::elliptic::curves::sapling_crypto::jubjub::{FixedGenerators, JubjubBls12};
+    use curv::elliptic::curves::sapling_crypto::redjubjub::{PrivateKey, PublicKey};
+    use curv::elliptic::curves::traits::{ECPoint, ECScalar};
+    use protocols::two_party::compute_vk;
+    use protocols::two_party::redjubjub;
+    use protocols::two_party::test::tests::{
+        two_party_keygen, two_party_sign_sighash, two_party_sign_with_alpha,
+    };
+    use protocols::two_party::{verify, NonceMode, SpendAuthMessage};
+    use {Check, Error, Round};
+
+    // signatures checked against sapling-crypto per differential test
+    const REFERENCE_ITERATIONS: usize = 64;
+
+    #[test]
+    fn test_2p_sign_verifies_under_reference() {
+        let mut rng = thread_rng();
+        for _ in 0..REFERENCE_ITERATIONS {
+            let (party1_keys, party2_keys, ak) = two_party_keygen();
+            let mut sighash = [0u8; 32];
+            rng.fill_bytes(&mut sighash);
+            let (message, sig) =
+                two_party_sign_sighash(party1_keys, party2_keys, ak, &sighash, NonceMode::Hedged)
+                    .expect("signing failed");
+            let vk = message.vk();
+            assert_eq!(verify(vk, &message, &sig), Ok(()));
+            assert_eq!(redjubjub::verify(&vk, &message, &sig), Ok(()));
+
+            let reference_sig = redjubjub::signature_to_reference(&sig);
+            assert_eq!(
+                redjubjub::signature_from_reference(&reference_sig),
+                Ok(sig.clone())
+            );
+            let reference_vk = redjubjub::vk_to_reference(&vk).expect("bad vk");
+            assert_eq!(redjubjub::vk_from_reference(&reference_vk), Ok(vk));
+
+            // both reject a signature over another sighash
+            sighash[0] ^= 1;
+            let other_message = SpendAuthMessage::new(&vk, &sighash).unwrap();
+            assert_eq!(verify(vk, &other_message, &sig), Err(Error::InvalidSig));
+            assert_eq!(
+                redjubjub::verify(&vk, &other_message, &sig),
+                Err(Error::InvalidSig)
+            );
+        }
+    }
+
+    #[test]
+    fn test_reference_sign_verifies() {
+        let params = &JubjubBls12::new();
+        let p_g = FixedGenerators::SpendingKeyGenerator;
+        let mut rng = thread_rng();
+        for _ in 0..REFERENCE_ITERATIONS {
+            let ask: FE = ECScalar::new_random();
+            let private_key = PrivateKey::<Bls12>(ask.get_element());
+            let reference_vk = PublicKey::from_private(&private_key, p_g, params);
+            let vk = redjubjub::vk_from_reference(&reference_vk).expect("bad reference vk");
+            assert_eq!(vk, GE::generator() * &ask);
+
+            let mut sighash = [0u8; 32];
+            rng.fill_bytes(&mut sighash);
+            let message = SpendAuthMessage::new(&vk, &sighash).unwrap();
+            let reference_sig = private_key.sign(&message.to_bytes(), &mut rng, p_g, params);
+            let sig = redjubjub::signature_from_reference(&reference_sig)
+                .expect("bad reference signature");
+            assert_eq!(verify(vk, &message, &sig), Ok(()));
+            assert_eq!(redjubjub::verify(&vk, &message, &sig), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_2p_sign_reference_edge_cases() {
+        let params = &JubjubBls12::new();
+        let p_g = FixedGenerators::SpendingKeyGenerator;
+        let mut rng = thread_rng();
+        let (party1_keys, party2_keys, ak) = two_party_keygen();
+        let private_key = PrivateKey::<Bls12>((party1_keys.ask + &party2_keys.ask).get_element());
+
+        let zero: FE = ECScalar::zero();
+        let one: FE = ECScalar::from(&BigInt::one());
+        let max: FE = ECScalar::from(&(FE::q() - BigInt::one()));
+        let mut random_sighash = [0u8; 32];
+        rng.fill_bytes(&mut random_sighash);
+        for alpha in &[zero, one, max, ECScalar::new_random()] {
+            // rerandomization agrees with the reference, alpha = 0 keeps ak
+            let randomized_key = private_key.randomize(alpha.get_element());
+            let reference_vk = PublicKey::from_private(&randomized_key, p_g, params);
+            let vk = compute_vk(&ak, alpha);
+            assert_eq!(redjubjub::vk_from_reference(&reference_vk), Ok(vk));
+            if *alpha == zero {
+                assert_eq!(vk, ak);
+            }
+
+            for sighash in &[[0u8; 32], [0xffu8; 32], random_sighash] {
+                let (message, sig) = two_party_sign_with_alpha(
+                    party1_keys.clone(),
+                    party2_keys.clone(),
+                    ak,
+                    alpha,
+                    sighash,
+                )
+                .expect("signing failed");
+                assert_eq!(message.vk(), vk);
+                assert_eq!(verify(vk, &message, &sig), Ok(()));
+                assert_eq!(redjubjub::verify(&vk, &message, &sig), Ok(()));
+
+                let reference_sig = randomized_key.sign(&message.to_bytes(), &mut rng, p_g, params);
+                let sig = redjubjub::signature_from_reference(&reference_sig)
+                    .expect("bad reference signature");
+                assert_eq!(verify(vk, &message, &sig), Ok(()));
+            }
+
+            // there is no message over an empty sighash to sign or verify
+            assert_eq!(
+                SpendAuthMessage::new(&vk, &[]),
+                Err(Error::protocol(Round::Signing, Check::MessageFormat))
+            );
+            assert_eq!(
+                two_party_sign_with_alpha(party1_keys.clone(), party2_keys.clone(), ak, alpha, &[])
+                    .map(|_| ()),
+                Err(Error::protocol(Round::Signing, Check::MessageFormat))
+            );
+        }
+    }
+}
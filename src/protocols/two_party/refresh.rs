@@ -0,0 +1,592 @@
+#![allow(non_snake_case)]
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Proactive refresh of the two ask shares.
+//! The parties flip a coin for delta, party one moves to ask1 + delta and party two to
+//! ask2 - delta, and each proves knowledge of its new share with a DLogProof. The new public
+//! shares still add up to ak, while an old share combined with a new one is useless.
+//!
+//! The refresh never touches the shares the caller already holds: every step works on a copy, and
+//! a party replaces its stored share only with the EcKeyPair returned at the end. The switch to
+//! the new shares is a two phase commit, so that a lost message never leaves one party with only
+//! a new share and the other with only an old one:
+//!
+//! 1. party one checks the proof of party two and sends a confirmation
+//! 2. party two answers with an acknowledgement, from now on it keeps its pending state
+//! 3. party one switches to its new share and sends the switch message
+//! 4. party two switches to its new share
+//!
+//! Each of the three messages carries a DLogProof of the new share of its sender under its own
+//! round label, so it can not be forged or replayed from another message. A party keeps its old
+//! share until the other one holds the new one: up to step 2 aborting is just dropping the state,
+//! after it party two keeps its pending state until the switch message arrives. The pending states
+//! are serializable and store the message they sent last, so a lost message is sent again.
+//!
+//! Note: the refreshed share of party one is not below q/3 anymore. Resampling delta until it is
+//! would leak information on ask1, so the bound only holds for freshly generated keys.
+
+use super::party_one;
+use super::{compute_ak, EcKeyPair, KeyGenRole, SessionId};
+use super::{PARTY_ONE, PARTY_TWO, REFRESH_CONFIRM_ROUND, REFRESH_ROUND, REFRESH_SWITCH_ROUND};
+use curv::cryptographic_primitives::proofs::sigma_dlog::DLogProof;
+use curv::cryptographic_primitives::proofs::sigma_dlog::ProveDLog;
+use curv::cryptographic_primitives::twoparty::coin_flip_optimal_rounds;
+use curv::elliptic::curves::curve_jubjub::FE;
+use curv::elliptic::curves::curve_jubjub::GE;
+use curv::elliptic::curves::traits::{ECPoint, ECScalar};
+use {Check, Error, Round};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Party1RefreshFirstMsg {
+    pub cf_msg1: coin_flip_optimal_rounds::Party1FirstMessage,
+    /// keygen role of the sender, the counterparty must have the other one
+    pub role: KeyGenRole,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Party2RefreshFirstMsg {
+    pub cf_msg1: coin_flip_optimal_rounds::Party2FirstMessage,
+    /// keygen role of the sender, the counterparty must have the other one
+    pub role: KeyGenRole,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Party1RefreshSecondMsg {
+    pub cf_msg2: coin_flip_optimal_rounds::Party1SecondMessage,
+    pub d_log_proof: DLogProof,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Party2RefreshSecondMsg {
+    pub d_log_proof: DLogProof,
+}
+
+/// party one checked the new share of party two
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Party1RefreshConfirmMsg {
+    pub d_log_proof: DLogProof,
+}
+
+/// party two holds its new share and waits for the switch
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Party2RefreshAckMsg {
+    pub d_log_proof: DLogProof,
+}
+
+/// party one switched to its new share
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Party1RefreshSwitchMsg {
+    pub d_log_proof: DLogProof,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1RefreshCommitted {
+    keys: EcKeyPair,
+    ak: GE,
+    session_id: SessionId,
+    witness: party_one::CoinFlipWitness,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1RefreshRevealed {
+    new_keys: EcKeyPair,
+    ak: GE,
+    session_id: SessionId,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2RefreshShared {
+    keys: EcKeyPair,
+    ak: GE,
+    session_id: SessionId,
+    party1_first_msg: Party1RefreshFirstMsg,
+    cf_msg1: coin_flip_optimal_rounds::Party2FirstMessage,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1RefreshPending {
+    new_keys: EcKeyPair,
+    counter_ak: GE,
+    session_id: SessionId,
+    confirm_msg: Party1RefreshConfirmMsg,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2RefreshPending {
+    new_keys: EcKeyPair,
+    counter_ak: GE,
+    session_id: SessionId,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2RefreshAcknowledged {
+    new_keys: EcKeyPair,
+    counter_ak: GE,
+    session_id: SessionId,
+    ack_msg: Party2RefreshAckMsg,
+}
+
+fn refreshed_key_pair(ask: FE) -> EcKeyPair {
+    let base: GE = ECPoint::generator();
+    EcKeyPair {
+        ak: base * &ask,
+        ask,
+    }
+}
+
+// party one adds delta and party two subtracts it, so the counterparty must have the other role
+fn check_counterparty(role: KeyGenRole, counter_role: KeyGenRole) -> Result<(), Error> {
+    role.check_counterparty(counter_role)
+        .map_err(|_| Error::protocol(Round::Refresh, Check::KeyGenRole))
+}
+
+// checks a confirmation message of the counterparty: a proof for its new share under round
+fn verify_new_share(
+    d_log_proof: &DLogProof,
+    counter_ak: &GE,
+    session_id: &SessionId,
+    party: u8,
+    round: &[u8],
+) -> Result<(), Error> {
+    DLogProof::verify(d_log_proof, &session_id.transcript(party, round))
+        .map_err(|_| Error::protocol(Round::Refresh, Check::DLogProof))?;
+    match d_log_proof.pk == *counter_ak {
+        true => Ok(()),
+        false => Err(Error::protocol(Round::Refresh, Check::PublicShare)),
+    }
+}
+
+impl Party1RefreshCommitted {
+    pub fn commit(
+        keys: &EcKeyPair,
+        ak: &GE,
+        session_id: &SessionId,
+    ) -> (Party1RefreshFirstMsg, Party1RefreshCommitted) {
+        let transcript = session_id.transcript(PARTY_ONE, REFRESH_ROUND);
+        let (cf_msg1, seed, blinding) =
+            coin_flip_optimal_rounds::Party1FirstMessage::commit(&transcript);
+        (
+            Party1RefreshFirstMsg {
+                cf_msg1,
+                role: KeyGenRole::PartyOne,
+            },
+            Party1RefreshCommitted {
+                keys: keys.clone(),
+                ak: *ak,
+                session_id: session_id.clone(),
+                witness: party_one::CoinFlipWitness {
+                    seed,
+                    blinding,
+                    role: KeyGenRole::PartyOne,
+                },
+            },
+        )
+    }
+
+    /// refuses to reveal if party two claims the keygen role of party one
+    pub fn reveal(
+        self,
+        party2_first_msg: &Party2RefreshFirstMsg,
+    ) -> Result<(Party1RefreshSecondMsg, Party1RefreshRevealed), Error> {
+        check_counterparty(self.witness.role, party2_first_msg.role)?;
+        let transcript = self.session_id.transcript(PARTY_ONE, REFRESH_ROUND);
+        let (cf_msg2, delta) = coin_flip_optimal_rounds::Party1SecondMessage::reveal(
+            &party2_first_msg.cf_msg1.seed,
+            &self.witness.seed,
+            &self.witness.blinding,
+            &transcript,
+        );
+        let new_keys = refreshed_key_pair(self.keys.ask + delta);
+        let d_log_proof = DLogProof::prove(&new_keys.ask, &transcript);
+        Ok((
+            Party1RefreshSecondMsg {
+                cf_msg2,
+                d_log_proof,
+            },
+            Party1RefreshRevealed {
+                new_keys,
+                ak: self.ak,
+                session_id: self.session_id,
+            },
+        ))
+    }
+}
+
+impl Party1RefreshRevealed {
+    /// checks the new share of party two, nothing is switched yet
+    pub fn finalize(
+        self,
+        party2_second_msg: &Party2RefreshSecondMsg,
+    ) -> Result<(Party1RefreshConfirmMsg, Party1RefreshPending), Error> {
+        let transcript = self.session_id.transcript(PARTY_TWO, REFRESH_ROUND);
+        DLogProof::verify(&party2_second_msg.d_log_proof, &transcript)
+            .map_err(|_| Error::protocol(Round::Refresh, Check::DLogProof))?;
+        let counter_ak = party2_second_msg.d_log_proof.pk;
+        if compute_ak(&self.new_keys, &counter_ak) != self.ak {
+            return Err(Error::protocol(Round::Refresh, Check::PublicShare));
+        }
+        let confirm_msg = Party1RefreshConfirmMsg {
+            d_log_proof: DLogProof::prove(
+                &self.new_keys.ask,
+                &self.session_id.transcript(PARTY_ONE, REFRESH_CONFIRM_ROUND),
+            ),
+        };
+        Ok((
+            confirm_msg.clone(),
+            Party1RefreshPending {
+                new_keys: self.new_keys,
+                counter_ak,
+                session_id: self.session_id,
+                confirm_msg,
+            },
+        ))
+    }
+}
+
+impl Party1RefreshPending {
+    /// to send again while the acknowledgement of party two is missing
+    pub fn confirm_msg(&self) -> Party1RefreshConfirmMsg {
+        self.confirm_msg.clone()
+    }
+
+    /// party two holds its new share: returns the switch message and the refreshed share, to be
+    /// stored in place of the old one
+    pub fn switch(
+        self,
+        party2_ack_msg: &Party2RefreshAckMsg,
+    ) -> Result<(Party1RefreshSwitchMsg, EcKeyPair), Error> {
+        verify_new_share(
+            &party2_ack_msg.d_log_proof,
+            &self.counter_ak,
+            &self.session_id,
+            PARTY_TWO,
+            REFRESH_CONFIRM_ROUND,
+        )?;
+        let d_log_proof = DLogProof::prove(
+            &self.new_keys.ask,
+            &self.session_id.transcript(PARTY_ONE, REFRESH_SWITCH_ROUND),
+        );
+        Ok((Party1RefreshSwitchMsg { d_log_proof }, self.new_keys))
+    }
+}
+
+impl Party2RefreshShared {
+    /// refuses to share if party one claims the keygen role of party two
+    pub fn share(
+        keys: &EcKeyPair,
+        ak: &GE,
+        session_id: &SessionId,
+        party1_first_msg: &Party1RefreshFirstMsg,
+    ) -> Result<(Party2RefreshFirstMsg, Party2RefreshShared), Error> {
+        check_counterparty(KeyGenRole::PartyTwo, party1_first_msg.role)?;
+        let transcript = session_id.transcript(PARTY_ONE, REFRESH_ROUND);
+        let cf_msg1 = coin_flip_optimal_rounds::Party2FirstMessage::share(
+            &party1_first_msg.cf_msg1.proof,
+            &transcript,
+        )
+        .map_err(|_| Error::protocol(Round::Refresh, Check::PedersenProof))?;
+        Ok((
+            Party2RefreshFirstMsg {
+                cf_msg1: cf_msg1.clone(),
+                role: KeyGenRole::PartyTwo,
+            },
+            Party2RefreshShared {
+                keys: keys.clone(),
+                ak: *ak,
+                session_id: session_id.clone(),
+                party1_first_msg: party1_first_msg.clone(),
+                cf_msg1,
+            },
+        ))
+    }
+
+    pub fn verify(
+        self,
+        party1_second_msg: &Party1RefreshSecondMsg,
+    ) -> Result<(Party2RefreshSecondMsg, Party2RefreshPending), Error> {
+        let transcript = self.session_id.transcript(PARTY_ONE, REFRESH_ROUND);
+        if party1_second_msg.cf_msg2.proof.com != self.party1_first_msg.cf_msg1.proof.com {
+            return Err(Error::protocol(Round::Refresh, Check::CoinFlipCommitment));
+        }
+        let delta = coin_flip_optimal_rounds::finalize(
+            &party1_second_msg.cf_msg2.proof,
+            &self.cf_msg1.seed,
+            &self.party1_first_msg.cf_msg1.proof.com,
+            &transcript,
+        )
+        .map_err(|_| Error::protocol(Round::Refresh, Check::PedersenBlindingProof))?;
+        DLogProof::verify(&party1_second_msg.d_log_proof, &transcript)
+            .map_err(|_| Error::protocol(Round::Refresh, Check::DLogProof))?;
+
+        let new_keys = refreshed_key_pair(self.keys.ask.sub(&delta.get_element()));
+        if compute_ak(&new_keys, &party1_second_msg.d_log_proof.pk) != self.ak {
+            return Err(Error::protocol(Round::Refresh, Check::PublicShare));
+        }
+        let d_log_proof = DLogProof::prove(
+            &new_keys.ask,
+            &self.session_id.transcript(PARTY_TWO, REFRESH_ROUND),
+        );
+        Ok((
+            Party2RefreshSecondMsg { d_log_proof },
+            Party2RefreshPending {
+                new_keys,
+                counter_ak: party1_second_msg.d_log_proof.pk,
+                session_id: self.session_id,
+            },
+        ))
+    }
+}
+
+impl Party2RefreshPending {
+    /// from here on the pending state is kept until the switch message of party one arrives
+    pub fn confirm(
+        self,
+        party1_confirm_msg: &Party1RefreshConfirmMsg,
+    ) -> Result<(Party2RefreshAckMsg, Party2RefreshAcknowledged), Error> {
+        verify_new_share(
+            &party1_confirm_msg.d_log_proof,
+            &self.counter_ak,
+            &self.session_id,
+            PARTY_ONE,
+            REFRESH_CONFIRM_ROUND,
+        )?;
+        let ack_msg = Party2RefreshAckMsg {
+            d_log_proof: DLogProof::prove(
+                &self.new_keys.ask,
+                &self.session_id.transcript(PARTY_TWO, REFRESH_CONFIRM_ROUND),
+            ),
+        };
+        Ok((
+            ack_msg.clone(),
+            Party2RefreshAcknowledged {
+                new_keys: self.new_keys,
+                counter_ak: self.counter_ak,
+                session_id: self.session_id,
+                ack_msg,
+            },
+        ))
+    }
+}
+
+impl Party2RefreshAcknowledged {
+    /// to send again when party one repeats its confirmation
+    pub fn ack_msg(&self) -> Party2RefreshAckMsg {
+        self.ack_msg.clone()
+    }
+
+    /// returns the refreshed share, to be stored in place of the old one
+    pub fn switch(self, party1_switch_msg: &Party1RefreshSwitchMsg) -> Result<EcKeyPair, Error> {
+        verify_new_share(
+            &party1_switch_msg.d_log_proof,
+            &self.counter_ak,
+            &self.session_id,
+            PARTY_ONE,
+            REFRESH_SWITCH_ROUND,
+        )?;
+        Ok(self.new_keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::cryptographic_primitives::proofs::sigma_dlog::{DLogProof, ProveDLog};
+    use protocols::two_party::compute_ak;
+    use protocols::two_party::party_two::KeyGenFirstMsg as Party2KeyGenFirstMsg;
+    use protocols::two_party::refresh::{
+        Party1RefreshCommitted, Party1RefreshConfirmMsg, Party1RefreshSwitchMsg,
+        Party2RefreshAcknowledged, Party2RefreshPending, Party2RefreshShared,
+    };
+    use protocols::two_party::test::tests::{two_party_keygen, two_party_sign};
+    use protocols::two_party::{KeyGenRole, SessionId, PARTY_ONE, PARTY_TWO};
+    use serde_json;
+    use {Check, Error, Round};
+
+    #[test]
+    fn test_2p_refresh() {
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let session_id = SessionId::new_random();
+
+        let (party1_first_message, party1_committed) =
+            Party1RefreshCommitted::commit(&party1_keys, &public_key, &session_id);
+        let (party2_first_message, party2_shared) = Party2RefreshShared::share(
+            &party2_keys,
+            &public_key,
+            &session_id,
+            &party1_first_message,
+        )
+        .expect("bad coin flip proof");
+        let (party1_second_message, party1_revealed) = party1_committed
+            .reveal(&party2_first_message)
+            .expect("bad keygen role");
+        let (party2_second_message, party2_pending) = party2_shared
+            .verify(&party1_second_message)
+            .expect("failed to verify refreshed share");
+        let (party1_confirm_message, party1_pending) = party1_revealed
+            .finalize(&party2_second_message)
+            .expect("failed to verify refreshed share");
+        let (party2_ack_message, party2_acknowledged) = party2_pending
+            .confirm(&party1_confirm_message)
+            .expect("bad confirmation");
+        let (party1_switch_message, party1_new_keys) = party1_pending
+            .switch(&party2_ack_message)
+            .expect("bad acknowledgement");
+        let party2_new_keys = party2_acknowledged
+            .switch(&party1_switch_message)
+            .expect("bad switch message");
+
+        assert!(party1_new_keys.ask != party1_keys.ask);
+        assert!(party2_new_keys.ask != party2_keys.ask);
+        assert_eq!(
+            compute_ak(&party1_new_keys, &party2_new_keys.ak),
+            public_key
+        );
+        assert!(
+            two_party_sign(party1_new_keys.clone(), party2_new_keys.clone(), public_key).is_ok()
+        );
+        // an old share is useless together with a new one
+        assert!(compute_ak(&party1_keys, &party2_new_keys.ak) != public_key);
+        assert!(two_party_sign(party1_keys, party2_new_keys, public_key).is_err());
+    }
+
+    #[test]
+    fn test_2p_refresh_abort() {
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let session_id = SessionId::new_random();
+
+        let (party1_first_message, party1_committed) =
+            Party1RefreshCommitted::commit(&party1_keys, &public_key, &session_id);
+        let (party2_first_message, party2_shared) = Party2RefreshShared::share(
+            &party2_keys,
+            &public_key,
+            &session_id,
+            &party1_first_message,
+        )
+        .expect("bad coin flip proof");
+        // neither side goes on with a counterparty that claims its own keygen role
+        let mut bad_first_message = party1_first_message.clone();
+        bad_first_message.role = KeyGenRole::PartyTwo;
+        match Party2RefreshShared::share(&party2_keys, &public_key, &session_id, &bad_first_message)
+        {
+            Err(e) => assert_eq!(e, Error::protocol(Round::Refresh, Check::KeyGenRole)),
+            Ok(_) => panic!("refresh with the same keygen role accepted"),
+        }
+        let mut bad_first_message = party2_first_message.clone();
+        bad_first_message.role = KeyGenRole::PartyOne;
+        let party1_committed = serde_json::to_string(&party1_committed).unwrap();
+        let party1_committed =
+            || serde_json::from_str::<Party1RefreshCommitted>(&party1_committed).unwrap();
+        match party1_committed().reveal(&bad_first_message) {
+            Err(e) => assert_eq!(e, Error::protocol(Round::Refresh, Check::KeyGenRole)),
+            Ok(_) => panic!("refresh with the same keygen role accepted"),
+        }
+        let (party1_second_message, party1_revealed) = party1_committed()
+            .reveal(&party2_first_message)
+            .expect("bad keygen role");
+        let (mut party2_second_message, _party2_pending) = party2_shared
+            .verify(&party1_second_message)
+            .expect("failed to verify refreshed share");
+        // party two sends a proof for a share that does not add up to ak
+        let (_, bad_keys) = Party2KeyGenFirstMsg::create(&session_id);
+        party2_second_message.d_log_proof =
+            DLogProof::prove(&bad_keys.ask, &session_id.transcript(PARTY_TWO, b"refresh"));
+        match party1_revealed.finalize(&party2_second_message) {
+            Err(e) => assert_eq!(e, Error::protocol(Round::Refresh, Check::PublicShare)),
+            Ok(_) => panic!("refresh with a bad public share accepted"),
+        }
+        // aborting leaves the old shares usable
+        assert!(two_party_sign(party1_keys, party2_keys, public_key).is_ok());
+    }
+
+    #[test]
+    fn test_2p_refresh_lost_messages() {
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let session_id = SessionId::new_random();
+
+        let (party1_first_message, party1_committed) =
+            Party1RefreshCommitted::commit(&party1_keys, &public_key, &session_id);
+        let (party2_first_message, party2_shared) = Party2RefreshShared::share(
+            &party2_keys,
+            &public_key,
+            &session_id,
+            &party1_first_message,
+        )
+        .expect("bad coin flip proof");
+        let (party1_second_message, party1_revealed) = party1_committed
+            .reveal(&party2_first_message)
+            .expect("bad keygen role");
+        let (party2_second_message, party2_pending) = party2_shared
+            .verify(&party1_second_message)
+            .expect("failed to verify refreshed share");
+        let (party1_confirm_message, party1_pending) = party1_revealed
+            .finalize(&party2_second_message)
+            .expect("failed to verify refreshed share");
+        let party2_pending = serde_json::to_string(&party2_pending).unwrap();
+        let party2_pending =
+            || serde_json::from_str::<Party2RefreshPending>(&party2_pending).unwrap();
+
+        // the confirmation can neither be forged nor replayed from another round
+        let (_, bad_keys) = Party2KeyGenFirstMsg::create(&session_id);
+        let forged = Party1RefreshConfirmMsg {
+            d_log_proof: DLogProof::prove(
+                &bad_keys.ask,
+                &session_id.transcript(PARTY_ONE, b"refresh_confirm"),
+            ),
+        };
+        match party2_pending().confirm(&forged) {
+            Err(e) => assert_eq!(e, Error::protocol(Round::Refresh, Check::PublicShare)),
+            Ok(_) => panic!("forged confirmation accepted"),
+        }
+        let replayed = Party1RefreshConfirmMsg {
+            d_log_proof: party1_second_message.d_log_proof.clone(),
+        };
+        match party2_pending().confirm(&replayed) {
+            Err(e) => assert_eq!(e, Error::protocol(Round::Refresh, Check::DLogProof)),
+            Ok(_) => panic!("replayed confirmation accepted"),
+        }
+
+        // the confirmation is lost, party one sends it again
+        assert_eq!(
+            party1_pending.confirm_msg().d_log_proof.pk,
+            party1_confirm_message.d_log_proof.pk
+        );
+        let (_, party2_acknowledged) = party2_pending()
+            .confirm(&party1_pending.confirm_msg())
+            .expect("bad confirmation");
+        // the acknowledgement is lost: nobody switched, the old shares still sign
+        assert!(two_party_sign(party1_keys, party2_keys.clone(), public_key).is_ok());
+        let (party1_switch_message, party1_new_keys) = party1_pending
+            .switch(&party2_acknowledged.ack_msg())
+            .expect("bad acknowledgement");
+
+        // party one switched, party two keeps its pending state until the switch message arrives
+        let replayed = Party1RefreshSwitchMsg {
+            d_log_proof: party1_confirm_message.d_log_proof.clone(),
+        };
+        let party2_acknowledged = serde_json::to_string(&party2_acknowledged).unwrap();
+        let party2_acknowledged =
+            || serde_json::from_str::<Party2RefreshAcknowledged>(&party2_acknowledged).unwrap();
+        match party2_acknowledged().switch(&replayed) {
+            Err(e) => assert_eq!(e, Error::protocol(Round::Refresh, Check::DLogProof)),
+            Ok(_) => panic!("replayed switch message accepted"),
+        }
+        let party2_new_keys = party2_acknowledged()
+            .switch(&party1_switch_message)
+            .expect("bad switch message");
+        assert!(two_party_sign(party1_new_keys, party2_new_keys, public_key).is_ok());
+        assert!(compute_ak(&party2_keys, &party1_switch_message.d_log_proof.pk) != public_key);
+    }
+}
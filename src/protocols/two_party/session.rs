@@ -0,0 +1,1158 @@
+#![allow(non_snake_case)]
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Typestate wrappers around the signing rounds of party_one and party_two.
+//! Every round method consumes the session and returns the outbound message together with the
+//! session in its next state, so rounds cannot be called out of order and the ephemeral key of a
+//! session is moved into exactly one local signature.
+//!
+//! Party1SigningSession is the initiator: it commits first in the coin flip and the ephemeral
+//! keygen. Party2SigningSession is the responder. Either keygen party can take either side by
+//! starting with `with_role`, alpha then goes to the share of keygen party one. Both coin flip
+//! messages carry the keygen role of their sender, and a session refuses to go on with a
+//! counterparty that claims its own role. `new` keeps the keygen roles, party one initiates. The
+//! ephemeral secret of each side is derived as chosen by `with_nonce_mode`, NonceMode::Hedged
+//! unless set otherwise.
+//!
+//! The ephemeral keygen and the local signature go through a NonceStore, which records the
+//! ephemeral key and refuses to use it twice, see the nonce_store module: party one runs
+//! eph_keygen_commit_guarded and local_sign_guarded, party two eph_keygen_create_guarded and
+//! local_sign_with_policy.
+//!
+//! Party two releases s2 only through local_sign_with_policy, which runs its SigningPolicy on the
+//! signing_context of the session first, see the policy module. A rejection hands the session
+//! back, so a request waiting for an operator is retried on it. The variants without a store or
+//! a policy are only open to this crate, e.g. for the Cosigner, which runs its policy itself.
+//!
+//! Keygen is wrapped the same way by Party1KeyGenCommitted and Party2KeyGenCreated. All states are
+//! serializable, see the state module for storing them between requests.
+
+use super::nonce_store::NonceStore;
+use super::party_one;
+use super::party_two;
+use super::policy::{Held, SigningContext, SigningPolicy, TransactionDetails};
+use super::SpendAuthMessage;
+use super::{combine_local_shares, compute_local_pk, compute_local_share, verify_local_share};
+use super::{compute_R, compute_ak, compute_vk};
+use super::{EcKeyPair, EphEcKeyPair, KeyGenRole, NonceMode, SessionId, Signature};
+use curv::elliptic::curves::curve_jubjub::FE;
+use curv::elliptic::curves::curve_jubjub::GE;
+use curv::elliptic::curves::traits::ECPoint;
+use Error;
+
+// alpha is added to the share of keygen party one only, whichever side it signs on
+fn alpha_share(role: KeyGenRole, alpha: &FE) -> Option<&FE> {
+    match role {
+        KeyGenRole::PartyOne => Some(alpha),
+        KeyGenRole::PartyTwo => None,
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1KeyGenCommitted {
+    session_id: SessionId,
+    comm_witness: party_one::CommWitness,
+    keys: EcKeyPair,
+}
+
+impl Party1KeyGenCommitted {
+    pub fn commit(session_id: &SessionId) -> (party_one::KeyGenFirstMsg, Party1KeyGenCommitted) {
+        let (first_msg, comm_witness, keys) =
+            party_one::KeyGenFirstMsg::create_commitments(session_id);
+        (
+            first_msg,
+            Party1KeyGenCommitted {
+                session_id: session_id.clone(),
+                comm_witness,
+                keys,
+            },
+        )
+    }
+
+    /// returns the share of party one and ak
+    pub fn decommit(
+        self,
+        party2_first_msg: &party_two::KeyGenFirstMsg,
+    ) -> Result<(party_one::KeyGenSecondMsg, EcKeyPair, GE), Error> {
+        let second_msg = party_one::KeyGenSecondMsg::verify_and_decommit(
+            self.comm_witness,
+            party2_first_msg,
+            &self.session_id,
+        )?;
+        let ak = compute_ak(&self.keys, &party2_first_msg.public_share);
+        Ok((second_msg, self.keys, ak))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2KeyGenCreated {
+    session_id: SessionId,
+    party1_first_msg: party_one::KeyGenFirstMsg,
+    keys: EcKeyPair,
+}
+
+impl Party2KeyGenCreated {
+    pub fn create(
+        party1_first_msg: &party_one::KeyGenFirstMsg,
+        session_id: &SessionId,
+    ) -> (party_two::KeyGenFirstMsg, Party2KeyGenCreated) {
+        let (first_msg, keys) = party_two::KeyGenFirstMsg::create(session_id);
+        (
+            first_msg,
+            Party2KeyGenCreated {
+                session_id: session_id.clone(),
+                party1_first_msg: party1_first_msg.clone(),
+                keys,
+            },
+        )
+    }
+
+    /// returns the share of party two and ak
+    pub fn verify(
+        self,
+        party1_second_msg: &party_one::KeyGenSecondMsg,
+    ) -> Result<(EcKeyPair, GE), Error> {
+        party_two::KeyGenSecondMsg::verify_commitments_and_dlog_proof(
+            &self.party1_first_msg,
+            party1_second_msg,
+            &self.session_id,
+        )?;
+        let ak = compute_ak(&self.keys, &party1_second_msg.comm_witness.public_share);
+        Ok((self.keys, ak))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1SigningSession<S> {
+    keys: EcKeyPair,
+    ak: GE,
+    // keygen public share of the counterparty
+    counter_ak: GE,
+    role: KeyGenRole,
+    nonce_mode: NonceMode,
+    session_id: SessionId,
+    state: S,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1Start;
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1CoinFlipCommitted {
+    witness: party_one::CoinFlipWitness,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1CoinFlipDone {
+    alpha: FE,
+    vk: GE,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1EphKeyGenCommitted {
+    alpha: FE,
+    vk: GE,
+    message: SpendAuthMessage,
+    comm_witness: party_one::EphCommWitness,
+    eph_keys: EphEcKeyPair,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1EphKeyGenDone {
+    alpha: FE,
+    vk: GE,
+    message: SpendAuthMessage,
+    eph_keys: EphEcKeyPair,
+    R: GE,
+    counter_R_i: GE,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party1LocalSigned {
+    vk: GE,
+    message: SpendAuthMessage,
+    R: GE,
+    counter_R_i: GE,
+    counter_pk_i: GE,
+    local_sig: party_one::LocalSignatureMsg,
+}
+
+impl Party1SigningSession<Party1Start> {
+    pub fn new(
+        keys: EcKeyPair,
+        ak: GE,
+        session_id: SessionId,
+    ) -> Party1SigningSession<Party1Start> {
+        Party1SigningSession::with_role(keys, ak, session_id, KeyGenRole::PartyOne)
+    }
+
+    /// starts a session as the initiator with the keys of either keygen party
+    pub fn with_role(
+        keys: EcKeyPair,
+        ak: GE,
+        session_id: SessionId,
+        role: KeyGenRole,
+    ) -> Party1SigningSession<Party1Start> {
+        let counter_ak = ak.sub_point(&keys.ak.get_element());
+        Party1SigningSession {
+            keys,
+            ak,
+            counter_ak,
+            role,
+            nonce_mode: NonceMode::Hedged,
+            session_id,
+            state: Party1Start,
+        }
+    }
+
+    /// sessions start in NonceMode::Hedged
+    pub fn with_nonce_mode(self, nonce_mode: NonceMode) -> Party1SigningSession<Party1Start> {
+        Party1SigningSession { nonce_mode, ..self }
+    }
+
+    pub fn coin_flip_commit(
+        self,
+    ) -> (
+        party_one::CoinFlipFirstMsg,
+        Party1SigningSession<Party1CoinFlipCommitted>,
+    ) {
+        let (cf_first_msg, witness) =
+            party_one::CoinFlipFirstMsg::commit(&self.session_id, self.role);
+        (
+            cf_first_msg,
+            Party1SigningSession {
+                keys: self.keys,
+                ak: self.ak,
+                counter_ak: self.counter_ak,
+                role: self.role,
+                nonce_mode: self.nonce_mode,
+                session_id: self.session_id,
+                state: Party1CoinFlipCommitted { witness },
+            },
+        )
+    }
+}
+
+impl Party1SigningSession<Party1CoinFlipCommitted> {
+    /// fails if the responder claims the same keygen role as this session
+    pub fn coin_flip_reveal(
+        self,
+        party2_cf_first_msg: &party_two::CoinFlipFirstMsg,
+    ) -> Result<
+        (
+            party_one::CoinFlipSecondMsg,
+            Party1SigningSession<Party1CoinFlipDone>,
+        ),
+        Error,
+    > {
+        let (cf_second_msg, alpha) = party_one::CoinFlipSecondMsg::reveal(
+            party2_cf_first_msg,
+            self.state.witness,
+            &self.session_id,
+        )?;
+        let vk = compute_vk(&self.ak, &alpha);
+        Ok((
+            cf_second_msg,
+            Party1SigningSession {
+                keys: self.keys,
+                ak: self.ak,
+                counter_ak: self.counter_ak,
+                role: self.role,
+                nonce_mode: self.nonce_mode,
+                session_id: self.session_id,
+                state: Party1CoinFlipDone { alpha, vk },
+            },
+        ))
+    }
+}
+
+impl Party1SigningSession<Party1CoinFlipDone> {
+    pub fn vk(&self) -> GE {
+        self.state.vk
+    }
+
+    pub fn alpha(&self) -> FE {
+        self.state.alpha
+    }
+
+    // no NonceStore, see eph_keygen_commit_guarded
+    pub(super) fn eph_keygen_commit(
+        self,
+        message: &SpendAuthMessage,
+    ) -> (
+        party_one::EphKeyGenFirstMsg,
+        Party1SigningSession<Party1EphKeyGenCommitted>,
+    ) {
+        let (eph_first_msg, comm_witness, eph_keys) =
+            party_one::EphKeyGenFirstMsg::create_commitments(
+                &self.keys.ask,
+                message,
+                &self.session_id,
+                self.nonce_mode,
+            );
+        (
+            eph_first_msg,
+            Party1SigningSession {
+                keys: self.keys,
+                ak: self.ak,
+                counter_ak: self.counter_ak,
+                role: self.role,
+                nonce_mode: self.nonce_mode,
+                session_id: self.session_id,
+                state: Party1EphKeyGenCommitted {
+                    alpha: self.state.alpha,
+                    vk: self.state.vk,
+                    message: message.clone(),
+                    comm_witness,
+                    eph_keys,
+                },
+            },
+        )
+    }
+
+    /// eph_keygen_commit, once store accepts R_1 for this session and message
+    pub fn eph_keygen_commit_guarded<S: NonceStore + ?Sized>(
+        self,
+        message: &SpendAuthMessage,
+        store: &mut S,
+    ) -> Result<
+        (
+            party_one::EphKeyGenFirstMsg,
+            Party1SigningSession<Party1EphKeyGenCommitted>,
+        ),
+        Error,
+    > {
+        let (eph_first_msg, session) = self.eph_keygen_commit(message);
+        store.commit(
+            &session.state.eph_keys.R_i,
+            &session.session_id,
+            &session.state.message,
+        )?;
+        Ok((eph_first_msg, session))
+    }
+}
+
+impl Party1SigningSession<Party1EphKeyGenCommitted> {
+    pub fn eph_keygen_decommit(
+        self,
+        party2_eph_first_msg: &party_two::EphKeyGenFirstMsg,
+    ) -> Result<
+        (
+            party_one::EphKeyGenSecondMsg,
+            Party1SigningSession<Party1EphKeyGenDone>,
+        ),
+        Error,
+    > {
+        let state = self.state;
+        let eph_second_msg = party_one::EphKeyGenSecondMsg::verify_and_decommit(
+            state.comm_witness,
+            party2_eph_first_msg,
+            &self.session_id,
+        )?;
+        let R = compute_R(&state.eph_keys, &party2_eph_first_msg.public_share);
+        Ok((
+            eph_second_msg,
+            Party1SigningSession {
+                keys: self.keys,
+                ak: self.ak,
+                counter_ak: self.counter_ak,
+                role: self.role,
+                nonce_mode: self.nonce_mode,
+                session_id: self.session_id,
+                state: Party1EphKeyGenDone {
+                    alpha: state.alpha,
+                    vk: state.vk,
+                    message: state.message,
+                    eph_keys: state.eph_keys,
+                    R,
+                    counter_R_i: party2_eph_first_msg.public_share,
+                },
+            },
+        ))
+    }
+}
+
+impl Party1SigningSession<Party1EphKeyGenDone> {
+    pub fn R(&self) -> GE {
+        self.state.R
+    }
+
+    /// local_sign, once store accepts the one signature of R_1
+    pub fn local_sign_guarded<S: NonceStore + ?Sized>(
+        self,
+        store: &mut S,
+    ) -> Result<
+        (
+            party_one::LocalSignatureMsg,
+            Party1SigningSession<Party1LocalSigned>,
+        ),
+        Error,
+    > {
+        store.sign(&self.state.eph_keys.R_i, &self.state.message)?;
+        self.local_sign()
+    }
+
+    // no NonceStore, see local_sign_guarded
+    pub(super) fn local_sign(
+        self,
+    ) -> Result<
+        (
+            party_one::LocalSignatureMsg,
+            Party1SigningSession<Party1LocalSigned>,
+        ),
+        Error,
+    > {
+        let state = self.state;
+        let counter_R_i = state.counter_R_i;
+        let counter_pk_i = compute_local_pk(
+            &self.counter_ak,
+            alpha_share(self.role.counterparty(), &state.alpha),
+        );
+        let s1 = compute_local_share(
+            &state.R,
+            &state.vk,
+            &self.keys.ask,
+            state.eph_keys,
+            &state.message,
+            alpha_share(self.role, &state.alpha),
+        )?;
+        let local_sig = party_one::LocalSignatureMsg { s1 };
+        Ok((
+            local_sig.clone(),
+            Party1SigningSession {
+                keys: self.keys,
+                ak: self.ak,
+                counter_ak: self.counter_ak,
+                role: self.role,
+                nonce_mode: self.nonce_mode,
+                session_id: self.session_id,
+                state: Party1LocalSigned {
+                    vk: state.vk,
+                    message: state.message,
+                    R: state.R,
+                    counter_R_i,
+                    counter_pk_i,
+                    local_sig,
+                },
+            },
+        ))
+    }
+}
+
+impl Party1SigningSession<Party1LocalSigned> {
+    pub fn finalize(
+        self,
+        party2_local_sig: &party_two::LocalSignatureMsg,
+    ) -> Result<Signature, Error> {
+        let state = self.state;
+        verify_local_share(
+            self.role.counterparty().party(),
+            &party2_local_sig.s2,
+            &state.counter_R_i,
+            &state.counter_pk_i,
+            &state.R,
+            &state.message,
+        )?;
+        combine_local_shares(
+            state.R,
+            state.vk,
+            &state.local_sig.s1,
+            &party2_local_sig.s2,
+            &state.message,
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2SigningSession<S> {
+    keys: EcKeyPair,
+    ak: GE,
+    // keygen public share of the counterparty
+    counter_ak: GE,
+    role: KeyGenRole,
+    nonce_mode: NonceMode,
+    session_id: SessionId,
+    state: S,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2Start;
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2CoinFlipShared {
+    party1_cf_first_msg: party_one::CoinFlipFirstMsg,
+    cf_first_msg: party_two::CoinFlipFirstMsg,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2CoinFlipDone {
+    alpha: FE,
+    vk: GE,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2EphKeyGenCreated {
+    alpha: FE,
+    vk: GE,
+    message: SpendAuthMessage,
+    party1_eph_first_msg: party_one::EphKeyGenFirstMsg,
+    eph_keys: EphEcKeyPair,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2EphKeyGenDone {
+    alpha: FE,
+    vk: GE,
+    message: SpendAuthMessage,
+    eph_keys: EphEcKeyPair,
+    R: GE,
+    counter_R_i: GE,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Party2LocalSigned {
+    vk: GE,
+    message: SpendAuthMessage,
+    R: GE,
+    counter_R_i: GE,
+    counter_pk_i: GE,
+    local_sig: party_two::LocalSignatureMsg,
+}
+
+impl Party2SigningSession<Party2Start> {
+    pub fn new(
+        keys: EcKeyPair,
+        ak: GE,
+        session_id: SessionId,
+    ) -> Party2SigningSession<Party2Start> {
+        Party2SigningSession::with_role(keys, ak, session_id, KeyGenRole::PartyTwo)
+    }
+
+    /// joins a session as the responder with the keys of either keygen party
+    pub fn with_role(
+        keys: EcKeyPair,
+        ak: GE,
+        session_id: SessionId,
+        role: KeyGenRole,
+    ) -> Party2SigningSession<Party2Start> {
+        let counter_ak = ak.sub_point(&keys.ak.get_element());
+        Party2SigningSession {
+            keys,
+            ak,
+            counter_ak,
+            role,
+            nonce_mode: NonceMode::Hedged,
+            session_id,
+            state: Party2Start,
+        }
+    }
+
+    /// sessions start in NonceMode::Hedged
+    pub fn with_nonce_mode(self, nonce_mode: NonceMode) -> Party2SigningSession<Party2Start> {
+        Party2SigningSession { nonce_mode, ..self }
+    }
+
+    pub fn coin_flip_share(
+        self,
+        party1_cf_first_msg: &party_one::CoinFlipFirstMsg,
+    ) -> Result<
+        (
+            party_two::CoinFlipFirstMsg,
+            Party2SigningSession<Party2CoinFlipShared>,
+        ),
+        Error,
+    > {
+        let cf_first_msg =
+            party_two::CoinFlipFirstMsg::share(party1_cf_first_msg, &self.session_id, self.role)?;
+        Ok((
+            cf_first_msg.clone(),
+            Party2SigningSession {
+                keys: self.keys,
+                ak: self.ak,
+                counter_ak: self.counter_ak,
+                role: self.role,
+                nonce_mode: self.nonce_mode,
+                session_id: self.session_id,
+                state: Party2CoinFlipShared {
+                    party1_cf_first_msg: party1_cf_first_msg.clone(),
+                    cf_first_msg,
+                },
+            },
+        ))
+    }
+}
+
+impl Party2SigningSession<Party2CoinFlipShared> {
+    pub fn coin_flip_finalize(
+        self,
+        party1_cf_second_msg: &party_one::CoinFlipSecondMsg,
+    ) -> Result<Party2SigningSession<Party2CoinFlipDone>, Error> {
+        let coin_flip_res = party_two::CoinFlipResult::finalize(
+            party1_cf_second_msg,
+            &self.state.cf_first_msg,
+            &self.state.party1_cf_first_msg,
+            &self.session_id,
+        )?;
+        let vk = compute_vk(&self.ak, &coin_flip_res.party2_alpha);
+        Ok(Party2SigningSession {
+            keys: self.keys,
+            ak: self.ak,
+            counter_ak: self.counter_ak,
+            role: self.role,
+            nonce_mode: self.nonce_mode,
+            session_id: self.session_id,
+            state: Party2CoinFlipDone {
+                alpha: coin_flip_res.party2_alpha,
+                vk,
+            },
+        })
+    }
+}
+
+impl Party2SigningSession<Party2CoinFlipDone> {
+    pub fn vk(&self) -> GE {
+        self.state.vk
+    }
+
+    pub fn alpha(&self) -> FE {
+        self.state.alpha
+    }
+
+    // no NonceStore, see eph_keygen_create_guarded
+    pub(super) fn eph_keygen_create(
+        self,
+        party1_eph_first_msg: &party_one::EphKeyGenFirstMsg,
+        message: &SpendAuthMessage,
+    ) -> (
+        party_two::EphKeyGenFirstMsg,
+        Party2SigningSession<Party2EphKeyGenCreated>,
+    ) {
+        let (eph_first_msg, eph_keys) = party_two::EphKeyGenFirstMsg::create(
+            &self.keys.ask,
+            message,
+            &self.session_id,
+            self.nonce_mode,
+        );
+        (
+            eph_first_msg,
+            Party2SigningSession {
+                keys: self.keys,
+                ak: self.ak,
+                counter_ak: self.counter_ak,
+                role: self.role,
+                nonce_mode: self.nonce_mode,
+                session_id: self.session_id,
+                state: Party2EphKeyGenCreated {
+                    alpha: self.state.alpha,
+                    vk: self.state.vk,
+                    message: message.clone(),
+                    party1_eph_first_msg: party1_eph_first_msg.clone(),
+                    eph_keys,
+                },
+            },
+        )
+    }
+
+    /// eph_keygen_create, once store accepts R_2 for this session and message
+    pub fn eph_keygen_create_guarded<S: NonceStore + ?Sized>(
+        self,
+        party1_eph_first_msg: &party_one::EphKeyGenFirstMsg,
+        message: &SpendAuthMessage,
+        store: &mut S,
+    ) -> Result<
+        (
+            party_two::EphKeyGenFirstMsg,
+            Party2SigningSession<Party2EphKeyGenCreated>,
+        ),
+        Error,
+    > {
+        let (eph_first_msg, session) = self.eph_keygen_create(party1_eph_first_msg, message);
+        store.commit(
+            &session.state.eph_keys.R_i,
+            &session.session_id,
+            &session.state.message,
+        )?;
+        Ok((eph_first_msg, session))
+    }
+}
+
+impl Party2SigningSession<Party2EphKeyGenCreated> {
+    pub fn eph_keygen_verify(
+        self,
+        party1_eph_second_msg: &party_one::EphKeyGenSecondMsg,
+    ) -> Result<
+        (
+            party_two::EphKeyGenSecondMsg,
+            Party2SigningSession<Party2EphKeyGenDone>,
+        ),
+        Error,
+    > {
+        let state = self.state;
+        let eph_second_msg = party_two::EphKeyGenSecondMsg::verify_commitments_and_dlog_proof(
+            &state.party1_eph_first_msg,
+            party1_eph_second_msg,
+            &self.session_id,
+        )?;
+        let R = compute_R(
+            &state.eph_keys,
+            &party1_eph_second_msg.comm_witness.public_share,
+        );
+        Ok((
+            eph_second_msg,
+            Party2SigningSession {
+                keys: self.keys,
+                ak: self.ak,
+                counter_ak: self.counter_ak,
+                role: self.role,
+                nonce_mode: self.nonce_mode,
+                session_id: self.session_id,
+                state: Party2EphKeyGenDone {
+                    alpha: state.alpha,
+                    vk: state.vk,
+                    message: state.message,
+                    eph_keys: state.eph_keys,
+                    R,
+                    counter_R_i: party1_eph_second_msg.comm_witness.public_share,
+                },
+            },
+        ))
+    }
+}
+
+impl Party2SigningSession<Party2EphKeyGenDone> {
+    pub fn R(&self) -> GE {
+        self.state.R
+    }
+
+    /// what a SigningPolicy decides on, caller and details are supplied by whoever serves party one
+    pub fn signing_context(
+        &self,
+        caller: &str,
+        details: Option<TransactionDetails>,
+    ) -> SigningContext {
+        SigningContext::new(
+            &self.ak,
+            &self.session_id,
+            &self.state.vk,
+            self.state.message.sighash(),
+            &self.state.alpha,
+            caller,
+            details,
+        )
+    }
+
+    /// local_sign once policy accepts the signing context and store the one signature of R_2.
+    /// A rejection of policy hands the session back, see Held
+    pub fn local_sign_with_policy<P: SigningPolicy + ?Sized, S: NonceStore + ?Sized>(
+        self,
+        policy: &P,
+        store: &mut S,
+        caller: &str,
+        details: Option<TransactionDetails>,
+    ) -> Result<
+        (
+            party_two::LocalSignatureMsg,
+            Party2SigningSession<Party2LocalSigned>,
+        ),
+        Held<Party2SigningSession<Party2EphKeyGenDone>>,
+    > {
+        let context = self.signing_context(caller, details);
+        if let Err(rejection) = policy.check(&context) {
+            return Err(Held::back(Error::PolicyRejected(rejection), self));
+        }
+        let signed = self.local_sign_guarded(store)?;
+        policy.record(&context);
+        Ok(signed)
+    }
+
+    /// local_sign, once store accepts the one signature of R_2
+    pub(super) fn local_sign_guarded<S: NonceStore + ?Sized>(
+        self,
+        store: &mut S,
+    ) -> Result<
+        (
+            party_two::LocalSignatureMsg,
+            Party2SigningSession<Party2LocalSigned>,
+        ),
+        Error,
+    > {
+        store.sign(&self.state.eph_keys.R_i, &self.state.message)?;
+        self.local_sign()
+    }
+
+    // no policy, see local_sign_with_policy
+    pub(super) fn local_sign(
+        self,
+    ) -> Result<
+        (
+            party_two::LocalSignatureMsg,
+            Party2SigningSession<Party2LocalSigned>,
+        ),
+        Error,
+    > {
+        let state = self.state;
+        let counter_R_i = state.counter_R_i;
+        let counter_pk_i = compute_local_pk(
+            &self.counter_ak,
+            alpha_share(self.role.counterparty(), &state.alpha),
+        );
+        let s2 = compute_local_share(
+            &state.R,
+            &state.vk,
+            &self.keys.ask,
+            state.eph_keys,
+            &state.message,
+            alpha_share(self.role, &state.alpha),
+        )?;
+        let local_sig = party_two::LocalSignatureMsg { s2 };
+        Ok((
+            local_sig.clone(),
+            Party2SigningSession {
+                keys: self.keys,
+                ak: self.ak,
+                counter_ak: self.counter_ak,
+                role: self.role,
+                nonce_mode: self.nonce_mode,
+                session_id: self.session_id,
+                state: Party2LocalSigned {
+                    vk: state.vk,
+                    message: state.message,
+                    R: state.R,
+                    counter_R_i,
+                    counter_pk_i,
+                    local_sig,
+                },
+            },
+        ))
+    }
+}
+
+impl Party2SigningSession<Party2LocalSigned> {
+    pub fn finalize(
+        self,
+        party1_local_sig: &party_one::LocalSignatureMsg,
+    ) -> Result<Signature, Error> {
+        let state = self.state;
+        verify_local_share(
+            self.role.counterparty().party(),
+            &party1_local_sig.s1,
+            &state.counter_R_i,
+            &state.counter_pk_i,
+            &state.R,
+            &state.message,
+        )?;
+        combine_local_shares(
+            state.R,
+            state.vk,
+            &state.local_sig.s2,
+            &party1_local_sig.s1,
+            &state.message,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::elliptic::curves::curve_jubjub::{FE, GE};
+    use curv::elliptic::curves::traits::{ECPoint, ECScalar};
+    use protocols::two_party::compute_vk;
+    use protocols::two_party::party_one::EphKeyGenFirstMsg as Party1EphKeyGenFirstMsg;
+    use protocols::two_party::party_one::LocalSignatureMsg as Party1LocalSignatureMsg;
+    use protocols::two_party::session::{Party1SigningSession, Party2SigningSession};
+    use protocols::two_party::test::tests::two_party_keygen;
+    use protocols::two_party::{
+        fe_to_bytes, verify, vk_to_bytes, KeyGenRole, NonceMode, SessionId, SpendAuthMessage,
+        PARTY_TWO,
+    };
+    use {Check, Error, Round};
+
+    #[test]
+    fn test_2p_sign_session() {
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let session_id = SessionId::new_random();
+        let party1_session = Party1SigningSession::new(party1_keys, public_key, session_id.clone());
+        let party2_session = Party2SigningSession::new(party2_keys, public_key, session_id);
+
+        let (party1_cf_first_message, party1_session) = party1_session.coin_flip_commit();
+        let (party2_cf_first_message, party2_session) = party2_session
+            .coin_flip_share(&party1_cf_first_message)
+            .expect("bad coin flip proof");
+        let (party1_cf_second_message, party1_session) = party1_session
+            .coin_flip_reveal(&party2_cf_first_message)
+            .expect("bad keygen role");
+        let party2_session = party2_session
+            .coin_flip_finalize(&party1_cf_second_message)
+            .expect("bad coin flip proof");
+        assert_eq!(party1_session.vk(), party2_session.vk());
+
+        let message = SpendAuthMessage::new(&party1_session.vk(), &[1u8; 32]).unwrap();
+
+        let (party1_eph_first_message, party1_session) = party1_session.eph_keygen_commit(&message);
+        let (party2_eph_first_message, party2_session) =
+            party2_session.eph_keygen_create(&party1_eph_first_message, &message);
+        let (party1_eph_second_message, party1_session) = party1_session
+            .eph_keygen_decommit(&party2_eph_first_message)
+            .expect("failed to verify and decommit");
+        let (_party2_eph_second_message, party2_session) = party2_session
+            .eph_keygen_verify(&party1_eph_second_message)
+            .expect("failed to verify commitments and DLog proof");
+        assert_eq!(party1_session.R(), party2_session.R());
+
+        let (party1_local_sig, party1_session) = party1_session.local_sign().expect("bad message");
+        let (party2_local_sig, party2_session) = party2_session.local_sign().expect("bad message");
+        let party1_sig = party1_session
+            .finalize(&party2_local_sig)
+            .expect("bad signature");
+        let party2_sig = party2_session
+            .finalize(&party1_local_sig)
+            .expect("bad signature");
+
+        assert_eq!(party1_sig, party2_sig);
+    }
+
+    #[test]
+    fn test_2p_sign_session_zero_sighash() {
+        // leading zero bytes in the sighash used to shorten the encoded message
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let session_id = SessionId::new_random();
+        let party1_session = Party1SigningSession::new(party1_keys, public_key, session_id.clone());
+        let party2_session = Party2SigningSession::new(party2_keys, public_key, session_id);
+
+        let (party1_cf_first_message, party1_session) = party1_session.coin_flip_commit();
+        let (party2_cf_first_message, party2_session) = party2_session
+            .coin_flip_share(&party1_cf_first_message)
+            .expect("bad coin flip proof");
+        let (party1_cf_second_message, party1_session) = party1_session
+            .coin_flip_reveal(&party2_cf_first_message)
+            .expect("bad keygen role");
+        let party2_session = party2_session
+            .coin_flip_finalize(&party1_cf_second_message)
+            .expect("bad coin flip proof");
+        let vk = party1_session.vk();
+
+        let message = SpendAuthMessage::new(&vk, &[0u8; 32]).unwrap();
+        let (party1_eph_first_message, party1_session) = party1_session.eph_keygen_commit(&message);
+        let (party2_eph_first_message, party2_session) =
+            party2_session.eph_keygen_create(&party1_eph_first_message, &message);
+        let (party1_eph_second_message, party1_session) = party1_session
+            .eph_keygen_decommit(&party2_eph_first_message)
+            .expect("failed to verify and decommit");
+        let (_party2_eph_second_message, party2_session) = party2_session
+            .eph_keygen_verify(&party1_eph_second_message)
+            .expect("failed to verify commitments and DLog proof");
+        let (party1_local_sig, party1_session) = party1_session.local_sign().expect("bad message");
+        let (party2_local_sig, party2_session) = party2_session.local_sign().expect("bad message");
+        let sig = party1_session
+            .finalize(&party2_local_sig)
+            .expect("bad signature");
+        assert_eq!(
+            sig,
+            party2_session
+                .finalize(&party1_local_sig)
+                .expect("bad signature")
+        );
+        assert!(verify(vk, &message, &sig).is_ok());
+
+        // the same signature does not verify under another vk or sighash
+        let other_vk: GE = ECPoint::generator();
+        let other_message = SpendAuthMessage::new(&other_vk, &[0u8; 32]).unwrap();
+        assert_eq!(
+            verify(other_vk, &other_message, &sig),
+            Err(Error::InvalidSig)
+        );
+        assert_eq!(verify(other_vk, &message, &sig), Err(Error::InvalidSig));
+        let other_message = SpendAuthMessage::new(&vk, &[1u8; 32]).unwrap();
+        assert_eq!(verify(vk, &other_message, &sig), Err(Error::InvalidSig));
+    }
+
+    #[test]
+    fn test_2p_sign_message_for_other_vk() {
+        let (party1_keys, _party2_keys, public_key) = two_party_keygen();
+        let session_id = SessionId::new_random();
+        let alpha: FE = ECScalar::new_random();
+        let vk = compute_vk(&public_key, &alpha);
+        // a message built for ak instead of the randomized vk
+        let message = SpendAuthMessage::new(&public_key, &[1u8; 32]).unwrap();
+        let (_, _, party1_eph_keys) = Party1EphKeyGenFirstMsg::create_commitments(
+            &party1_keys.ask,
+            &message,
+            &session_id,
+            NonceMode::Hedged,
+        );
+        let R = party1_eph_keys.R_i;
+        let result = Party1LocalSignatureMsg::compute_s1(
+            &R,
+            &vk,
+            party1_keys,
+            party1_eph_keys,
+            &message,
+            &alpha,
+        );
+        assert_eq!(
+            result.err(),
+            Some(Error::protocol(Round::Signing, Check::MessageFormat))
+        );
+    }
+
+    #[test]
+    fn test_2p_sign_session_bad_local_sig() {
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let session_id = SessionId::new_random();
+        let party2_ak_share = party2_keys.ak;
+        let party1_session = Party1SigningSession::new(party1_keys, public_key, session_id.clone());
+        let party2_session = Party2SigningSession::new(party2_keys, public_key, session_id);
+
+        let (party1_cf_first_message, party1_session) = party1_session.coin_flip_commit();
+        let (party2_cf_first_message, party2_session) = party2_session
+            .coin_flip_share(&party1_cf_first_message)
+            .expect("bad coin flip proof");
+        let (party1_cf_second_message, party1_session) = party1_session
+            .coin_flip_reveal(&party2_cf_first_message)
+            .expect("bad keygen role");
+        let party2_session = party2_session
+            .coin_flip_finalize(&party1_cf_second_message)
+            .expect("bad coin flip proof");
+
+        let message = SpendAuthMessage::new(&party1_session.vk(), &[1u8; 32]).unwrap();
+
+        let (party1_eph_first_message, party1_session) = party1_session.eph_keygen_commit(&message);
+        let (party2_eph_first_message, party2_session) =
+            party2_session.eph_keygen_create(&party1_eph_first_message, &message);
+        let (party1_eph_second_message, party1_session) = party1_session
+            .eph_keygen_decommit(&party2_eph_first_message)
+            .expect("failed to verify and decommit");
+        let (_, party2_session) = party2_session
+            .eph_keygen_verify(&party1_eph_second_message)
+            .expect("failed to verify commitments and DLog proof");
+
+        let (_, party1_session) = party1_session.local_sign().expect("bad message");
+        let (mut party2_local_sig, _) = party2_session.local_sign().expect("bad message");
+        // party two cheats
+        party2_local_sig.s2 = ECScalar::new_random();
+        match party1_session.finalize(&party2_local_sig) {
+            Err(Error::LocalSignature {
+                party,
+                s_i,
+                R_i,
+                pk_i,
+            }) => {
+                assert_eq!(party, PARTY_TWO);
+                assert_eq!(s_i, fe_to_bytes(&party2_local_sig.s2));
+                assert_eq!(R_i, vk_to_bytes(&party2_eph_first_message.public_share));
+                assert_eq!(pk_i, vk_to_bytes(&party2_ak_share));
+            }
+            _ => panic!("bad local signature accepted"),
+        }
+    }
+
+    #[test]
+    fn test_2p_sign_session_party2_initiates() {
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let session_id = SessionId::new_random();
+        // keygen party two starts the session and takes the committing role
+        let initiator = Party1SigningSession::with_role(
+            party2_keys,
+            public_key,
+            session_id.clone(),
+            KeyGenRole::PartyTwo,
+        );
+        let responder = Party2SigningSession::with_role(
+            party1_keys,
+            public_key,
+            session_id,
+            KeyGenRole::PartyOne,
+        );
+
+        let (initiator_cf_first_message, initiator) = initiator.coin_flip_commit();
+        let (responder_cf_first_message, responder) = responder
+            .coin_flip_share(&initiator_cf_first_message)
+            .expect("bad coin flip proof");
+        let (initiator_cf_second_message, initiator) = initiator
+            .coin_flip_reveal(&responder_cf_first_message)
+            .expect("bad keygen role");
+        let responder = responder
+            .coin_flip_finalize(&initiator_cf_second_message)
+            .expect("bad coin flip proof");
+        assert_eq!(initiator.vk(), responder.vk());
+        assert_eq!(initiator.alpha(), responder.alpha());
+
+        let message = SpendAuthMessage::new(&initiator.vk(), &[1u8; 32]).unwrap();
+
+        let (initiator_eph_first_message, initiator) = initiator.eph_keygen_commit(&message);
+        let (responder_eph_first_message, responder) =
+            responder.eph_keygen_create(&initiator_eph_first_message, &message);
+        let (initiator_eph_second_message, initiator) = initiator
+            .eph_keygen_decommit(&responder_eph_first_message)
+            .expect("failed to verify and decommit");
+        let (_, responder) = responder
+            .eph_keygen_verify(&initiator_eph_second_message)
+            .expect("failed to verify commitments and DLog proof");
+
+        let (initiator_local_sig, initiator) = initiator.local_sign().expect("bad message");
+        let (responder_local_sig, responder) = responder.local_sign().expect("bad message");
+        let initiator_sig = initiator
+            .finalize(&responder_local_sig)
+            .expect("bad signature");
+        let responder_sig = responder
+            .finalize(&initiator_local_sig)
+            .expect("bad signature");
+        assert_eq!(initiator_sig, responder_sig);
+    }
+
+    #[test]
+    fn test_2p_sign_session_same_role() {
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let session_id = SessionId::new_random();
+        // both sides claim the share alpha is added to
+        let initiator = Party1SigningSession::with_role(
+            party1_keys.clone(),
+            public_key,
+            session_id.clone(),
+            KeyGenRole::PartyOne,
+        );
+        let responder = Party2SigningSession::with_role(
+            party2_keys.clone(),
+            public_key,
+            session_id.clone(),
+            KeyGenRole::PartyOne,
+        );
+        let (initiator_cf_first_message, _) = initiator.coin_flip_commit();
+        match responder.coin_flip_share(&initiator_cf_first_message) {
+            Err(e) => assert_eq!(e, Error::protocol(Round::CoinFlip, Check::KeyGenRole)),
+            Ok(_) => panic!("responder accepted its own keygen role"),
+        }
+
+        // the initiator checks the role of the responder before revealing its seed
+        let initiator = Party1SigningSession::with_role(
+            party2_keys,
+            public_key,
+            session_id.clone(),
+            KeyGenRole::PartyTwo,
+        );
+        let responder = Party2SigningSession::new(party1_keys, public_key, session_id);
+        let (mut initiator_cf_first_message, initiator) = initiator.coin_flip_commit();
+        // a tampered first message gets past the responder, which claims keygen party two as well
+        initiator_cf_first_message.role = KeyGenRole::PartyOne;
+        let (responder_cf_first_message, _) = responder
+            .coin_flip_share(&initiator_cf_first_message)
+            .expect("bad coin flip proof");
+        match initiator.coin_flip_reveal(&responder_cf_first_message) {
+            Err(e) => assert_eq!(e, Error::protocol(Round::CoinFlip, Check::KeyGenRole)),
+            Ok(_) => panic!("initiator accepted its own keygen role"),
+        }
+    }
+}
@@ -0,0 +1,270 @@
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Passphrase encrypted file for the share of one party.
+//! The public part (key id, keygen role, ak) is readable without the passphrase, the EcKeyPair is
+//! encrypted:
+//!
+//! - the passphrase is stretched with scrypt over a random salt. The scrypt parameters are fixed
+//!   by the version of the file and not read from it, so a file cannot ask for a cheaper or a
+//!   ruinously expensive derivation
+//! - the EcKeyPair is sealed with ChaCha20-Poly1305 under the derived key and a random nonce, with
+//!   the public part and the salt as associated data
+//!
+//! The plaintext and the derived key are zeroized once used. save writes the file readable by
+//! its owner only. A wrong passphrase and a modified file both give Error::InvalidKey.
+
+use super::{from_hex, key_id, to_hex, vk_from_bytes, vk_to_bytes, EcKeyPair, KeyGenRole};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use curv::elliptic::curves::curve_jubjub::GE;
+use curv::elliptic::curves::rand::{thread_rng, Rng};
+use curv::elliptic::curves::traits::ECPoint;
+use scrypt::{self, Params};
+use serde_json;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use zeroize::Zeroize;
+use Error;
+
+/// bumped on every change to the layout or the cryptography of a share file
+pub const SHARE_FILE_VERSION: u32 = 1;
+
+/// scrypt cost of version SHARE_FILE_VERSION: N = 2^15, r = 8, p = 1, 32 MiB of memory
+pub const SCRYPT_LOG_N: u8 = 15;
+pub const SCRYPT_R: u32 = 8;
+pub const SCRYPT_P: u32 = 1;
+
+const KEY_SIZE: usize = 32;
+const SALT_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+// the JSON of an EcKeyPair fits, so serializing it never reallocates and leaves no copy behind
+const PLAINTEXT_CAPACITY: usize = 1 << 12;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ShareFile {
+    pub version: u32,
+    pub key_id: String,
+    pub role: KeyGenRole,
+    /// hex encoding of ak
+    pub ak: String,
+    salt: String,
+    nonce: String,
+    /// with the Poly1305 tag appended
+    ciphertext: String,
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+fn derive_key(passphrase: &[u8], salt: &[u8]) -> Result<[u8; KEY_SIZE], Error> {
+    // the parameters are constants in range, scrypt only fails on an output of the wrong size
+    let params =
+        Params::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P, KEY_SIZE).map_err(|_| Error::InvalidKey)?;
+    let mut key = [0u8; KEY_SIZE];
+    scrypt::scrypt(passphrase, salt, &params, &mut key).map_err(|_| Error::InvalidKey)?;
+    Ok(key)
+}
+
+impl ShareFile {
+    // the public part and the salt, every field prefixed with its length
+    fn associated_data(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for field in &[
+            self.version.to_le_bytes().to_vec(),
+            self.key_id.as_bytes().to_vec(),
+            vec![self.role.party()],
+            self.ak.as_bytes().to_vec(),
+            self.salt.as_bytes().to_vec(),
+        ] {
+            data.extend_from_slice(&(field.len() as u64).to_le_bytes());
+            data.extend_from_slice(field);
+        }
+        data
+    }
+
+    pub fn seal(
+        keys: &EcKeyPair,
+        ak: &GE,
+        role: KeyGenRole,
+        passphrase: &[u8],
+    ) -> Result<ShareFile, Error> {
+        let salt = random_bytes(SALT_SIZE);
+        let nonce = random_bytes(NONCE_SIZE);
+        let mut share_file = ShareFile {
+            version: SHARE_FILE_VERSION,
+            key_id: key_id(ak),
+            role,
+            ak: to_hex(&vk_to_bytes(ak)),
+            salt: to_hex(&salt),
+            nonce: to_hex(&nonce),
+            ciphertext: String::new(),
+        };
+        let mut key = derive_key(passphrase, &salt)?;
+        let mut plaintext = Vec::with_capacity(PLAINTEXT_CAPACITY);
+        let serialized = serde_json::to_writer(&mut plaintext, keys);
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key)).encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &plaintext,
+                aad: &share_file.associated_data(),
+            },
+        );
+        key.zeroize();
+        plaintext.zeroize();
+        serialized.map_err(|_| Error::InvalidEncoding)?;
+        share_file.ciphertext = to_hex(&ciphertext.map_err(|_| Error::InvalidEncoding)?);
+        Ok(share_file)
+    }
+
+    /// returns the share and ak, once the share is checked against ak
+    pub fn open(&self, passphrase: &[u8]) -> Result<(EcKeyPair, GE), Error> {
+        if self.version != SHARE_FILE_VERSION {
+            return Err(Error::StateVersion(self.version));
+        }
+        let decode = |hex: &str| from_hex(hex).ok_or(Error::InvalidEncoding);
+        let (salt, nonce, ciphertext) = (
+            decode(&self.salt)?,
+            decode(&self.nonce)?,
+            decode(&self.ciphertext)?,
+        );
+        if salt.len() != SALT_SIZE || nonce.len() != NONCE_SIZE {
+            return Err(Error::InvalidEncoding);
+        }
+        let mut key = derive_key(passphrase, &salt)?;
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key)).decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: &self.associated_data(),
+            },
+        );
+        key.zeroize();
+        let mut plaintext = plaintext.map_err(|_| Error::InvalidKey)?;
+        let keys = serde_json::from_slice::<EcKeyPair>(&plaintext);
+        plaintext.zeroize();
+        let keys = keys.map_err(|_| Error::InvalidKey)?;
+        let ak = self.ak()?;
+        let base: GE = ECPoint::generator();
+        if base * &keys.ask != keys.ak || self.key_id != key_id(&ak) {
+            return Err(Error::InvalidKey);
+        }
+        Ok((keys, ak))
+    }
+
+    pub fn ak(&self) -> Result<GE, Error> {
+        vk_from_bytes(&from_hex(&self.ak).ok_or(Error::InvalidEncoding)?)
+    }
+
+    /// Replaces the file at path. The file is created with mode 0600 on unix next to path and
+    /// renamed into place, so it is never readable by others and never half written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(self).map_err(|_| Error::InvalidEncoding)?;
+        let tmp_path = path.as_ref().with_extension("tmp");
+        // a leftover of an interrupted save may have any mode
+        let _ = fs::remove_file(&tmp_path);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        options
+            .open(&tmp_path)
+            .and_then(|mut file| {
+                file.write_all(json.as_bytes())
+                    .and_then(|_| file.sync_all())
+            })
+            .and_then(|_| fs::rename(&tmp_path, path.as_ref()))
+            .map_err(|_| Error::Storage)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ShareFile, Error> {
+        let json = fs::read_to_string(path).map_err(|_| Error::Storage)?;
+        serde_json::from_str(&json).map_err(|_| Error::InvalidEncoding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::elliptic::curves::rand::{thread_rng, Rng};
+    use protocols::two_party::share_file::ShareFile;
+    use protocols::two_party::test::tests::two_party_keygen;
+    use protocols::two_party::KeyGenRole;
+    use serde_json;
+    use std::env;
+    use std::fs;
+    use Error;
+
+    #[test]
+    fn test_share_file() {
+        let (party1_keys, _, ak) = two_party_keygen();
+        let share_file = ShareFile::seal(&party1_keys, &ak, KeyGenRole::PartyOne, b"passphrase")
+            .expect("failed to seal");
+        assert_eq!(share_file.ak().unwrap(), ak);
+        assert_eq!(share_file.role, KeyGenRole::PartyOne);
+        let json = serde_json::to_string(&share_file).unwrap();
+        assert!(!json.contains(&serde_json::to_string(&party1_keys.ask).unwrap()));
+
+        let share_file: ShareFile = serde_json::from_str(&json).unwrap();
+        let (keys, opened_ak) = share_file.open(b"passphrase").expect("failed to open");
+        assert_eq!(keys.ask, party1_keys.ask);
+        assert_eq!(opened_ak, ak);
+        assert_eq!(
+            share_file.open(b"wrong passphrase").err(),
+            Some(Error::InvalidKey)
+        );
+
+        // the public part is authenticated too
+        let (other_keys, _, other_ak) = two_party_keygen();
+        let other_file =
+            ShareFile::seal(&other_keys, &other_ak, KeyGenRole::PartyOne, b"passphrase")
+                .expect("failed to seal");
+        let mut tampered = share_file.clone();
+        tampered.ak = other_file.ak.clone();
+        tampered.key_id = other_file.key_id.clone();
+        assert_eq!(tampered.open(b"passphrase").err(), Some(Error::InvalidKey));
+        let mut tampered = share_file.clone();
+        tampered.role = KeyGenRole::PartyTwo;
+        assert_eq!(tampered.open(b"passphrase").err(), Some(Error::InvalidKey));
+        // and so is the salt of the key derivation
+        let mut json: serde_json::Value = serde_json::to_value(&share_file).unwrap();
+        json["salt"] = serde_json::to_value("00".repeat(32)).unwrap();
+        let tampered: ShareFile = serde_json::from_value(json).unwrap();
+        assert_eq!(tampered.open(b"passphrase").err(), Some(Error::InvalidKey));
+
+        let dir = env::temp_dir().join(format!(
+            "paradise-city-share-file-{}",
+            thread_rng().gen::<u64>()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("share.json");
+        share_file.save(&path).expect("failed to save");
+        other_file.save(&path).expect("failed to replace");
+        assert_eq!(ShareFile::load(&path).unwrap(), other_file);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
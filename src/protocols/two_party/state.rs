@@ -0,0 +1,165 @@
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Versioned storage of in-flight party state.
+//! Any intermediate state of keygen, signing, batch signing, presigning or refresh can be wrapped
+//! in a VersionedState and stored, e.g. in a database between two requests, then loaded by another
+//! process to continue the protocol. Loading checks the version tag so a state written by an
+//! incompatible release is rejected instead of misread.
+//!
+//! States hold secret shares and ephemeral keys: store them encrypted, and delete a stored
+//! signing state once it is loaded, so an ephemeral key is never used for two signatures.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use Error;
+
+/// bumped on every change to the layout of a stored state
+pub const STATE_VERSION: u32 = 4;
+
+// the version tag alone, read before the state so an old layout is never decoded as the new one
+#[derive(Deserialize)]
+struct VersionTag {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VersionedState<T> {
+    pub version: u32,
+    pub state: T,
+}
+
+impl<T> VersionedState<T> {
+    pub fn new(state: T) -> VersionedState<T> {
+        VersionedState {
+            version: STATE_VERSION,
+            state,
+        }
+    }
+
+    pub fn into_state(self) -> Result<T, Error> {
+        match self.version == STATE_VERSION {
+            true => Ok(self.state),
+            false => Err(Error::StateVersion(self.version)),
+        }
+    }
+}
+
+impl<T: Serialize> VersionedState<T> {
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string(self).map_err(|_| Error::InvalidEncoding)
+    }
+}
+
+impl<T: DeserializeOwned> VersionedState<T> {
+    /// parses a stored state, a state of another version is rejected before it is decoded
+    pub fn from_json(stored: &str) -> Result<VersionedState<T>, Error> {
+        let tag: VersionTag = serde_json::from_str(stored).map_err(|_| Error::InvalidEncoding)?;
+        if tag.version != STATE_VERSION {
+            return Err(Error::StateVersion(tag.version));
+        }
+        serde_json::from_str(stored).map_err(|_| Error::InvalidEncoding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocols::two_party::session::{
+        Party1KeyGenCommitted, Party1SigningSession, Party1Start, Party2KeyGenCreated,
+        Party2SigningSession,
+    };
+    use protocols::two_party::state::{VersionedState, STATE_VERSION};
+    use protocols::two_party::test::tests::{reload, two_party_keygen};
+    use protocols::two_party::{SessionId, SpendAuthMessage};
+    use Error;
+
+    #[test]
+    fn test_2p_keygen_and_sign_resumed() {
+        let session_id = SessionId::new_random();
+        let (party1_first_message, party1_keygen) = Party1KeyGenCommitted::commit(&session_id);
+        let party1_keygen = reload(party1_keygen);
+        let (party2_first_message, party2_keygen) =
+            Party2KeyGenCreated::create(&party1_first_message, &session_id);
+        let party2_keygen = reload(party2_keygen);
+        let (party1_second_message, party1_keys, party1_ak) = party1_keygen
+            .decommit(&party2_first_message)
+            .expect("failed to verify and decommit");
+        let (party2_keys, party2_ak) = party2_keygen
+            .verify(&party1_second_message)
+            .expect("failed to verify commitments and DLog proof");
+        assert_eq!(party1_ak, party2_ak);
+
+        let session_id = SessionId::new_random();
+        let party1_session = Party1SigningSession::new(party1_keys, party1_ak, session_id.clone());
+        let party2_session = Party2SigningSession::new(party2_keys, party2_ak, session_id);
+
+        let (party1_cf_first_message, party1_session) = reload(party1_session).coin_flip_commit();
+        let (party2_cf_first_message, party2_session) = reload(party2_session)
+            .coin_flip_share(&party1_cf_first_message)
+            .expect("bad coin flip proof");
+        let (party1_cf_second_message, party1_session) = reload(party1_session)
+            .coin_flip_reveal(&party2_cf_first_message)
+            .expect("bad keygen role");
+        let party2_session = reload(party2_session)
+            .coin_flip_finalize(&party1_cf_second_message)
+            .expect("bad coin flip proof");
+
+        let party1_session = reload(party1_session);
+        let message = SpendAuthMessage::new(&party1_session.vk(), &[1u8; 32]).unwrap();
+
+        let (party1_eph_first_message, party1_session) = party1_session.eph_keygen_commit(&message);
+        let (party2_eph_first_message, party2_session) =
+            reload(party2_session).eph_keygen_create(&party1_eph_first_message, &message);
+        let (party1_eph_second_message, party1_session) = reload(party1_session)
+            .eph_keygen_decommit(&party2_eph_first_message)
+            .expect("failed to verify and decommit");
+        let (_, party2_session) = reload(party2_session)
+            .eph_keygen_verify(&party1_eph_second_message)
+            .expect("failed to verify commitments and DLog proof");
+
+        let (party1_local_sig, party1_session) =
+            reload(party1_session).local_sign().expect("bad message");
+        let (party2_local_sig, party2_session) =
+            reload(party2_session).local_sign().expect("bad message");
+        let party1_sig = reload(party1_session)
+            .finalize(&party2_local_sig)
+            .expect("bad signature");
+        let party2_sig = reload(party2_session)
+            .finalize(&party1_local_sig)
+            .expect("bad signature");
+        assert_eq!(party1_sig, party2_sig);
+    }
+
+    #[test]
+    fn test_state_wrong_version() {
+        let (party1_keys, _, public_key) = two_party_keygen();
+        let session = Party1SigningSession::new(party1_keys, public_key, SessionId::new_random());
+        let mut stored = VersionedState::new(session);
+        stored.version = STATE_VERSION + 1;
+        let stored = stored.to_json().expect("failed to store state");
+        match VersionedState::<Party1SigningSession<Party1Start>>::from_json(&stored) {
+            Err(e) => assert_eq!(e, Error::StateVersion(STATE_VERSION + 1)),
+            Ok(_) => panic!("state of another version accepted"),
+        }
+        // a state in an older layout is rejected for its version, not decoded
+        let stored = format!("{{\"version\":{},\"state\":{{}}}}", STATE_VERSION - 1);
+        match VersionedState::<Party1SigningSession<Party1Start>>::from_json(&stored) {
+            Err(e) => assert_eq!(e, Error::StateVersion(STATE_VERSION - 1)),
+            Ok(_) => panic!("state of another version accepted"),
+        }
+    }
+}
@@ -17,13 +17,17 @@
 //use protocols::two_party::
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use curv::arithmetic::big_gmp::BigInt;
-    use curv::elliptic::curves::curve_jubjub::GE;
-    use curv::elliptic::curves::traits::ECPoint;
+    use curv::elliptic::curves::curve_jubjub::{FE, GE};
+    use curv::elliptic::curves::traits::{ECPoint, ECScalar};
+    use protocols::two_party::batch::{Party1BatchSigningSession, Party2BatchSigningSession};
     use protocols::two_party::compute_R;
     use protocols::two_party::compute_ak;
+    use protocols::two_party::compute_local_pk;
     use protocols::two_party::compute_vk;
+    use protocols::two_party::cosigner::Cosigner;
+    use protocols::two_party::nonce_store::MemoryNonceStore;
     use protocols::two_party::party_one::CoinFlipFirstMsg as Party1CFFirstMsg;
     use protocols::two_party::party_one::CoinFlipSecondMsg as Party1CFSecondMsg;
     use protocols::two_party::party_one::EphKeyGenFirstMsg as Party1EphKeyGenFirstMsg;
@@ -38,21 +42,38 @@ mod tests {
     use protocols::two_party::party_two::KeyGenFirstMsg as Party2KeyGenFirstMsg;
     use protocols::two_party::party_two::KeyGenSecondMsg as Party2KeyGenSecondMsg;
     use protocols::two_party::party_two::LocalSignatureMsg as Party2LocalSignatureMsg;
-    use protocols::two_party::EcKeyPair;
+    use protocols::two_party::policy::TransactionDetails;
+    use protocols::two_party::session::{
+        Party1EphKeyGenDone, Party1SigningSession, Party2EphKeyGenDone, Party2SigningSession,
+    };
+    use protocols::two_party::state::VersionedState;
+    use protocols::two_party::zip32::{ExtendedPublicKey, HARDENED};
+    use protocols::two_party::{
+        from_hex, verify, vk_from_bytes, vk_to_bytes, EcKeyPair, KeyGenRole, NonceMode, SessionId,
+        Signature, SpendAuthMessage,
+    };
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use serde_json;
+    use std::net::TcpListener;
+    use std::thread;
+    use {Check, Error, Round};
 
     #[test]
     fn test_2p_keygen() {
+        let session_id = SessionId::new_random();
         // round 1
         // party1:
         let (party1_first_message, comm_witness, party1_keys) =
-            Party1KeyGenFirstMsg::create_commitments();
+            Party1KeyGenFirstMsg::create_commitments(&session_id);
         // party2:
-        let (party2_first_message, party2_keys) = Party2KeyGenFirstMsg::create();
+        let (party2_first_message, party2_keys) = Party2KeyGenFirstMsg::create(&session_id);
         // round 2
         // party1
         let party1_second_message = Party1KeyGenSecondMsg::verify_and_decommit(
             comm_witness,
-            &party2_first_message.d_log_proof,
+            &party2_first_message,
+            &session_id,
         )
         .expect("failed to verify and decommit");
         // compute ak:
@@ -61,6 +82,7 @@ mod tests {
         let _party_two_second_message = Party2KeyGenSecondMsg::verify_commitments_and_dlog_proof(
             &party1_first_message,
             &party1_second_message,
+            &session_id,
         )
         .expect("failed to verify commitments and DLog proof");
         let party2_ak = compute_ak(
@@ -71,49 +93,165 @@ mod tests {
         assert_eq!(party1_ak, party2_ak);
     }
 
+    #[test]
+    fn test_2p_keygen_bad_decommitment() {
+        let session_id = SessionId::new_random();
+        let (party1_first_message, comm_witness, _party1_keys) =
+            Party1KeyGenFirstMsg::create_commitments(&session_id);
+        let (party2_first_message, _party2_keys) = Party2KeyGenFirstMsg::create(&session_id);
+        let mut party1_second_message = Party1KeyGenSecondMsg::verify_and_decommit(
+            comm_witness,
+            &party2_first_message,
+            &session_id,
+        )
+        .expect("failed to verify and decommit");
+        party1_second_message.comm_witness.public_share = GE::generator();
+        let result = Party2KeyGenSecondMsg::verify_commitments_and_dlog_proof(
+            &party1_first_message,
+            &party1_second_message,
+            &session_id,
+        );
+        assert_eq!(
+            result.err(),
+            Some(Error::protocol(Round::KeyGen, Check::PkCommitment))
+        );
+    }
+
+    #[test]
+    fn test_2p_keygen_proof_for_other_share() {
+        let session_id = SessionId::new_random();
+        let (party1_first_message, comm_witness, _party1_keys) =
+            Party1KeyGenFirstMsg::create_commitments(&session_id);
+        let (other_first_message, other_witness, _) =
+            Party1KeyGenFirstMsg::create_commitments(&session_id);
+        let (party2_first_message, _party2_keys) = Party2KeyGenFirstMsg::create(&session_id);
+        // party two proves knowledge of a share other than the one it sends
+        let (other_party2_message, _) = Party2KeyGenFirstMsg::create(&session_id);
+        let mut bad_party2_message = party2_first_message.clone();
+        bad_party2_message.d_log_proof = other_party2_message.d_log_proof;
+        let result = Party1KeyGenSecondMsg::verify_and_decommit(
+            comm_witness.clone(),
+            &bad_party2_message,
+            &session_id,
+        );
+        assert_eq!(
+            result.err(),
+            Some(Error::protocol(Round::KeyGen, Check::PublicShare))
+        );
+        // party one commits to its share and to the proof for another one
+        let mut party1_second_message = Party1KeyGenSecondMsg::verify_and_decommit(
+            comm_witness,
+            &party2_first_message,
+            &session_id,
+        )
+        .expect("failed to verify and decommit");
+        party1_second_message.comm_witness.d_log_proof = other_witness.d_log_proof;
+        party1_second_message.comm_witness.zk_pok_blind_factor = other_witness.zk_pok_blind_factor;
+        let bad_party1_first_message = Party1KeyGenFirstMsg {
+            pk_commitment: party1_first_message.pk_commitment,
+            zk_pok_commitment: other_first_message.zk_pok_commitment,
+        };
+        let result = Party2KeyGenSecondMsg::verify_commitments_and_dlog_proof(
+            &bad_party1_first_message,
+            &party1_second_message,
+            &session_id,
+        );
+        assert_eq!(
+            result.err(),
+            Some(Error::protocol(Round::KeyGen, Check::PublicShare))
+        );
+    }
+
+    #[test]
+    fn test_2p_keygen_wrong_session() {
+        let session_id = SessionId::new_random();
+        let other_session_id = SessionId::new_random();
+        let (party1_first_message, comm_witness, _party1_keys) =
+            Party1KeyGenFirstMsg::create_commitments(&session_id);
+        let (party2_first_message, _party2_keys) = Party2KeyGenFirstMsg::create(&session_id);
+        // a proof from another session is rejected:
+        let (party2_replayed_message, _) = Party2KeyGenFirstMsg::create(&other_session_id);
+        assert!(Party1KeyGenSecondMsg::verify_and_decommit(
+            comm_witness.clone(),
+            &party2_replayed_message,
+            &session_id,
+        )
+        .is_err());
+        let party1_second_message = Party1KeyGenSecondMsg::verify_and_decommit(
+            comm_witness,
+            &party2_first_message,
+            &session_id,
+        )
+        .expect("failed to verify and decommit");
+        // so is a decommitment checked under another session:
+        let result = Party2KeyGenSecondMsg::verify_commitments_and_dlog_proof(
+            &party1_first_message,
+            &party1_second_message,
+            &other_session_id,
+        );
+        assert_eq!(
+            result.err(),
+            Some(Error::protocol(Round::KeyGen, Check::PkCommitment))
+        );
+    }
+
     #[test]
     fn test_2p_sign() {
         let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let session_id = SessionId::new_random();
         let message_vec = [
             1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
             1, 1, 1,
         ];
         // round 1
         // party1
-        let (party1_cf_first_message, party1_cf_seed, party1_cf_blinding) =
-            Party1CFFirstMsg::commit();
+        let (party1_cf_first_message, party1_cf_witness) =
+            Party1CFFirstMsg::commit(&session_id, KeyGenRole::PartyOne);
         // party2
-        let party2_cf_first_message = Party2CFFirstMsg::share(&party1_cf_first_message);
+        let party2_cf_first_message =
+            Party2CFFirstMsg::share(&party1_cf_first_message, &session_id, KeyGenRole::PartyTwo)
+                .expect("bad coin flip proof");
         // round 2
         // party1
         let (party1_cf_second_message, party1_alpha) =
-            Party1CFSecondMsg::reveal(&party2_cf_first_message, party1_cf_seed, party1_cf_blinding);
+            Party1CFSecondMsg::reveal(&party2_cf_first_message, party1_cf_witness, &session_id)
+                .expect("bad keygen role");
         let party1_vk = compute_vk(&public_key, &party1_alpha);
         // party2
         let coin_flip_res = CoinFlipResult::finalize(
             &party1_cf_second_message,
             &party2_cf_first_message,
             &party1_cf_first_message,
-        );
+            &session_id,
+        )
+        .expect("bad coin flip proof");
 
         let party2_vk = compute_vk(&public_key, &coin_flip_res.party2_alpha);
 
         assert_eq!(party1_vk, party2_vk);
-        let mut vk_bytes = party1_vk.pk_to_key_slice();
-        vk_bytes.extend_from_slice(&message_vec[..]);
-        let message = BigInt::from(&vk_bytes[..]);
+        let message = SpendAuthMessage::new(&party1_vk, &message_vec[..]).unwrap();
         // round 3
         // party1:
         let (party1_eph_first_message, party1_comm_witness, party1_eph_keys) =
-            Party1EphKeyGenFirstMsg::create_commitments(&party1_vk, &message);
+            Party1EphKeyGenFirstMsg::create_commitments(
+                &party1_keys.ask,
+                &message,
+                &session_id,
+                NonceMode::Hedged,
+            );
         // party2:
-        let (party2_eph_first_message, party2_eph_keys) =
-            Party2EphKeyGenFirstMsg::create(&party2_vk, &message);
+        let (party2_eph_first_message, party2_eph_keys) = Party2EphKeyGenFirstMsg::create(
+            &party2_keys.ask,
+            &message,
+            &session_id,
+            NonceMode::Hedged,
+        );
         // round 4
         // party1
         let party1_eph_second_message = Party1EphKeyGenSecondMsg::verify_and_decommit(
             party1_comm_witness,
             &party2_eph_first_message,
+            &session_id,
         )
         .expect("failed to verify and decommit");
         // compute R:
@@ -123,6 +261,7 @@ mod tests {
             Party2EphKeyGenSecondMsg::verify_commitments_and_dlog_proof(
                 &party1_eph_first_message,
                 &party1_eph_second_message,
+                &session_id,
             )
             .expect("failed to verify commitments and DLog proof");
         let party2_R = compute_R(
@@ -133,6 +272,8 @@ mod tests {
         assert_eq!(party1_R, party2_R);
 
         // round 5
+        let party1_ak_share = party1_keys.ak;
+        let party2_ak_share = party2_keys.ak;
         // party1
         let party1_local_sig = Party1LocalSignatureMsg::compute_s1(
             &party1_R,
@@ -141,7 +282,8 @@ mod tests {
             party1_eph_keys,
             &message,
             &party1_alpha,
-        );
+        )
+        .expect("bad message");
         // party2
         let party2_local_sig = Party2LocalSignatureMsg::compute_s2(
             &party2_R,
@@ -149,7 +291,8 @@ mod tests {
             party2_keys,
             party2_eph_keys,
             &message,
-        );
+        )
+        .expect("bad message");
 
         // party1
         let party1_sig = Party1LocalSignatureMsg::compute(
@@ -157,32 +300,416 @@ mod tests {
             party1_vk,
             &party1_local_sig,
             &party2_local_sig,
+            &party2_eph_first_message.public_share,
+            &party2_ak_share,
             &message,
-        );
+        )
+        .expect("bad signature");
         // party2
         let party2_sig = Party2LocalSignatureMsg::compute(
             party2_R,
             party2_vk,
             &party2_local_sig,
             &party1_local_sig,
+            &party1_eph_second_message.comm_witness.public_share,
+            &compute_local_pk(&party1_ak_share, Some(&coin_flip_res.party2_alpha)),
             &message,
-        );
+        )
+        .expect("bad signature");
 
         assert_eq!(party1_sig, party2_sig);
     }
 
+    #[test]
+    fn test_spend_auth_message() {
+        let vk: GE = ECPoint::generator();
+        let message = SpendAuthMessage::new(&vk, &[0u8; 32]).unwrap();
+        let bytes = message.to_bytes();
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(bytes[..32], vk.pk_to_key_slice()[..]);
+        assert_eq!(bytes[32..], [0u8; 32][..]);
+
+        for sighash in &[&[0u8; 31][..], &[0u8; 33][..], &[][..]] {
+            assert_eq!(
+                SpendAuthMessage::new(&vk, sighash),
+                Err(Error::protocol(Round::Signing, Check::MessageFormat))
+            );
+        }
+    }
+
+    #[test]
+    fn test_signature_encoding() {
+        let (party1_keys, party2_keys, ak) = two_party_keygen();
+        let sig = two_party_sign(party1_keys, party2_keys, ak).expect("signing failed");
+
+        let bytes = sig.to_bytes();
+        assert_eq!(bytes[..32], sig.R.pk_to_key_slice()[..]);
+        assert_eq!(Signature::from_bytes(&bytes), Ok(sig.clone()));
+
+        let json = serde_json::to_string(&sig).expect("failed to serialize");
+        let des_sig: Signature = serde_json::from_str(&json).expect("failed to deserialize");
+        assert_eq!(des_sig, sig);
+
+        assert_eq!(
+            Signature::from_bytes(&bytes[..63]),
+            Err(Error::InvalidEncoding)
+        );
+        // s >= q
+        let mut bad_s = bytes;
+        for byte in bad_s[32..].iter_mut() {
+            *byte = 0xff;
+        }
+        assert_eq!(Signature::from_bytes(&bad_s), Err(Error::InvalidEncoding));
+        // sign bit set on the identity, a non canonical R
+        let mut bad_R = bytes;
+        bad_R[..32].copy_from_slice(&[0u8; 32]);
+        bad_R[0] = 1;
+        bad_R[31] = 0x80;
+        assert_eq!(Signature::from_bytes(&bad_R), Err(Error::InvalidEncoding));
+
+        assert_eq!(vk_from_bytes(&vk_to_bytes(&ak)), Ok(ak));
+        assert_eq!(vk_from_bytes(&bad_R[..32]), Err(Error::InvalidEncoding));
+        assert_eq!(vk_from_bytes(&[0u8; 31]), Err(Error::InvalidEncoding));
+    }
+
+    #[test]
+    fn test_nonce_modes() {
+        let (party1_keys, party2_keys, ak) = two_party_keygen();
+        let session_id = SessionId::new_random();
+        let message = SpendAuthMessage::new(&ak, &[1u8; 32]).unwrap();
+        let eph_share = |ask: &FE, session_id: &SessionId, nonce_mode: NonceMode| {
+            let (_, _, eph_keys) =
+                Party1EphKeyGenFirstMsg::create_commitments(ask, &message, session_id, nonce_mode);
+            eph_keys.R_i
+        };
+
+        let deterministic = eph_share(&party1_keys.ask, &session_id, NonceMode::Deterministic);
+        assert_eq!(
+            deterministic,
+            eph_share(&party1_keys.ask, &session_id, NonceMode::Deterministic)
+        );
+        // bound to the share and the session id
+        assert!(
+            deterministic != eph_share(&party2_keys.ask, &session_id, NonceMode::Deterministic)
+        );
+        let other_session_id = SessionId::new_random();
+        assert!(
+            deterministic
+                != eph_share(
+                    &party1_keys.ask,
+                    &other_session_id,
+                    NonceMode::Deterministic
+                )
+        );
+        // fresh randomness on every call
+        let hedged = eph_share(&party1_keys.ask, &session_id, NonceMode::Hedged);
+        assert!(hedged != deterministic);
+        assert!(hedged != eph_share(&party1_keys.ask, &session_id, NonceMode::Hedged));
+
+        let (message, sig) = two_party_sign_sighash(
+            party1_keys,
+            party2_keys,
+            ak,
+            &[1u8; 32],
+            NonceMode::Deterministic,
+        )
+        .expect("signing failed");
+        assert_eq!(verify(message.vk(), &message, &sig), Ok(()));
+    }
+
+    // stores a state and loads it back, as a cosigner would between two requests
+    pub fn reload<T: Serialize + DeserializeOwned>(state: T) -> T {
+        let stored = VersionedState::new(state)
+            .to_json()
+            .expect("failed to store state");
+        VersionedState::from_json(&stored)
+            .expect("failed to load state")
+            .into_state()
+            .expect("bad state version")
+    }
+
+    // a batch of spends through the ephemeral keygen, with the vk and message of every spend
+    pub fn two_party_batch_eph_keygen(
+        spends: u32,
+        party1_store: &mut MemoryNonceStore,
+        party2_store: &mut MemoryNonceStore,
+    ) -> (
+        Party1BatchSigningSession<Party1EphKeyGenDone>,
+        Party2BatchSigningSession<Party2EphKeyGenDone>,
+        Vec<GE>,
+        Vec<SpendAuthMessage>,
+        GE,
+    ) {
+        let (party1_keys, party2_keys, public_key) = two_party_keygen();
+        let session_id = SessionId::new_random();
+        let party1_batch =
+            Party1BatchSigningSession::new(party1_keys, public_key, &session_id, spends);
+        let party2_batch =
+            Party2BatchSigningSession::new(party2_keys, public_key, &session_id, spends);
+
+        let (party1_cf_first_messages, party1_batch) = party1_batch.coin_flip_commit();
+        let (party2_cf_first_messages, party2_batch) = party2_batch
+            .coin_flip_share(&party1_cf_first_messages)
+            .expect("bad coin flip proof");
+        let (party1_cf_second_messages, party1_batch) = party1_batch
+            .coin_flip_reveal(&party2_cf_first_messages)
+            .expect("bad batch size");
+        let party2_batch = party2_batch
+            .coin_flip_finalize(&party1_cf_second_messages)
+            .expect("bad coin flip proof");
+        let vks = party1_batch.vks();
+        assert_eq!(vks, party2_batch.vks());
+
+        let messages = vks
+            .iter()
+            .enumerate()
+            .map(|(i, vk)| SpendAuthMessage::new(vk, &[i as u8 + 1; 32]).unwrap())
+            .collect::<Vec<_>>();
+
+        let (party1_eph_first_messages, party1_batch) = party1_batch
+            .eph_keygen_commit_guarded(&messages, party1_store)
+            .expect("bad batch size");
+        let (party2_eph_first_messages, party2_batch) = party2_batch
+            .eph_keygen_create_guarded(&party1_eph_first_messages, &messages, party2_store)
+            .expect("bad batch size");
+        let (party1_eph_second_messages, party1_batch) = party1_batch
+            .eph_keygen_decommit(&party2_eph_first_messages)
+            .expect("failed to verify and decommit");
+        let party2_batch = party2_batch
+            .eph_keygen_verify(&party1_eph_second_messages)
+            .expect("failed to verify commitments and DLog proof");
+        (party1_batch, party2_batch, vks, messages, public_key)
+    }
+
+    #[test]
+    fn test_session_id_child() {
+        let session_id = SessionId(vec![1u8, 2]);
+        assert_eq!(session_id.child(3), session_id.child(3));
+        assert!(session_id.child(3) != session_id.child(4));
+        // neither another session id nor the child of another one
+        assert!(session_id.child(3) != SessionId(vec![1u8, 2, 3, 0, 0, 0]));
+        assert!(session_id.child(3) != SessionId(vec![1u8]).child(0x0302));
+        assert!(session_id.child(3).child(0) != session_id.child(3));
+        assert_eq!(session_id.child(3).0.len(), 32);
+    }
+
+    pub fn serve_cosigner(cosigner: Cosigner) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind");
+        let addr = listener.local_addr().expect("no local address").to_string();
+        thread::spawn(move || cosigner.serve(listener));
+        addr
+    }
+
+    pub fn details(value: u64, recipient: &str) -> Option<TransactionDetails> {
+        Some(TransactionDetails {
+            value,
+            recipient: recipient.to_string(),
+        })
+    }
+
+    // (ask, nsk, ak, nk, ovk, dk, chain code, parent fvk tag) of m, m/1, m/1/2' and m/1/2'/3 for
+    // the seed 0, 1, .., 31 of the sapling_zip32 vectors of zcash-test-vectors
+    // (https://github.com/zcash/zcash-test-vectors). m/1/2'/3 is derived from the full viewing key
+    // of m/1/2', its ask and nsk are not part of the vectors
+    pub const ZIP32_VECTORS: [[&str; 8]; 4] = [
+        [
+            "b6c00c93d36032b9a268e99e86a860776560bf0e83c1a10b51f607c954742506",
+            "8204ede83b2f1fbd84f9b45d7f996e2ebd0a030ad243b48ed39f748a8821ea06",
+            "93442e5feffbff16e7217202dc7306729ffffe85af5683bce2642e3eeb5d3871",
+            "dce8e7edece04b8950417f85ba57691b783c45b1a27422db1693dceb67b10106",
+            "395884890323b9d4933c021db89bcf767df21977b2ff0683848321a4df4afb21",
+            "77c17cb75b7796afb39f0f3e91c924607da56fa9a20e283509bc8a3ef996a172",
+            "d0947c4b03bf72a37ab44f72276d1cf3fdcd7ebf3e73348b7e550d752018668e",
+            "00000000",
+        ],
+        [
+            "282bc197a516287c8ea8f68c424abad302b45cdf95407961d7b8b455267a350c",
+            "e7a32988fdca1efcd6d1c4c562e629c2e96b2c3f7eda04ac4efd1810ff6bba01",
+            "dc14b514d3a92594c21925af2f7765a547b30e73fa7b700ea1bff2e5efaaa88b",
+            "6152eb7fdb252779ddcb95d217ea4b6fd34036e9adadb3b5c9cbeceb41ba452a",
+            "5f1381fc8886da6a02dffeefcf503c40fa8f5a36f7a7142fd81b5518c5a47474",
+            "e04de832a2d791ec129ab9002b91c9e9cdeed79241a7c4960e5178d870c1b4dc",
+            "0147110c691a03b9d9f0ba9005c5e790a595b7f04e3329d2fa438a6705dabce6",
+            "14c2713a",
+        ],
+        [
+            "8be8113cee3413a71f82c41fc8da517be134049832e6825c92da6b84fee4c60d",
+            "3778059dc569e7d0d32391573f951bbde92fc6b9cf614773661c5c273aa6990c",
+            "a6c5925a0f85fa4f1e405e3a4970d0c4a4b4814438f4e9d4520e20f7fdcf3841",
+            "304e305916216beb7b654d8aae50ecd188fcb384bc36c00c664f307725e2ee11",
+            "cf81182e96223c028ce3d6eb4794d3113b95069d14c57588e193b65efc2813bc",
+            "a3eda19f9eff46ca12dfa1bf10371b48d1b4a40c4d05a0d8dce0e7dc62b07b37",
+            "97ce15f4ed1b9739b0262a463bcb3dc9b3bd2323a9baa441ca42777383a8d435",
+            "db999e07",
+        ],
+        [
+            "",
+            "",
+            "b185c57b509c2536c4f2d326d766c8fab25447de5375a9328d649ddabd97a6a3",
+            "db88049e02d207568afc42e07db2abed500b2701c01bbff36399764b81c0664f",
+            "69b9e0fa1c4b3deb91d53beee871156121474b8b62ef24134478dc3499691af6",
+            "becb50c363bb2ed9da5c3043ceb0f1a0527bf836b29a35f7c0c9f261123be56e",
+            "8d937bcf81ba430d5b49afc0a403367b1fd99879ecba41be051c5a4aa7d6e7e8",
+            "48c18375",
+        ],
+    ];
+
+    pub const ZIP32_PATHS: [&[u32]; 4] = [&[], &[1], &[1, 2 | HARDENED], &[1, 2 | HARDENED, 3]];
+
+    pub fn bytes_32(hex: &str) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&from_hex(hex).unwrap());
+        bytes
+    }
+
+    // scalars of the vectors are little endian
+    pub fn scalar_from_hex(hex: &str) -> FE {
+        let mut bytes = from_hex(hex).unwrap();
+        bytes.reverse();
+        ECScalar::from(&BigInt::from(&bytes[..]))
+    }
+
+    // the key of ZIP32_VECTORS[i]
+    pub fn zip32_vector(i: usize) -> ExtendedPublicKey {
+        let vector = &ZIP32_VECTORS[i];
+        let path = ZIP32_PATHS[i];
+        let mut parent_fvk_tag = [0u8; 4];
+        parent_fvk_tag.copy_from_slice(&from_hex(vector[7]).unwrap());
+        ExtendedPublicKey {
+            depth: path.len() as u8,
+            parent_fvk_tag,
+            child_index: path.last().cloned().unwrap_or(0),
+            ak: vk_from_bytes(&from_hex(vector[2]).unwrap()).unwrap(),
+            nk: vk_from_bytes(&from_hex(vector[3]).unwrap()).unwrap(),
+            ovk: bytes_32(vector[4]),
+            dk: bytes_32(vector[5]),
+            chain_code: bytes_32(vector[6]),
+        }
+    }
+
+    pub fn two_party_sign(
+        party1_keys: EcKeyPair,
+        party2_keys: EcKeyPair,
+        ak: GE,
+    ) -> Result<Signature, Error> {
+        two_party_sign_sighash(party1_keys, party2_keys, ak, &[1u8; 32], NonceMode::Hedged)
+            .map(|(_, sig)| sig)
+    }
+
+    pub fn two_party_sign_sighash(
+        party1_keys: EcKeyPair,
+        party2_keys: EcKeyPair,
+        ak: GE,
+        sighash: &[u8],
+        nonce_mode: NonceMode,
+    ) -> Result<(SpendAuthMessage, Signature), Error> {
+        let session_id = SessionId::new_random();
+        let party1_session = Party1SigningSession::new(party1_keys, ak, session_id.clone())
+            .with_nonce_mode(nonce_mode);
+        let party2_session =
+            Party2SigningSession::new(party2_keys, ak, session_id).with_nonce_mode(nonce_mode);
+        let (party1_cf_first_message, party1_session) = party1_session.coin_flip_commit();
+        let (party2_cf_first_message, party2_session) =
+            party2_session.coin_flip_share(&party1_cf_first_message)?;
+        let (party1_cf_second_message, party1_session) =
+            party1_session.coin_flip_reveal(&party2_cf_first_message)?;
+        let party2_session = party2_session.coin_flip_finalize(&party1_cf_second_message)?;
+
+        let message = SpendAuthMessage::new(&party1_session.vk(), sighash)?;
+
+        let (party1_eph_first_message, party1_session) = party1_session.eph_keygen_commit(&message);
+        let (party2_eph_first_message, party2_session) =
+            party2_session.eph_keygen_create(&party1_eph_first_message, &message);
+        let (party1_eph_second_message, party1_session) =
+            party1_session.eph_keygen_decommit(&party2_eph_first_message)?;
+        let (_, party2_session) = party2_session.eph_keygen_verify(&party1_eph_second_message)?;
+        let (_, party1_session) = party1_session.local_sign()?;
+        let (party2_local_sig, _) = party2_session.local_sign()?;
+        let sig = party1_session.finalize(&party2_local_sig)?;
+        Ok((message, sig))
+    }
+
+    // the rounds of party_one and party_two with alpha fixed instead of flipped
+    pub fn two_party_sign_with_alpha(
+        party1_keys: EcKeyPair,
+        party2_keys: EcKeyPair,
+        ak: GE,
+        alpha: &FE,
+        sighash: &[u8],
+    ) -> Result<(SpendAuthMessage, Signature), Error> {
+        let session_id = SessionId::new_random();
+        let vk = compute_vk(&ak, alpha);
+        let message = SpendAuthMessage::new(&vk, sighash)?;
+        let party2_ak_share = party2_keys.ak;
+        let (party1_eph_first_message, party1_comm_witness, party1_eph_keys) =
+            Party1EphKeyGenFirstMsg::create_commitments(
+                &party1_keys.ask,
+                &message,
+                &session_id,
+                NonceMode::Hedged,
+            );
+        let (party2_eph_first_message, party2_eph_keys) = Party2EphKeyGenFirstMsg::create(
+            &party2_keys.ask,
+            &message,
+            &session_id,
+            NonceMode::Hedged,
+        );
+        let party1_eph_second_message = Party1EphKeyGenSecondMsg::verify_and_decommit(
+            party1_comm_witness,
+            &party2_eph_first_message,
+            &session_id,
+        )?;
+        Party2EphKeyGenSecondMsg::verify_commitments_and_dlog_proof(
+            &party1_eph_first_message,
+            &party1_eph_second_message,
+            &session_id,
+        )?;
+        let party1_R = compute_R(&party1_eph_keys, &party2_eph_first_message.public_share);
+        let party2_R = compute_R(
+            &party2_eph_keys,
+            &party1_eph_second_message.comm_witness.public_share,
+        );
+        let party1_local_sig = Party1LocalSignatureMsg::compute_s1(
+            &party1_R,
+            &vk,
+            party1_keys,
+            party1_eph_keys,
+            &message,
+            alpha,
+        )?;
+        let party2_local_sig = Party2LocalSignatureMsg::compute_s2(
+            &party2_R,
+            &vk,
+            party2_keys,
+            party2_eph_keys,
+            &message,
+        )?;
+        let sig = Party1LocalSignatureMsg::compute(
+            party1_R,
+            vk,
+            &party1_local_sig,
+            &party2_local_sig,
+            &party2_eph_first_message.public_share,
+            &party2_ak_share,
+            &message,
+        )?;
+        Ok((message, sig))
+    }
+
     pub fn two_party_keygen() -> (EcKeyPair, EcKeyPair, GE) {
+        let session_id = SessionId::new_random();
         // round 1
         // party1:
         let (party1_first_message, comm_witness, party1_keys) =
-            Party1KeyGenFirstMsg::create_commitments();
+            Party1KeyGenFirstMsg::create_commitments(&session_id);
         // party2:
-        let (party2_first_message, party2_keys) = Party2KeyGenFirstMsg::create();
+        let (party2_first_message, party2_keys) = Party2KeyGenFirstMsg::create(&session_id);
         // round 2
         // party1
         let party1_second_message = Party1KeyGenSecondMsg::verify_and_decommit(
             comm_witness,
-            &party2_first_message.d_log_proof,
+            &party2_first_message,
+            &session_id,
         )
         .expect("failed to verify and decommit");
         // compute ak:
@@ -191,6 +718,7 @@ mod tests {
         let _party_two_second_message = Party2KeyGenSecondMsg::verify_commitments_and_dlog_proof(
             &party1_first_message,
             &party1_second_message,
+            &session_id,
         )
         .expect("failed to verify commitments and DLog proof");
         let _party2_ak = compute_ak(
@@ -0,0 +1,477 @@
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Transports the round messages between the two parties.
+//! A Transport moves opaque frames, send and receive put a label and the json encoding of a
+//! round message into a frame. The receiver names the label it expects, so a message sent out of
+//! order is rejected instead of being decoded as another round.
+//!
+//! ChannelTransport connects two parties in one process, TcpTransport connects them over a TCP
+//! stream with length prefixed frames, FileTransport writes every frame to a numbered file for
+//! parties that exchange files instead of connecting. None of them encrypts or authenticates:
+//! over a network, run TcpTransport inside a channel that does, e.g. TLS with client
+//! certificates.
+//!
+//! AsyncTransport is the same for the async_driver: send and receive return futures, and the
+//! transport is moved into each of them and handed back once it completes. AsyncChannelTransport
+//! connects two parties in one process, AsyncTcpTransport sends the frames of TcpTransport, so an
+//! async party can talk to a blocking one. Other transports implement send_frame and
+//! receive_frame on top of whatever event loop they run on.
+//!
+//! The futures are futures 0.1 ones: the crate is built as edition 2015 against the pinned 2018
+//! dependency set, which has neither async/await nor std::future.
+
+use futures::future;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::{Future, Stream};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use Error;
+
+/// frames above this size are rejected before they are read
+pub const MAX_FRAME_SIZE: usize = 1 << 20;
+
+#[derive(Serialize, Deserialize)]
+struct Frame {
+    label: String,
+    payload: String,
+}
+
+fn encode_frame<T: Serialize>(label: &str, msg: &T) -> Result<Vec<u8>, Error> {
+    let frame = Frame {
+        label: label.to_string(),
+        payload: serde_json::to_string(msg).map_err(|_| Error::InvalidEncoding)?,
+    };
+    serde_json::to_vec(&frame).map_err(|_| Error::InvalidEncoding)
+}
+
+fn decode_frame<T: DeserializeOwned>(frame: &[u8], label: &str) -> Result<T, Error> {
+    let frame: Frame = serde_json::from_slice(frame).map_err(|_| Error::InvalidEncoding)?;
+    if frame.label != label {
+        return Err(Error::InvalidEncoding);
+    }
+    serde_json::from_str(&frame.payload).map_err(|_| Error::InvalidEncoding)
+}
+
+pub trait Transport {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), Error>;
+
+    fn receive_frame(&mut self) -> Result<Vec<u8>, Error>;
+
+    fn send<T: Serialize>(&mut self, label: &str, msg: &T) -> Result<(), Error> {
+        let frame = encode_frame(label, msg)?;
+        self.send_frame(&frame)
+    }
+
+    fn receive<T: DeserializeOwned>(&mut self, label: &str) -> Result<T, Error> {
+        let frame = self.receive_frame()?;
+        decode_frame(&frame, label)
+    }
+}
+
+/// what the operations of an AsyncTransport resolve to
+pub type TransportFuture<I> = Box<dyn Future<Item = I, Error = Error> + Send>;
+
+pub trait AsyncTransport: Sized + Send + 'static {
+    fn send_frame(self, frame: Vec<u8>) -> TransportFuture<Self>;
+
+    fn receive_frame(self) -> TransportFuture<(Self, Vec<u8>)>;
+
+    fn send<T: Serialize>(self, label: &str, msg: &T) -> TransportFuture<Self> {
+        match encode_frame(label, msg) {
+            Ok(frame) => self.send_frame(frame),
+            Err(e) => Box::new(future::err(e)),
+        }
+    }
+
+    fn receive<T: DeserializeOwned + Send + 'static>(
+        self,
+        label: &str,
+    ) -> TransportFuture<(Self, T)> {
+        let label = label.to_string();
+        Box::new(self.receive_frame().and_then(move |(transport, frame)| {
+            decode_frame::<T>(&frame, &label).map(|msg| (transport, msg))
+        }))
+    }
+}
+
+/// one end of an in-process connection that does not block
+pub struct AsyncChannelTransport {
+    sender: UnboundedSender<Vec<u8>>,
+    receiver: UnboundedReceiver<Vec<u8>>,
+}
+
+impl AsyncChannelTransport {
+    /// both ends of a connection, the futures of the two parties can run on one thread
+    pub fn pair() -> (AsyncChannelTransport, AsyncChannelTransport) {
+        let (sender1, receiver2) = unbounded();
+        let (sender2, receiver1) = unbounded();
+        (
+            AsyncChannelTransport {
+                sender: sender1,
+                receiver: receiver1,
+            },
+            AsyncChannelTransport {
+                sender: sender2,
+                receiver: receiver2,
+            },
+        )
+    }
+}
+
+impl AsyncTransport for AsyncChannelTransport {
+    fn send_frame(self, frame: Vec<u8>) -> TransportFuture<AsyncChannelTransport> {
+        let sent = self
+            .sender
+            .unbounded_send(frame)
+            .map(|_| self)
+            .map_err(|_| Error::Transport);
+        Box::new(future::result(sent))
+    }
+
+    fn receive_frame(self) -> TransportFuture<(AsyncChannelTransport, Vec<u8>)> {
+        let sender = self.sender;
+        Box::new(
+            self.receiver
+                .into_future()
+                .map_err(|_| Error::Transport)
+                .and_then(move |(frame, receiver)| match frame {
+                    Some(frame) => Ok((AsyncChannelTransport { sender, receiver }, frame)),
+                    // the other end is gone
+                    None => Err(Error::Transport),
+                }),
+        )
+    }
+}
+
+/// TcpTransport for the async_driver, on std sockets. Every connection has a reader thread that
+/// reads frames ahead into a channel and a writer thread that writes the frames sent, so waiting
+/// for the counterparty holds these two threads but never the executor. A send resolves once its
+/// frame is queued, a failed write shows in the next send or receive. Dropping the transport
+/// writes the queued frames and closes the connection.
+pub struct AsyncTcpTransport {
+    sender: Sender<Vec<u8>>,
+    receiver: UnboundedReceiver<Result<Vec<u8>, Error>>,
+}
+
+impl AsyncTcpTransport {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<AsyncTcpTransport, Error> {
+        let stream = TcpStream::connect(addr).map_err(|_| Error::Transport)?;
+        AsyncTcpTransport::from_stream(stream)
+    }
+
+    /// waits for the next connection on listener, this blocks
+    pub fn accept(listener: &TcpListener) -> Result<AsyncTcpTransport, Error> {
+        let (stream, _) = listener.accept().map_err(|_| Error::Transport)?;
+        AsyncTcpTransport::from_stream(stream)
+    }
+
+    pub fn from_stream(stream: TcpStream) -> Result<AsyncTcpTransport, Error> {
+        let mut reader =
+            TcpTransport::from_stream(stream.try_clone().map_err(|_| Error::Transport)?);
+        let mut writer = TcpTransport::from_stream(stream);
+        let (frame_sender, frame_receiver) = unbounded();
+        thread::spawn(move || loop {
+            let frame = reader.receive_frame();
+            let failed = frame.is_err();
+            // the transport is gone, or the connection: either way nothing more to read
+            if frame_sender.unbounded_send(frame).is_err() || failed {
+                break;
+            }
+        });
+        let (sender, receiver) = channel::<Vec<u8>>();
+        thread::spawn(move || {
+            for frame in receiver {
+                if writer.send_frame(&frame).is_err() {
+                    break;
+                }
+            }
+            // ends the reader thread as well
+            let _ = writer.stream.shutdown(Shutdown::Both);
+        });
+        Ok(AsyncTcpTransport {
+            sender,
+            receiver: frame_receiver,
+        })
+    }
+}
+
+impl AsyncTransport for AsyncTcpTransport {
+    fn send_frame(self, frame: Vec<u8>) -> TransportFuture<AsyncTcpTransport> {
+        if frame.len() > MAX_FRAME_SIZE {
+            return Box::new(future::err(Error::InvalidEncoding));
+        }
+        let sent = self
+            .sender
+            .send(frame)
+            .map(|_| self)
+            .map_err(|_| Error::Transport);
+        Box::new(future::result(sent))
+    }
+
+    fn receive_frame(self) -> TransportFuture<(AsyncTcpTransport, Vec<u8>)> {
+        let sender = self.sender;
+        Box::new(
+            self.receiver
+                .into_future()
+                .map_err(|_| Error::Transport)
+                .and_then(move |(frame, receiver)| match frame {
+                    Some(Ok(frame)) => Ok((AsyncTcpTransport { sender, receiver }, frame)),
+                    Some(Err(e)) => Err(e),
+                    // the reader thread is gone
+                    None => Err(Error::Transport),
+                }),
+        )
+    }
+}
+
+/// one end of an in-process connection
+pub struct ChannelTransport {
+    sender: Sender<Vec<u8>>,
+    receiver: Receiver<Vec<u8>>,
+}
+
+impl ChannelTransport {
+    /// both ends of a connection, to be moved to the threads of the two parties
+    pub fn pair() -> (ChannelTransport, ChannelTransport) {
+        let (sender1, receiver2) = channel();
+        let (sender2, receiver1) = channel();
+        (
+            ChannelTransport {
+                sender: sender1,
+                receiver: receiver1,
+            },
+            ChannelTransport {
+                sender: sender2,
+                receiver: receiver2,
+            },
+        )
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), Error> {
+        self.sender
+            .send(frame.to_vec())
+            .map_err(|_| Error::Transport)
+    }
+
+    fn receive_frame(&mut self) -> Result<Vec<u8>, Error> {
+        self.receiver.recv().map_err(|_| Error::Transport)
+    }
+}
+
+/// frames are sent as a u32 little endian length followed by the frame
+pub struct TcpTransport {
+    stream: TcpStream,
+}
+
+impl TcpTransport {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<TcpTransport, Error> {
+        let stream = TcpStream::connect(addr).map_err(|_| Error::Transport)?;
+        Ok(TcpTransport::from_stream(stream))
+    }
+
+    /// waits for the next connection on listener
+    pub fn accept(listener: &TcpListener) -> Result<TcpTransport, Error> {
+        let (stream, _) = listener.accept().map_err(|_| Error::Transport)?;
+        Ok(TcpTransport::from_stream(stream))
+    }
+
+    pub fn from_stream(stream: TcpStream) -> TcpTransport {
+        TcpTransport { stream }
+    }
+
+    /// a receive waiting longer than timeout fails with Error::Transport, None waits forever
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.stream
+            .set_read_timeout(timeout)
+            .map_err(|_| Error::Transport)
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), Error> {
+        if frame.len() > MAX_FRAME_SIZE {
+            return Err(Error::InvalidEncoding);
+        }
+        let len = (frame.len() as u32).to_le_bytes();
+        self.stream
+            .write_all(&len)
+            .and_then(|_| self.stream.write_all(frame))
+            .and_then(|_| self.stream.flush())
+            .map_err(|_| Error::Transport)
+    }
+
+    fn receive_frame(&mut self) -> Result<Vec<u8>, Error> {
+        let mut len = [0u8; 4];
+        self.stream
+            .read_exact(&mut len)
+            .map_err(|_| Error::Transport)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_FRAME_SIZE {
+            return Err(Error::InvalidEncoding);
+        }
+        let mut frame = vec![0u8; len];
+        self.stream
+            .read_exact(&mut frame)
+            .map_err(|_| Error::Transport)?;
+        Ok(frame)
+    }
+}
+
+/// Frames go to outbox as 0000.frame, 0001.frame, ... and are read from inbox in the same order.
+/// The outbox of one party is the inbox of the other, moving the files in between is up to the
+/// operator.
+pub struct FileTransport {
+    outbox: PathBuf,
+    inbox: PathBuf,
+    sent: u32,
+    received: u32,
+    timeout: Option<Duration>,
+}
+
+const FILE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+fn frame_file_name(index: u32) -> String {
+    format!("{:04}.frame", index)
+}
+
+impl FileTransport {
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(
+        outbox: P,
+        inbox: Q,
+    ) -> Result<FileTransport, Error> {
+        fs::create_dir_all(outbox.as_ref()).map_err(|_| Error::Transport)?;
+        Ok(FileTransport {
+            outbox: outbox.as_ref().to_path_buf(),
+            inbox: inbox.as_ref().to_path_buf(),
+            sent: 0,
+            received: 0,
+            timeout: None,
+        })
+    }
+
+    /// a receive waiting longer than timeout fails with Error::Transport, None waits forever
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+}
+
+impl Transport for FileTransport {
+    fn send_frame(&mut self, frame: &[u8]) -> Result<(), Error> {
+        let path = self.outbox.join(frame_file_name(self.sent));
+        // renamed into place, the other party never reads a partial frame
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, frame)
+            .and_then(|_| fs::rename(&tmp_path, &path))
+            .map_err(|_| Error::Transport)?;
+        self.sent += 1;
+        Ok(())
+    }
+
+    fn receive_frame(&mut self) -> Result<Vec<u8>, Error> {
+        let path = self.inbox.join(frame_file_name(self.received));
+        let start = Instant::now();
+        while !path.exists() {
+            if let Some(timeout) = self.timeout {
+                if start.elapsed() > timeout {
+                    return Err(Error::Transport);
+                }
+            }
+            thread::sleep(FILE_POLL_INTERVAL);
+        }
+        let len = fs::metadata(&path).map_err(|_| Error::Transport)?.len();
+        if len > MAX_FRAME_SIZE as u64 {
+            return Err(Error::InvalidEncoding);
+        }
+        let frame = fs::read(&path).map_err(|_| Error::Transport)?;
+        self.received += 1;
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::elliptic::curves::rand::{thread_rng, Rng};
+    use protocols::two_party::compute_ak;
+    use protocols::two_party::driver;
+    use protocols::two_party::party_one::KeyGenFirstMsg as Party1KeyGenFirstMsg;
+    use protocols::two_party::party_one::KeyGenSecondMsg as Party1KeyGenSecondMsg;
+    use protocols::two_party::party_two::KeyGenFirstMsg as Party2KeyGenFirstMsg;
+    use protocols::two_party::session::Party1KeyGenCommitted;
+    use protocols::two_party::transport::{ChannelTransport, FileTransport, Transport};
+    use protocols::two_party::SessionId;
+    use std::env;
+    use std::fs;
+    use std::time::Duration;
+    use Error;
+
+    #[test]
+    fn test_transport_out_of_order() {
+        let (mut party1_transport, mut party2_transport) = ChannelTransport::pair();
+        let (first_msg, _) = Party1KeyGenCommitted::commit(&SessionId::new_random());
+        party1_transport
+            .send("keygen_first", &first_msg)
+            .expect("failed to send");
+        // party two expects another round
+        match party2_transport.receive::<Party1KeyGenSecondMsg>("keygen_second") {
+            Err(e) => assert_eq!(e, Error::InvalidEncoding),
+            Ok(_) => panic!("message of another round accepted"),
+        }
+        // the other end is gone
+        drop(party1_transport);
+        match party2_transport.receive::<Party1KeyGenFirstMsg>("keygen_first") {
+            Err(e) => assert_eq!(e, Error::Transport),
+            Ok(_) => panic!("receive on a closed transport"),
+        }
+    }
+
+    #[test]
+    fn test_file_transport() {
+        let dir = env::temp_dir().join(format!(
+            "paradise-city-frames-{}",
+            thread_rng().gen::<u64>()
+        ));
+        let (party1_outbox, party2_outbox) = (dir.join("party1"), dir.join("party2"));
+        let party1_transport =
+            FileTransport::new(&party1_outbox, &party2_outbox).expect("bad outbox");
+        let party2_transport =
+            FileTransport::new(&party2_outbox, &party1_outbox).expect("bad outbox");
+        let (party1_keys, party2_keys, ak) =
+            driver::run_keygen(party1_transport, party2_transport, &SessionId::new_random())
+                .expect("keygen failed");
+        assert_eq!(compute_ak(&party1_keys, &party2_keys.ak), ak);
+
+        // nothing ever arrives
+        let mut transport =
+            FileTransport::new(dir.join("party3"), dir.join("nobody")).expect("bad outbox");
+        transport.set_timeout(Some(Duration::from_millis(500)));
+        match transport.receive::<Party2KeyGenFirstMsg>("keygen_first") {
+            Err(e) => assert_eq!(e, Error::Transport),
+            Ok(_) => panic!("received a frame nobody sent"),
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
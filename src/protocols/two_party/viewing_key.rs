@@ -0,0 +1,349 @@
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Sapling viewing keys and payment addresses of a two-party ak.
+//! ask never exists in one place, but everything a wallet needs besides it does:
+//!
+//! - ProofGenerationKey (ak, nsk), for whoever builds the spend proofs. nsk is known to both
+//!   parties when it comes from ExtendedPublicKey::create, any other nsk works as well
+//! - FullViewingKey (ak, nk, ovk) and ivk = CRH^ivk(ak, nk)
+//! - diversifiers d_j = FF1-AES256(dk, j) of ZIP 32 and payment addresses (d, ivk*g_d)
+//! - the bech32 encodings of payment addresses and of ExtendedPublicKey (zxviews)
+//!
+//! None of them depends on the shares, both parties derive the same addresses from the
+//! ExtendedPublicKey of keygen.
+
+use super::{fe_from_digest, vk_from_bytes, vk_to_bytes};
+use aes::Aes256;
+use bech32::{self, FromBase32, ToBase32, Variant};
+use blake2_rfc::blake2b::Blake2b;
+use blake2_rfc::blake2s::Blake2s;
+use curv::elliptic::curves::curve_jubjub::{FE, GE};
+use fpe::ff1::{BinaryNumeralString, FF1};
+use Error;
+
+pub const DIVERSIFIER_SIZE: usize = 11;
+pub const PAYMENT_ADDRESS_SIZE: usize = DIVERSIFIER_SIZE + 32;
+pub const FULL_VIEWING_KEY_SIZE: usize = 96;
+
+const IVK_PERSONA: &[u8] = b"Zcashivk";
+const DIVERSIFY_PERSONA: &[u8] = b"Zcash_gd";
+const FINGERPRINT_PERSONA: &[u8] = b"ZcashSaplingFVFP";
+
+/// selects the bech32 human readable parts
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Network {
+    Main,
+    Test,
+}
+
+impl Network {
+    pub fn payment_address_hrp(&self) -> &'static str {
+        match *self {
+            Network::Main => "zs",
+            Network::Test => "ztestsapling",
+        }
+    }
+
+    pub fn extended_key_hrp(&self) -> &'static str {
+        match *self {
+            Network::Main => "zxviews",
+            Network::Test => "zxviewtestsapling",
+        }
+    }
+}
+
+pub(super) fn bech32_encode(hrp: &str, bytes: &[u8]) -> String {
+    // only fails on an invalid hrp, the hrps of Network are valid
+    bech32::encode(hrp, bytes.to_base32(), Variant::Bech32).unwrap()
+}
+
+pub(super) fn bech32_decode(hrp: &str, encoded: &str) -> Result<Vec<u8>, Error> {
+    match bech32::decode(encoded) {
+        Ok((ref decoded_hrp, ref data, Variant::Bech32)) if decoded_hrp == hrp => {
+            Vec::<u8>::from_base32(data).map_err(|_| Error::InvalidEncoding)
+        }
+        _ => Err(Error::InvalidEncoding),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProofGenerationKey {
+    pub ak: GE,
+    pub nsk: FE,
+}
+
+impl ProofGenerationKey {
+    pub fn new(ak: &GE, nsk: &FE) -> ProofGenerationKey {
+        ProofGenerationKey { ak: *ak, nsk: *nsk }
+    }
+
+    pub fn nk(&self) -> GE {
+        GE::proof_generation_generator() * &self.nsk
+    }
+
+    pub fn full_viewing_key(&self, ovk: &[u8; 32]) -> FullViewingKey {
+        FullViewingKey {
+            ak: self.ak,
+            nk: self.nk(),
+            ovk: *ovk,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FullViewingKey {
+    pub ak: GE,
+    pub nk: GE,
+    pub ovk: [u8; 32],
+}
+
+impl FullViewingKey {
+    /// ak || nk || ovk
+    pub fn to_bytes(&self) -> [u8; FULL_VIEWING_KEY_SIZE] {
+        let mut bytes = [0u8; FULL_VIEWING_KEY_SIZE];
+        bytes[..32].copy_from_slice(&vk_to_bytes(&self.ak));
+        bytes[32..64].copy_from_slice(&vk_to_bytes(&self.nk));
+        bytes[64..].copy_from_slice(&self.ovk);
+        bytes
+    }
+
+    /// CRH^ivk(ak, nk), the low 251 bits of a Blake2s hash
+    pub fn ivk(&self) -> FE {
+        let mut digest = Blake2s::with_params(32, &[], &[], IVK_PERSONA);
+        digest.update(&vk_to_bytes(&self.ak));
+        digest.update(&vk_to_bytes(&self.nk));
+        let mut ivk = digest.finalize().as_bytes().to_vec();
+        ivk[31] &= 0b0000_0111;
+        // below 2^251 < q, fe_from_digest does not reduce it
+        fe_from_digest(&ivk)
+    }
+
+    /// fingerprint of ZIP 32, the first 4 bytes are the tag the children of the key carry
+    pub fn fingerprint(&self) -> [u8; 32] {
+        let mut digest = Blake2b::with_params(32, &[], &[], FINGERPRINT_PERSONA);
+        digest.update(&self.to_bytes());
+        let mut fingerprint = [0u8; 32];
+        fingerprint.copy_from_slice(digest.finalize().as_bytes());
+        fingerprint
+    }
+
+    /// None if d is not a valid diversifier
+    pub fn address(&self, diversifier: &Diversifier) -> Option<PaymentAddress> {
+        let g_d = diversifier.g_d()?;
+        Some(PaymentAddress {
+            diversifier: *diversifier,
+            pk_d: g_d * &self.ivk(),
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diversifier(pub [u8; DIVERSIFIER_SIZE]);
+
+impl Diversifier {
+    /// d_j of ZIP 32. The index j has 88 bits there, 64 of them are enough here
+    pub fn from_index(dk: &[u8; 32], index: u64) -> Diversifier {
+        let mut j = [0u8; DIVERSIFIER_SIZE];
+        j[..8].copy_from_slice(&index.to_le_bytes());
+        // a 32 byte key, radix 2 and 88 numerals are all in range
+        let ff1 = FF1::<Aes256>::new(dk, 2).unwrap();
+        let d = ff1
+            .encrypt(&[], &BinaryNumeralString::from_bytes_le(&j))
+            .unwrap()
+            .to_bytes_le();
+        let mut diversifier = [0u8; DIVERSIFIER_SIZE];
+        diversifier.copy_from_slice(&d);
+        Diversifier(diversifier)
+    }
+
+    /// DiversifyHash, None for about half of all diversifiers
+    pub fn g_d(&self) -> Option<GE> {
+        GE::group_hash(&self.0, DIVERSIFY_PERSONA)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PaymentAddress {
+    pub diversifier: Diversifier,
+    pub pk_d: GE,
+}
+
+impl PaymentAddress {
+    /// d || pk_d
+    pub fn to_bytes(&self) -> [u8; PAYMENT_ADDRESS_SIZE] {
+        let mut bytes = [0u8; PAYMENT_ADDRESS_SIZE];
+        bytes[..DIVERSIFIER_SIZE].copy_from_slice(&self.diversifier.0);
+        bytes[DIVERSIFIER_SIZE..].copy_from_slice(&vk_to_bytes(&self.pk_d));
+        bytes
+    }
+
+    /// rejects invalid diversifiers and pk_d outside of the prime order subgroup
+    pub fn from_bytes(bytes: &[u8]) -> Result<PaymentAddress, Error> {
+        if bytes.len() != PAYMENT_ADDRESS_SIZE {
+            return Err(Error::InvalidEncoding);
+        }
+        let mut diversifier = [0u8; DIVERSIFIER_SIZE];
+        diversifier.copy_from_slice(&bytes[..DIVERSIFIER_SIZE]);
+        let diversifier = Diversifier(diversifier);
+        diversifier.g_d().ok_or(Error::InvalidEncoding)?;
+        Ok(PaymentAddress {
+            diversifier,
+            pk_d: vk_from_bytes(&bytes[DIVERSIFIER_SIZE..])?,
+        })
+    }
+
+    /// zs1... on Network::Main
+    pub fn encode(&self, network: Network) -> String {
+        bech32_encode(network.payment_address_hrp(), &self.to_bytes())
+    }
+
+    pub fn decode(network: Network, encoded: &str) -> Result<PaymentAddress, Error> {
+        PaymentAddress::from_bytes(&bech32_decode(network.payment_address_hrp(), encoded)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::elliptic::curves::curve_jubjub::GE;
+    use curv::elliptic::curves::traits::{ECPoint, ECScalar};
+    use protocols::two_party::test::tests::{
+        bytes_32, scalar_from_hex, two_party_keygen, zip32_vector, ZIP32_VECTORS,
+    };
+    use protocols::two_party::viewing_key::{
+        Diversifier, Network, PaymentAddress, ProofGenerationKey, DIVERSIFIER_SIZE,
+    };
+    use protocols::two_party::zip32::{derive_nsk, ExtendedPublicKey};
+    use protocols::two_party::{from_hex, vk_to_bytes};
+    use Error;
+
+    #[test]
+    fn test_sapling_addresses() {
+        // sk = 0, .., 0 of the sapling_key_components vectors of zcash-test-vectors: ak, nk, ivk
+        // and the default address
+        let base: GE = ECPoint::generator();
+        let ask =
+            scalar_from_hex("8548a14a473ea547aa2378402044f818cf1911cf5dd2054f678345f00d0e8806");
+        let nsk =
+            scalar_from_hex("30114ea0dd0bb61cf0eaeab6ec3331f581b0425e27338501262d7eac745e6e05");
+        let ovk = bytes_32("98d16913d99b04177caba44f6e4d224e03b5ac031d7ce45e865138e1b996d63b");
+        let fvk = ProofGenerationKey::new(&(base * &ask), &nsk).full_viewing_key(&ovk);
+        assert_eq!(
+            vk_to_bytes(&fvk.ak).to_vec(),
+            from_hex("f344ec380fe1273e3098c2588c5d3a791fd7ba958032760777fd0efa8ef11620").unwrap()
+        );
+        assert_eq!(
+            vk_to_bytes(&fvk.nk).to_vec(),
+            from_hex("f7cf9e77f2e58683383c1519ac7b062d30040e27a725fb88fb19a978bd3fd6ba").unwrap()
+        );
+        assert_eq!(
+            fvk.ivk(),
+            scalar_from_hex("b70b7cd0ed03cbdfd7ada9502ee245b13e569d54a5719d2daa0f5f1451479204")
+        );
+        let mut diversifier = [0u8; DIVERSIFIER_SIZE];
+        diversifier.copy_from_slice(&from_hex("f19d9b797e39f337445839").unwrap());
+        let address = fvk.address(&Diversifier(diversifier)).unwrap();
+        assert_eq!(
+            vk_to_bytes(&address.pk_d).to_vec(),
+            from_hex("db4cd2b0aac4f7eb8ca131f16567c445a9555126d3c29f14e3d776e841ae7415").unwrap()
+        );
+        assert_eq!(
+            PaymentAddress::from_bytes(&address.to_bytes()),
+            Ok(address.clone())
+        );
+
+        // ivk and the diversifiers d0, d1 and d2 of ZIP32_VECTORS, d2 of m and d1 of m/1 are not
+        // valid
+        let master = zip32_vector(0);
+        let master_nsk = scalar_from_hex(ZIP32_VECTORS[0][1]);
+        let fvk = ProofGenerationKey::new(&master.ak, &master_nsk).full_viewing_key(&master.ovk);
+        assert_eq!(fvk, master.full_viewing_key());
+        assert_eq!(
+            fvk.ivk(),
+            scalar_from_hex("4847a130e799d3dbea36a1c16467d621fb2d80e30b3b1d1a426893415dad6601")
+        );
+        let diversifiers: [[&str; 3]; 3] = [
+            ["d8621b981cf300e9d4cc89", "48ea17a199c84bd1baa5d4", ""],
+            ["8b4138320dfafd7b399781", "", "5749a13352bc223e308078"],
+            ["e8d03793cdd2bacc9c7041", "020a7a6b0bf84d3e899f68", ""],
+        ];
+        for (i, key_diversifiers) in diversifiers.iter().enumerate() {
+            let key = zip32_vector(i);
+            for (j, diversifier) in key_diversifiers.iter().enumerate() {
+                match key.address(j as u64) {
+                    Some(address) => assert_eq!(
+                        address.diversifier.0.to_vec(),
+                        from_hex(diversifier).unwrap()
+                    ),
+                    None => assert!(diversifier.is_empty()),
+                }
+            }
+        }
+        assert_eq!(zip32_vector(1).find_address(1).unwrap().0, 2);
+
+        // the payment address encodings of the zcash_client_backend tests of librustzcash,
+        // diversifier 0 and the pk_d below
+        let encoded =
+            "zs1qqqqqqqqqqqqqqqqqqcguyvaw2vjk4sdyeg0lc970u659lvhqq7t0np6hlup5lusxle75c8v35z";
+        let address = PaymentAddress::decode(Network::Main, encoded).unwrap();
+        assert_eq!(address.diversifier, Diversifier([0u8; DIVERSIFIER_SIZE]));
+        assert_eq!(
+            vk_to_bytes(&address.pk_d).to_vec(),
+            from_hex("308e119d72992b560d2650ffe0be7f3542fd97003cb7cc3abff81a7f9037f3ea").unwrap()
+        );
+        assert_eq!(address.encode(Network::Main), encoded);
+        assert_eq!(
+            address.encode(Network::Test),
+            "ztestsapling1qqqqqqqqqqqqqqqqqqcguyvaw2vjk4sdyeg0lc970u659lvhqq7t0np6hlup5lusxle75ss7jnk"
+        );
+        assert_eq!(
+            PaymentAddress::decode(Network::Test, encoded),
+            Err(Error::InvalidEncoding)
+        );
+        // a changed character breaks the checksum
+        let tampered = format!("{}q", &encoded[..encoded.len() - 1]);
+        assert_eq!(
+            PaymentAddress::decode(Network::Main, &tampered),
+            Err(Error::InvalidEncoding)
+        );
+
+        // zxviews is the bech32 encoding of to_bytes, the layout of the xfvk of ZIP 32
+        let encoded = master.encode(Network::Main);
+        assert!(encoded.starts_with("zxviews1"));
+        assert!(master
+            .encode(Network::Test)
+            .starts_with("zxviewtestsapling1"));
+        assert_eq!(
+            ExtendedPublicKey::decode(Network::Main, &encoded),
+            Ok(master.clone())
+        );
+        let tampered = format!("{}q", &encoded[..encoded.len() - 1]);
+        assert_eq!(
+            ExtendedPublicKey::decode(Network::Main, &tampered),
+            Err(Error::InvalidEncoding)
+        );
+
+        // the prover of a two-party key gets the viewing key of every child
+        let (_, _, ak) = two_party_keygen();
+        let (key, nsk) = ExtendedPublicKey::create(&ak, &ECScalar::new_random());
+        let (child, _) = key.derive(&[5, 6]).unwrap();
+        let child_nsk = derive_nsk(&nsk, &key, &[5, 6]).unwrap();
+        assert_eq!(
+            ProofGenerationKey::new(&child.ak, &child_nsk).full_viewing_key(&child.ovk),
+            child.full_viewing_key()
+        );
+    }
+}
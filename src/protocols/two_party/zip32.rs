@@ -0,0 +1,444 @@
+#![allow(non_snake_case)]
+/*
+    paradise-city
+
+    Copyright 2019 by Kzen Networks
+
+    This file is part of paradise-city library
+    (https://github.com/KZen-networks/paradise-city)
+
+    paradise-city is free software: you can redistribute
+    it and/or modify it under the terms of the GNU General Public
+    License as published by the Free Software Foundation, either
+    version 3 of the License, or (at your option) any later version.
+
+    @license GPL-3.0+ <https://github.com/KZen-networks/paradise-city/blob/master/LICENSE>
+*/
+
+//! Non-hardened ZIP 32 derivation of two-party keys.
+//! A child key of ZIP 32 (CKDfvk) only needs the extended full viewing key of its parent: the
+//! child ak is ak + I_ak*G and the child nk is nk + I_nsk*H, for tweaks I_ak and I_nsk hashed
+//! from the chain code, the parent key and the index. A two-party key gets an ExtendedPublicKey
+//! once at keygen, and from then on both parties derive the child key of any non-hardened path
+//! on their own and agree on it without a message: the public tweak is added to the share of
+//! keygen party one (like alpha when signing), the share of party two stays as it is.
+//!
+//! After keygen both parties run a coin flip, and derive nsk, ovk, dk and the chain code from its
+//! result and ak the way ZIP 32 derives a master key from a seed, so neither party picks them.
+//! Both parties learn nsk, neither nsk nor any of the others gives a say over spend authorization.
+//! Hardened indices need the spending key and are refused with Error::InvalidKey.
+//! The full viewing key, payment addresses and the zxviews encoding of a key are built with the
+//! viewing_key module.
+//!
+//! Note: like after a refresh, the child share of party one is not below q/3.
+
+use super::viewing_key::{
+    bech32_decode, bech32_encode, Diversifier, FullViewingKey, Network, PaymentAddress,
+};
+use super::{fe_from_digest, fe_to_bytes, vk_from_bytes, vk_to_bytes, EcKeyPair, KeyGenRole};
+use blake2_rfc::blake2b::Blake2b;
+use curv::elliptic::curves::curve_jubjub::{FE, GE};
+use curv::elliptic::curves::traits::{ECPoint, ECScalar};
+use Error;
+
+/// indices from HARDENED on are hardened
+pub const HARDENED: u32 = 1 << 31;
+
+pub const EXTENDED_KEY_SIZE: usize = 169;
+
+const EXPAND_PERSONA: &[u8] = b"Zcash_ExpandSeed";
+const MASTER_PERSONA: &[u8] = b"ZcashIP32Sapling";
+
+// domain separators of the master key
+const MASTER_ASK: u8 = 0x00;
+const MASTER_NSK: u8 = 0x01;
+const MASTER_OVK: u8 = 0x02;
+const MASTER_DK: u8 = 0x10;
+
+// domain separators of CKDfvk
+const CHILD_KEY: u8 = 0x12;
+const CHILD_AK: u8 = 0x13;
+const CHILD_NSK: u8 = 0x14;
+const CHILD_OVK: u8 = 0x15;
+const CHILD_DK: u8 = 0x16;
+
+// PRF^expand(sk, t) of the Sapling spec, t is passed in parts
+fn prf_expand(sk: &[u8], t: &[&[u8]]) -> [u8; 64] {
+    let mut digest = Blake2b::with_params(64, &[], &[], EXPAND_PERSONA);
+    digest.update(sk);
+    for part in t {
+        digest.update(part);
+    }
+    let mut out = [0u8; 64];
+    out.copy_from_slice(digest.finalize().as_bytes());
+    out
+}
+
+fn truncate_32(bytes: &[u8]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&bytes[..32]);
+    out
+}
+
+/// extended spending key of ZIP 32 at depth 0
+pub struct MasterKey {
+    pub ask: FE,
+    pub nsk: FE,
+    pub ovk: [u8; 32],
+    pub dk: [u8; 32],
+    pub chain_code: [u8; 32],
+}
+
+/// the master key of ZIP 32 for seed
+pub fn master_from_seed(seed: &[u8]) -> MasterKey {
+    let mut digest = Blake2b::with_params(64, &[], &[], MASTER_PERSONA);
+    digest.update(seed);
+    let I = digest.finalize();
+    let (sk, chain_code) = I.as_bytes().split_at(32);
+    MasterKey {
+        ask: fe_from_digest(&prf_expand(sk, &[&[MASTER_ASK]])),
+        nsk: fe_from_digest(&prf_expand(sk, &[&[MASTER_NSK]])),
+        ovk: truncate_32(&prf_expand(sk, &[&[MASTER_OVK]])),
+        dk: truncate_32(&prf_expand(sk, &[&[MASTER_DK]])),
+        chain_code: truncate_32(chain_code),
+    }
+}
+
+/// extended full viewing key of ZIP 32
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExtendedPublicKey {
+    pub depth: u8,
+    /// first 4 bytes of the fingerprint of the parent, zeros for the key of keygen
+    pub parent_fvk_tag: [u8; 4],
+    pub child_index: u32,
+    pub ak: GE,
+    pub nk: GE,
+    pub ovk: [u8; 32],
+    pub dk: [u8; 32],
+    pub chain_code: [u8; 32],
+}
+
+/// what a child key adds to the ask and nsk of its ancestor
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tweak {
+    pub ak: FE,
+    pub nsk: FE,
+}
+
+impl Tweak {
+    fn zero() -> Tweak {
+        Tweak {
+            ak: ECScalar::zero(),
+            nsk: ECScalar::zero(),
+        }
+    }
+
+    fn plus(&self, other: &Tweak) -> Tweak {
+        Tweak {
+            ak: self.ak + &other.ak,
+            nsk: self.nsk + &other.nsk,
+        }
+    }
+}
+
+impl ExtendedPublicKey {
+    /// the key of ak and its nsk, for both parties. coin_flip is the result of the coin flip after
+    /// keygen, coin_flip || ak is the seed of the ZIP 32 master key, whose ask is not used
+    pub fn create(ak: &GE, coin_flip: &FE) -> (ExtendedPublicKey, FE) {
+        let mut seed = fe_to_bytes(coin_flip).to_vec();
+        seed.extend_from_slice(&vk_to_bytes(ak));
+        let master = master_from_seed(&seed);
+        let H = GE::proof_generation_generator();
+        let key = ExtendedPublicKey {
+            depth: 0,
+            parent_fvk_tag: [0u8; 4],
+            child_index: 0,
+            ak: *ak,
+            nk: H * &master.nsk,
+            ovk: master.ovk,
+            dk: master.dk,
+            chain_code: master.chain_code,
+        };
+        (key, master.nsk)
+    }
+
+    /// checks the key is for ak, e.g. one that was passed on by the other party
+    pub fn verify(&self, ak: &GE) -> Result<(), Error> {
+        match self.ak == *ak {
+            true => Ok(()),
+            false => Err(Error::InvalidKey),
+        }
+    }
+
+    /// CKDfvk for a non-hardened index, returns the child key and its tweak
+    pub fn child(&self, index: u32) -> Result<(ExtendedPublicKey, Tweak), Error> {
+        if index >= HARDENED || self.depth == u8::max_value() {
+            return Err(Error::InvalidKey);
+        }
+        let I = prf_expand(
+            &self.chain_code,
+            &[
+                &[CHILD_KEY],
+                &vk_to_bytes(&self.ak),
+                &vk_to_bytes(&self.nk),
+                &self.ovk,
+                &self.dk,
+                &index.to_le_bytes(),
+            ],
+        );
+        let (I_L, I_R) = I.split_at(32);
+        let tweak = Tweak {
+            ak: fe_from_digest(&prf_expand(I_L, &[&[CHILD_AK]])),
+            nsk: fe_from_digest(&prf_expand(I_L, &[&[CHILD_NSK]])),
+        };
+        let G = GE::generator();
+        let H = GE::proof_generation_generator();
+        let mut parent_fvk_tag = [0u8; 4];
+        parent_fvk_tag.copy_from_slice(&self.full_viewing_key().fingerprint()[..4]);
+        let child = ExtendedPublicKey {
+            depth: self.depth + 1,
+            parent_fvk_tag,
+            child_index: index,
+            ak: G * &tweak.ak + &self.ak,
+            nk: H * &tweak.nsk + &self.nk,
+            ovk: truncate_32(&prf_expand(I_L, &[&[CHILD_OVK], &self.ovk])),
+            dk: truncate_32(&prf_expand(I_L, &[&[CHILD_DK], &self.dk])),
+            chain_code: truncate_32(I_R),
+        };
+        Ok((child, tweak))
+    }
+
+    /// the key at path below this one, and the sum of the tweaks along it
+    pub fn derive(&self, path: &[u32]) -> Result<(ExtendedPublicKey, Tweak), Error> {
+        let mut key = self.clone();
+        let mut tweak = Tweak::zero();
+        for index in path {
+            let (child, child_tweak) = key.child(*index)?;
+            key = child;
+            tweak = tweak.plus(&child_tweak);
+        }
+        Ok((key, tweak))
+    }
+
+    pub fn full_viewing_key(&self) -> FullViewingKey {
+        FullViewingKey {
+            ak: self.ak,
+            nk: self.nk,
+            ovk: self.ovk,
+        }
+    }
+
+    /// payment address of diversifier index j, None if d_j is not a valid diversifier
+    pub fn address(&self, index: u64) -> Option<PaymentAddress> {
+        self.full_viewing_key()
+            .address(&Diversifier::from_index(&self.dk, index))
+    }
+
+    /// first valid address from index on, the default address of ZIP 32 is find_address(0)
+    pub fn find_address(&self, index: u64) -> Option<(u64, PaymentAddress)> {
+        (index..u64::max_value())
+            .filter_map(|j| self.address(j).map(|address| (j, address)))
+            .next()
+    }
+
+    /// depth || parent_fvk_tag || child_index || chain_code || ak || nk || ovk || dk
+    pub fn to_bytes(&self) -> [u8; EXTENDED_KEY_SIZE] {
+        let mut bytes = [0u8; EXTENDED_KEY_SIZE];
+        bytes[0] = self.depth;
+        bytes[1..5].copy_from_slice(&self.parent_fvk_tag);
+        bytes[5..9].copy_from_slice(&self.child_index.to_le_bytes());
+        bytes[9..41].copy_from_slice(&self.chain_code);
+        bytes[41..137].copy_from_slice(&self.full_viewing_key().to_bytes());
+        bytes[137..].copy_from_slice(&self.dk);
+        bytes
+    }
+
+    /// rejects ak and nk outside of the prime order subgroup
+    pub fn from_bytes(bytes: &[u8]) -> Result<ExtendedPublicKey, Error> {
+        if bytes.len() != EXTENDED_KEY_SIZE {
+            return Err(Error::InvalidEncoding);
+        }
+        let mut child_index = [0u8; 4];
+        child_index.copy_from_slice(&bytes[5..9]);
+        let mut parent_fvk_tag = [0u8; 4];
+        parent_fvk_tag.copy_from_slice(&bytes[1..5]);
+        Ok(ExtendedPublicKey {
+            depth: bytes[0],
+            parent_fvk_tag,
+            child_index: u32::from_le_bytes(child_index),
+            ak: vk_from_bytes(&bytes[41..73])?,
+            nk: vk_from_bytes(&bytes[73..105])?,
+            ovk: truncate_32(&bytes[105..137]),
+            dk: truncate_32(&bytes[137..]),
+            chain_code: truncate_32(&bytes[9..41]),
+        })
+    }
+
+    /// zxviews1... on Network::Main
+    pub fn encode(&self, network: Network) -> String {
+        bech32_encode(network.extended_key_hrp(), &self.to_bytes())
+    }
+
+    pub fn decode(network: Network, encoded: &str) -> Result<ExtendedPublicKey, Error> {
+        ExtendedPublicKey::from_bytes(&bech32_decode(network.extended_key_hrp(), encoded)?)
+    }
+}
+
+/// Share of the child key at path, and the child ak to open signing sessions with. keys is the
+/// share keygen gave the party in role, key the ExtendedPublicKey of keygen.
+pub fn derive_share(
+    keys: &EcKeyPair,
+    role: KeyGenRole,
+    key: &ExtendedPublicKey,
+    path: &[u32],
+) -> Result<(EcKeyPair, GE), Error> {
+    let (child, tweak) = key.derive(path)?;
+    let child_keys = match role {
+        KeyGenRole::PartyOne => {
+            let G = GE::generator();
+            EcKeyPair {
+                ak: G * &tweak.ak + &keys.ak,
+                ask: keys.ask + &tweak.ak,
+            }
+        }
+        KeyGenRole::PartyTwo => keys.clone(),
+    };
+    Ok((child_keys, child.ak))
+}
+
+/// nsk of the child key at path, for either party
+pub fn derive_nsk(nsk: &FE, key: &ExtendedPublicKey, path: &[u32]) -> Result<FE, Error> {
+    let (_, tweak) = key.derive(path)?;
+    Ok(*nsk + &tweak.nsk)
+}
+
+#[cfg(test)]
+mod tests {
+    use curv::elliptic::curves::curve_jubjub::GE;
+    use curv::elliptic::curves::traits::{ECPoint, ECScalar};
+    use protocols::two_party::compute_ak;
+    use protocols::two_party::driver;
+    use protocols::two_party::import::split_ask;
+    use protocols::two_party::test::tests::{
+        scalar_from_hex, two_party_keygen, two_party_sign_sighash, zip32_vector, ZIP32_PATHS,
+        ZIP32_VECTORS,
+    };
+    use protocols::two_party::transport::ChannelTransport;
+    use protocols::two_party::zip32::{
+        derive_nsk, derive_share, master_from_seed, ExtendedPublicKey, HARDENED,
+    };
+    use protocols::two_party::{verify, EcKeyPair, KeyGenRole, NonceMode, SessionId};
+    use std::thread;
+    use Error;
+
+    #[test]
+    fn test_zip32_vectors() {
+        let keys: Vec<ExtendedPublicKey> = (0..ZIP32_VECTORS.len()).map(zip32_vector).collect();
+        let base: GE = ECPoint::generator();
+        let proof_base = GE::proof_generation_generator();
+        for (vector, key) in ZIP32_VECTORS.iter().zip(&keys).take(3) {
+            assert_eq!(base * &scalar_from_hex(vector[0]), key.ak);
+            assert_eq!(proof_base * &scalar_from_hex(vector[1]), key.nk);
+        }
+
+        // m is the master key of the seed 0, 1, .., 31
+        let seed: Vec<u8> = (0..32).collect();
+        let master = master_from_seed(&seed);
+        assert_eq!(master.ask, scalar_from_hex(ZIP32_VECTORS[0][0]));
+        assert_eq!(master.nsk, scalar_from_hex(ZIP32_VECTORS[0][1]));
+        assert_eq!(master.ovk, keys[0].ovk);
+        assert_eq!(master.dk, keys[0].dk);
+        assert_eq!(master.chain_code, keys[0].chain_code);
+
+        // m/1 and m/1/2'/3 are non-hardened children, m/1/2' needs the spending key
+        assert_eq!(keys[0].child(1).unwrap().0, keys[1]);
+        assert_eq!(keys[2].child(3).unwrap().0, keys[3]);
+        assert_eq!(keys[2].derive(&[3]).unwrap().0, keys[3]);
+        assert_eq!(keys[1].child(2 | HARDENED).err(), Some(Error::InvalidKey));
+
+        // ask of the parent split into two shares, as import does
+        for &(parent, child) in &[(0, 1), (2, 3)] {
+            let path = [*ZIP32_PATHS[child].last().unwrap()];
+            let (party1_share, import_msg) = split_ask(&scalar_from_hex(ZIP32_VECTORS[parent][0]));
+            let party1_keys = EcKeyPair {
+                ak: base * &party1_share,
+                ask: party1_share,
+            };
+            let party2_keys = EcKeyPair {
+                ak: base * &import_msg.secret_share,
+                ask: import_msg.secret_share,
+            };
+            let (party1_child, party1_ak) =
+                derive_share(&party1_keys, KeyGenRole::PartyOne, &keys[parent], &path).unwrap();
+            let (party2_child, party2_ak) =
+                derive_share(&party2_keys, KeyGenRole::PartyTwo, &keys[parent], &path).unwrap();
+            let nsk = derive_nsk(
+                &scalar_from_hex(ZIP32_VECTORS[parent][1]),
+                &keys[parent],
+                &path,
+            )
+            .unwrap();
+            assert_eq!(party1_ak, keys[child].ak);
+            assert_eq!(party2_ak, keys[child].ak);
+            assert_eq!(compute_ak(&party1_child, &party2_child.ak), keys[child].ak);
+            assert_eq!(
+                base * &(party1_child.ask + &party2_child.ask),
+                keys[child].ak
+            );
+            assert_eq!(proof_base * &nsk, keys[child].nk);
+            if !ZIP32_VECTORS[child][0].is_empty() {
+                assert_eq!(
+                    party1_child.ask + &party2_child.ask,
+                    scalar_from_hex(ZIP32_VECTORS[child][0])
+                );
+                assert_eq!(nsk, scalar_from_hex(ZIP32_VECTORS[child][1]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_zip32_derive_share() {
+        let session_id = SessionId::new_random();
+        let (mut party1_transport, mut party2_transport) = ChannelTransport::pair();
+        let party1_session_id = session_id.clone();
+        let party1 = thread::spawn(move || {
+            driver::party_one_extended_keygen(&mut party1_transport, &party1_session_id)
+        });
+        let (party2_keys, party2_key, party2_nsk) =
+            driver::party_two_extended_keygen(&mut party2_transport, &session_id)
+                .expect("keygen failed");
+        let (party1_keys, party1_key, nsk) = party1.join().unwrap().expect("keygen failed");
+        // both parties end up with the same key and nsk
+        assert_eq!(party1_key, party2_key);
+        assert_eq!(nsk, party2_nsk);
+        assert_eq!(GE::proof_generation_generator() * &nsk, party1_key.nk);
+        // another coin flip gives another key for the same ak
+        let (other_key, _) = ExtendedPublicKey::create(&party1_key.ak, &ECScalar::new_random());
+        assert_ne!(other_key.chain_code, party1_key.chain_code);
+
+        // both parties derive the child on their own
+        let path = [0, 7];
+        let (party1_child, ak) =
+            derive_share(&party1_keys, KeyGenRole::PartyOne, &party1_key, &path).unwrap();
+        let (party2_child, party2_ak) =
+            derive_share(&party2_keys, KeyGenRole::PartyTwo, &party2_key, &path).unwrap();
+        assert_eq!(ak, party2_ak);
+        assert_ne!(ak, party1_key.ak);
+        let (message, sig) = two_party_sign_sighash(
+            party1_child,
+            party2_child,
+            ak,
+            &[3u8; 32],
+            NonceMode::Hedged,
+        )
+        .expect("signing failed");
+        assert!(verify(message.vk(), &message, &sig).is_ok());
+
+        assert_eq!(
+            party1_key.derive(&[0, HARDENED]).err(),
+            Some(Error::InvalidKey)
+        );
+        let (_, _, other_ak) = two_party_keygen();
+        assert_eq!(party2_key.verify(&other_ak), Err(Error::InvalidKey));
+    }
+}